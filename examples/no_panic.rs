@@ -0,0 +1,70 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Link-time check that the panic-free subset of `CBuf` can't panic
+//!
+//! `RELEASE=true make no_panic`: every call below runs under a guard
+//! whose `Drop`, only reachable while unwinding, refers to a symbol that
+//! doesn't exist. If the optimizer can't prove a call panic-free, linking
+//! fails on `cbuf_call_may_panic`. Debug builds skip the check.
+
+extern crate cbuf;
+
+use std::hint::black_box;
+
+use cbuf::CBuf;
+
+struct Guard;
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        #[cfg(not(debug_assertions))]
+        {
+            extern "C" {
+                fn cbuf_call_may_panic() -> !;
+            }
+            unsafe { cbuf_call_may_panic() }
+        }
+    }
+}
+
+fn no_panic<R, F: FnOnce() -> R>(f: F) -> R {
+    let guard = Guard;
+    let ret = f();
+    core::mem::forget(guard);
+    ret
+}
+
+// `CBuf` state is opaque to the optimizer here, so nothing is proven by
+// constant folding
+#[inline(never)]
+fn exercise(cbuf: &mut CBuf<u32>, val: u32, n: usize, dst: &mut [u32]) -> u32 {
+    let mut sum = 0;
+    no_panic(|| {
+        let _ = cbuf.try_put(val);
+        sum += cbuf.put_slice(dst) as u32;
+        sum += cbuf.count() as u32 + cbuf.remaining_capacity() as u32;
+        sum += *cbuf.front().unwrap_or(&0) + *cbuf.back().unwrap_or(&0);
+        sum += *cbuf.get_at(n).unwrap_or(&0) + *cbuf.peek().unwrap_or(&0);
+        sum += cbuf.get().unwrap_or(0);
+        sum += cbuf.pop_into(dst) as u32;
+        sum += cbuf.discard(n) as u32;
+        let (a, b) = cbuf.as_slices();
+        sum += (a.len() + b.len()) as u32;
+        sum += cbuf.is_full() as u32 + cbuf.is_empty() as u32;
+        cbuf.clear();
+    });
+    sum
+}
+
+fn main() {
+    let buf = &mut [0u32; 8];
+    let mut cbuf = match no_panic(|| CBuf::try_new(buf)) {
+        Some(cbuf) => cbuf,
+        None => return,
+    };
+    let dst = &mut [1u32; 5];
+    println!("{}", exercise(black_box(&mut cbuf), black_box(1), black_box(3), black_box(dst)));
+}
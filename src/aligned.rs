@@ -0,0 +1,74 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Storage wrappers meeting DMA and cache-line alignment requirements
+//!
+//! ```
+//! use cbuf::{Align32, CBuf};
+//!
+//! static mut STORAGE: Align32<[u8; 256]> = Align32::new([0; 256]);
+//!
+//! let storage = unsafe { &mut *core::ptr::addr_of_mut!(STORAGE) };
+//! let mut cbuf = CBuf::new(&mut storage[..]);
+//! assert_eq!(cbuf.as_non_null().as_ptr() as usize % 32, 0);
+//! ```
+
+use core::ops::{Deref, DerefMut};
+
+macro_rules! aligned {
+    ($($name:ident $align:tt),*) => {$(
+        /// Value aligned to
+        #[doc = stringify!($align)]
+        /// bytes; derefs to the wrapped value
+        #[derive(Debug, Clone, Copy, Default)]
+        #[repr(C, align($align))]
+        pub struct $name<T>(pub T);
+
+        impl<T> $name<T> {
+            /// Wrap `value`
+            #[inline]
+            pub const fn new(value: T) -> $name<T> {
+                $name(value)
+            }
+        }
+
+        impl<T> Deref for $name<T> {
+            type Target = T;
+
+            #[inline]
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $name<T> {
+            #[inline]
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.0
+            }
+        }
+    )*}
+}
+
+aligned!(Align8 8, Align16 16, Align32 32, Align64 64, Align128 128);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aligned() {
+        let mut storage = [0u8; 3];
+        let mut a64 = Align64::new([0u8; 3]);
+        let mut a128 = Align128::new([0u16; 5]);
+
+        assert_eq!(core::mem::align_of_val(&storage), 1);
+        assert_eq!(a64.as_mut_ptr() as usize % 64, 0);
+        assert_eq!(a128.as_mut_ptr() as usize % 128, 0);
+        a64[1] = 7;
+        storage.copy_from_slice(&a64[..]);
+        assert_eq!(storage, [0, 7, 0]);
+    }
+}
@@ -0,0 +1,511 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer owning its storage array
+
+use core::fmt;
+
+use super::CBUF_DATA_BIT;
+use ring::{CBufControl, Iter, NoSpace};
+use rom::ConstCBuf;
+use traits::RingProducerExt;
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer owning an array of `N` elements
+///
+/// Unlike `CBuf` it needs no separately declared storage, so it can sit in
+/// a `static`, a driver struct deriving `Default`, or be moved around:
+///
+/// ```
+/// use cbuf::CBufArray;
+///
+/// static QUEUE: CBufArray<u8, 16> = CBufArray::new([0; 16]);
+///
+/// let mut queue: CBufArray<u8, 4> = Default::default();
+/// queue.put(1);
+/// assert_eq!(queue.get(), Some(1));
+/// ```
+pub struct CBufArray<T, const N: usize> {
+    buf: [T; N],
+    ctrl: CBufControl<T>,
+}
+
+impl<T: Clone, const N: usize> CBufArray<T, N> {
+    /// Create new CBufArray using `buf` as storage
+    ///
+    /// panics if N == 0, or if N doesn't fit below the index tag bit
+    /// (only possible for zero-sized `T`)
+    pub const fn new(buf: [T; N]) -> CBufArray<T, N> {
+        if N == 0 {
+            panic!("len==0")
+        }
+        if N >= CBUF_DATA_BIT {
+            panic!("len too big")
+        }
+
+        CBufArray {
+            buf,
+            ctrl: CBufControl::new(),
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.ctrl.count(N)
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ctrl.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ctrl.is_empty()
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&mut self) -> Option<&T> {
+        self.ctrl.peek(&self.buf)
+    }
+
+    /// `n`-th oldest element, without removing it
+    ///
+    /// Returns `None` if there are no more than `n` elements. Indexing
+    /// with `buf[n]` does the same, but panics instead.
+    #[inline]
+    pub fn get_at(&self, n: usize) -> Option<&T> {
+        self.ctrl.get_at(&self.buf, n)
+    }
+
+    /// Mutable `n`-th oldest element, see `get_at`
+    #[inline]
+    pub fn get_at_mut(&mut self, n: usize) -> Option<&mut T> {
+        self.ctrl.get_at_mut(&mut self.buf, n)
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        self.ctrl.get(&self.buf)
+    }
+
+    /// Cancel the most recent `put`, removing and returning its element
+    ///
+    /// Write-side inverse of `get`, eg. to withdraw the last queued
+    /// command when an abort arrives. Returns `None` if buffer is empty.
+    #[inline]
+    pub fn revert_put(&mut self) -> Option<T> {
+        self.ctrl.revert_put(&self.buf)
+    }
+
+    /// Read-only view of current contents, see `CBuf::view`
+    #[inline]
+    pub fn view(&self) -> ConstCBuf<'_, T> {
+        ConstCBuf::new(&self.buf, self.ctrl.tail_index() & !CBUF_DATA_BIT, self.count())
+    }
+
+    /// Iterate over elements, oldest first, without removing them
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.ctrl.iter(&self.buf)
+    }
+
+    /// Iterate over the newest `n` elements (or all, if fewer), oldest
+    /// first, without removing them
+    #[inline]
+    pub fn last_n(&self, n: usize) -> Iter<'_, T> {
+        self.ctrl.last_n(&self.buf, n)
+    }
+
+    /// Remove elements while iterating over them, oldest first
+    ///
+    /// Elements not consumed when the iterator is dropped are removed too.
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'_, T, N> {
+        Drain { buf: self }
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        self.ctrl.put(&mut self.buf, val)
+    }
+
+    /// Remove all elements
+    ///
+    /// Slots are left as they are.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.ctrl = CBufControl::new();
+    }
+
+    /// Elements in the buffer as two slices, oldest first
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.ctrl.as_slices(&self.buf)
+    }
+}
+
+impl<T: Clone + Default, const N: usize> Default for CBufArray<T, N> {
+    fn default() -> CBufArray<T, N> {
+        CBufArray::new(core::array::from_fn(|_| T::default()))
+    }
+}
+
+impl<T: Clone + Default, const N: usize> CBufArray<T, N> {
+    /// Collect `iter` into a new buffer
+    ///
+    /// Returns `NoSpace` if `iter` has more than `N` elements; `collect()`
+    /// keeps the first `N` instead.
+    pub fn try_from_iter<I: IntoIterator<Item = T>>(iter: I) -> Result<CBufArray<T, N>, NoSpace> {
+        let mut iter = iter.into_iter();
+        let mut buf = CBufArray::default();
+        buf.put_iter(&mut iter);
+        match iter.next() {
+            Some(_) => Err(NoSpace),
+            None => Ok(buf),
+        }
+    }
+}
+
+/// Keeps the first `N` elements, see `try_from_iter`
+impl<T: Clone + Default, const N: usize> core::iter::FromIterator<T> for CBufArray<T, N> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> CBufArray<T, N> {
+        let mut buf = CBufArray::default();
+        buf.put_iter(iter);
+        buf
+    }
+}
+
+/// Shows the queued elements oldest first, like `CBuf`
+impl<T: Clone + fmt::Debug, const N: usize> fmt::Debug for CBufArray<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CBufArray")
+            .field("contents", &::DebugList(self.iter()))
+            .field("count", &self.count())
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+/// Iterator returned by `CBufArray::drain`
+pub struct Drain<'r, T: 'r + Clone, const N: usize> {
+    buf: &'r mut CBufArray<T, N>,
+}
+
+impl<'r, T: Clone, const N: usize> Iterator for Drain<'r, T, N> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.buf.get()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.buf.count(), Some(self.buf.count()))
+    }
+}
+
+impl<'r, T: Clone, const N: usize> DoubleEndedIterator for Drain<'r, T, N> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.buf.revert_put()
+    }
+}
+
+impl<'r, T: Clone, const N: usize> ExactSizeIterator for Drain<'r, T, N> {}
+
+impl<'r, T: Clone, const N: usize> core::iter::FusedIterator for Drain<'r, T, N> {}
+
+/// Removes elements that weren't consumed
+impl<'r, T: Clone, const N: usize> Drop for Drain<'r, T, N> {
+    fn drop(&mut self) {
+        self.buf.clear();
+    }
+}
+
+/// Iterator returned by `CBufArray::into_iter`
+pub struct IntoIter<T: Clone, const N: usize> {
+    buf: CBufArray<T, N>,
+}
+
+impl<T: Clone, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.buf.get()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.buf.count(), Some(self.buf.count()))
+    }
+}
+
+impl<T: Clone, const N: usize> DoubleEndedIterator for IntoIter<T, N> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.buf.revert_put()
+    }
+}
+
+impl<T: Clone, const N: usize> ExactSizeIterator for IntoIter<T, N> {}
+
+impl<T: Clone, const N: usize> core::iter::FusedIterator for IntoIter<T, N> {}
+
+impl<T: Clone, const N: usize> IntoIterator for CBufArray<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    #[inline]
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter { buf: self }
+    }
+}
+
+impl<'r, T: Clone, const N: usize> IntoIterator for &'r CBufArray<T, N> {
+    type Item = &'r T;
+    type IntoIter = Iter<'r, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'r, T> {
+        self.iter()
+    }
+}
+
+/// `n`-th oldest element, panics if there are no more than `n` elements
+impl<T: Clone, const N: usize> core::ops::Index<usize> for CBufArray<T, N> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, n: usize) -> &T {
+        match self.get_at(n) {
+            Some(val) => val,
+            None => panic!("index {} out of range for {} elements", n, self.count()),
+        }
+    }
+}
+
+impl<T: Clone, const N: usize> core::ops::IndexMut<usize> for CBufArray<T, N> {
+    #[inline]
+    fn index_mut(&mut self, n: usize) -> &mut T {
+        let count = self.count();
+        match self.get_at_mut(n) {
+            Some(val) => val,
+            None => panic!("index {} out of range for {} elements", n, count),
+        }
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: Clone + defmt::Format, const N: usize> defmt::Format for CBufArray<T, N> {
+    fn format(&self, f: defmt::Formatter) {
+        let (a, b) = self.as_slices();
+        ::defmt_ring(f, "CBufArray", a.iter().chain(b), self.count(), N)
+    }
+}
+
+/// Compares queued elements, not raw slots or indexes
+impl<T: Clone + PartialEq<U>, U: Clone, const N: usize, const M: usize> PartialEq<CBufArray<U, M>>
+    for CBufArray<T, N> {
+    fn eq(&self, other: &CBufArray<U, M>) -> bool {
+        let ((a, b), (c, d)) = (self.as_slices(), other.as_slices());
+        self.count() == other.count() && a.iter().chain(b).zip(c.iter().chain(d)).all(|(x, y)| x == y)
+    }
+}
+
+impl<T: Clone + Eq, const N: usize> Eq for CBufArray<T, N> {}
+
+impl<T: Clone + PartialEq<U>, U, const N: usize> PartialEq<[U]> for CBufArray<T, N> {
+    fn eq(&self, other: &[U]) -> bool {
+        let (a, b) = self.as_slices();
+        self.count() == other.len() && a.iter().chain(b).zip(other).all(|(x, y)| x == y)
+    }
+}
+
+impl<T: Clone + PartialEq<U>, U, const N: usize, const M: usize> PartialEq<[U; M]> for CBufArray<T, N> {
+    fn eq(&self, other: &[U; M]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: Clone, const N: usize> RingProducer<T> for CBufArray<T, N> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        CBufArray::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        CBufArray::put(self, val)
+    }
+}
+
+impl<T: Clone, const N: usize> RingConsumer<T> for CBufArray<T, N> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        CBufArray::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        CBufArray::get(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Clone + serde::Serialize, const N: usize> serde::Serialize for CBufArray<T, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        let (a, b) = self.as_slices();
+        let mut seq = serializer.serialize_seq(Some(self.count()))?;
+        for val in a.iter().chain(b) {
+            seq.serialize_element(val)?;
+        }
+        seq.end()
+    }
+}
+
+/// Fails with `invalid_length` on sequences longer than `N`
+#[cfg(feature = "serde")]
+impl<'de, T: Clone + Default + serde::Deserialize<'de>, const N: usize> serde::Deserialize<'de>
+    for CBufArray<T, N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<T, const N: usize>(core::marker::PhantomData<T>);
+
+        impl<'de, T: Clone + Default + serde::Deserialize<'de>, const N: usize> serde::de::Visitor<'de>
+            for Visitor<T, N> {
+            type Value = CBufArray<T, N>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a sequence of at most {} elements", N)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut buf = CBufArray::default();
+                while let Some(val) = seq.next_element()? {
+                    if buf.is_full() {
+                        return Err(serde::de::Error::invalid_length(N + 1, &self));
+                    }
+                    buf.put(val);
+                }
+                Ok(buf)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor(core::marker::PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[derive(Default)]
+    struct Driver {
+        rx: CBufArray<u8, 3>,
+    }
+
+    #[test]
+    fn array() {
+        let mut driver = Driver::default();
+        let rx = &mut driver.rx;
+
+        assert!(rx.is_empty());
+        rx.put(1);
+        rx.put(2);
+        rx.put(3);
+        rx.put(4); // will have no effect
+        assert!(rx.is_full());
+        assert_eq!(rx.get(), Some(1));
+        rx.put(5);
+        assert_eq!(rx.as_slices(), (&[2, 3][..], &[5][..]));
+        assert_eq!(*rx, [2, 3, 5]);
+        assert_eq!(std::format!("{:?}", rx), "CBufArray { contents: [2, 3, 5], count: 3, capacity: 3 }");
+        assert_eq!((rx[0], rx.get_at(2), rx.get_at(3)), (2, Some(&5), None));
+        rx[1] = 4;
+        assert_eq!(*rx, [2, 4, 5]);
+
+        rx.clear();
+        assert!(rx.is_empty());
+        assert_eq!(rx.get(), None);
+    }
+
+    #[test]
+    fn array_from_iter() {
+        let buf: CBufArray<u8, 3> = (1..10).collect();
+        assert_eq!(buf, [1, 2, 3]);
+        assert_eq!(CBufArray::<u8, 3>::try_from_iter(1..3).map(|buf| buf == [1, 2]), Ok(true));
+        assert_eq!(CBufArray::<u8, 3>::try_from_iter(1..4).map(|buf| buf.count()), Ok(3));
+        assert_eq!(CBufArray::<u8, 3>::try_from_iter(1..5), Err(NoSpace));
+    }
+
+    #[test]
+    fn array_iter() {
+        let mut buf = CBufArray::<u8, 4>::default();
+        buf.put(1);
+        buf.put(2);
+        buf.get();
+        buf.put(3);
+        buf.put(4);
+        buf.put(5);
+
+        assert_eq!(buf.iter().len(), 4);
+        assert!(buf.iter().rev().eq(&[5, 4, 3, 2]));
+        assert!(buf.last_n(3).eq(&[3, 4, 5]));
+        assert_eq!(buf.view().back(), Some(&5));
+        assert_eq!(buf.revert_put(), Some(5));
+
+        let mut drain = buf.drain();
+        assert_eq!((drain.len(), drain.next_back(), drain.next()), (3, Some(4), Some(2)));
+        drop(drain);
+        assert!(buf.is_empty());
+
+        buf.put(6);
+        buf.put(7);
+        let mut iter = buf.into_iter();
+        assert_eq!((iter.len(), iter.next_back(), iter.next(), iter.next()), (2, Some(7), Some(6), None));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn array_serde() {
+        use serde_test::{assert_de_tokens_error, assert_tokens, Token};
+
+        let mut rx = CBufArray::<u8, 3>::default();
+        rx.put(1);
+        rx.put(2);
+        rx.get();
+        rx.put(3);
+        rx.put(4);
+        assert_tokens(&rx, &[Token::Seq { len: Some(3) }, Token::U8(2), Token::U8(3), Token::U8(4), Token::SeqEnd]);
+
+        assert_de_tokens_error::<CBufArray<u8, 1>>(
+            &[Token::Seq { len: Some(2) }, Token::U8(1), Token::U8(2), Token::SeqEnd],
+            "invalid length 2, expected a sequence of at most 1 elements",
+        );
+    }
+}
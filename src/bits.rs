@@ -0,0 +1,413 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffers of bits and narrow samples packed into `u32` words
+
+use super::{cbuf_advance, cbuf_next, CBUF_DATA_BIT};
+use ring::CBufControl;
+use traits::{RingConsumer, RingProducer};
+
+const WORD_BITS: usize = 32;
+
+/// Circular Buffer storing one bit per element
+///
+/// Keeps long histories of digital signals in 1/8 of the RAM a `CBuf<bool>`
+/// would take. Bit `i` of the ring lives in bit `i % 32` of word `i / 32`.
+pub struct BitRing<'a> {
+    words: &'a mut [u32],
+    ctrl: CBufControl<bool>,
+}
+
+impl<'a> BitRing<'a> {
+    /// Create new BitRing holding `32 * words.len()` bits
+    ///
+    /// panics if words.len() == 0
+    pub fn new(words: &'a mut [u32]) -> BitRing<'a> {
+        if words.is_empty() {
+            panic!("len==0")
+        }
+        if words.len() >= CBUF_DATA_BIT / WORD_BITS {
+            panic!("len too big")
+        }
+
+        BitRing {
+            words,
+            ctrl: CBufControl::new(),
+        }
+    }
+
+    /// get the buffer length in bits
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.words.len() * WORD_BITS
+    }
+
+    /// Number of bits currently stored
+    #[inline]
+    pub fn count(&self) -> usize {
+        let (head, tail) = (self.ctrl.head_index(), self.ctrl.tail_index());
+        if (head ^ tail) & CBUF_DATA_BIT == 0 {
+            head - tail
+        } else {
+            self.len() - (tail & !CBUF_DATA_BIT) + (head & !CBUF_DATA_BIT)
+        }
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ctrl.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ctrl.is_empty()
+    }
+
+    /// Oldest bit, without removing it
+    #[inline]
+    pub fn peek(&self) -> Option<bool> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.bit(self.ctrl.tail_index() & !CBUF_DATA_BIT))
+    }
+
+    /// Remove one bit
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<bool> {
+        let val = self.peek();
+        if val.is_some() {
+            let tail = cbuf_next(self.ctrl.tail_index(), self.len());
+            unsafe { self.ctrl.set_tail_index(tail) };
+        }
+        val
+    }
+
+    /// Add bit the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: bool) {
+        if self.is_full() {
+            return;
+        }
+        let head = self.ctrl.head_index();
+        let slot = head & !CBUF_DATA_BIT;
+        let mask = 1 << (slot % WORD_BITS);
+        if val {
+            self.words[slot / WORD_BITS] |= mask;
+        } else {
+            self.words[slot / WORD_BITS] &= !mask;
+        }
+        unsafe { self.ctrl.set_head_index(cbuf_next(head, self.len())) };
+    }
+
+    /// Add 32 bits at once, least significant bit first
+    ///
+    /// Returns `false` and adds nothing if fewer than 32 bits are free.
+    pub fn put_word(&mut self, word: u32) -> bool {
+        if self.len() - self.count() < WORD_BITS {
+            return false;
+        }
+        let head = self.ctrl.head_index();
+        let slot = head & !CBUF_DATA_BIT;
+        if slot.is_multiple_of(WORD_BITS) {
+            self.words[slot / WORD_BITS] = word;
+            unsafe { self.ctrl.set_head_index(cbuf_advance(head, WORD_BITS, self.len())) };
+        } else {
+            for i in 0..WORD_BITS {
+                self.put(word & (1 << i) != 0);
+            }
+        }
+        true
+    }
+
+    /// Remove 32 bits at once, oldest in the least significant bit
+    ///
+    /// Returns `None` and removes nothing if fewer than 32 bits are stored.
+    pub fn get_word(&mut self) -> Option<u32> {
+        if self.count() < WORD_BITS {
+            return None;
+        }
+        let tail = self.ctrl.tail_index();
+        let slot = tail & !CBUF_DATA_BIT;
+        if slot.is_multiple_of(WORD_BITS) {
+            unsafe { self.ctrl.set_tail_index(cbuf_advance(tail, WORD_BITS, self.len())) };
+            return Some(self.words[slot / WORD_BITS]);
+        }
+        let mut word = 0;
+        for i in 0..WORD_BITS {
+            if self.get() == Some(true) {
+                word |= 1 << i;
+            }
+        }
+        Some(word)
+    }
+
+    #[inline]
+    fn bit(&self, slot: usize) -> bool {
+        self.words[slot / WORD_BITS] & (1 << (slot % WORD_BITS)) != 0
+    }
+}
+
+/// Circular Buffer of `BITS`-bit samples packed into `u32` words
+///
+/// Generalizes `BitRing` to eg. 4-bit or 12-bit ADC codes: samples are
+/// packed back to back, straddling word boundaries, and unpacked into
+/// `u16` on read. Sample `i` occupies bits `i * BITS..(i + 1) * BITS` of
+/// the backing, counting from the least significant bit of word 0.
+pub struct PackedRing<'a, const BITS: usize> {
+    words: &'a mut [u32],
+    ctrl: CBufControl<u16>,
+}
+
+impl<'a, const BITS: usize> PackedRing<'a, BITS> {
+    /// Create new PackedRing holding `32 * words.len() / BITS` samples
+    ///
+    /// panics if BITS is not in 1..=16, or if words can't hold a sample
+    pub fn new(words: &'a mut [u32]) -> PackedRing<'a, BITS> {
+        if BITS == 0 || BITS > 16 {
+            panic!("BITS not in 1..=16")
+        }
+        if words.len() * WORD_BITS < BITS {
+            panic!("len==0")
+        }
+        if words.len() >= CBUF_DATA_BIT / WORD_BITS {
+            panic!("len too big")
+        }
+
+        PackedRing {
+            words,
+            ctrl: CBufControl::new(),
+        }
+    }
+
+    /// get the buffer length in samples
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.words.len() * WORD_BITS / BITS
+    }
+
+    /// Number of samples currently stored
+    #[inline]
+    pub fn count(&self) -> usize {
+        let (head, tail) = (self.ctrl.head_index(), self.ctrl.tail_index());
+        if (head ^ tail) & CBUF_DATA_BIT == 0 {
+            head - tail
+        } else {
+            self.len() - (tail & !CBUF_DATA_BIT) + (head & !CBUF_DATA_BIT)
+        }
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ctrl.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ctrl.is_empty()
+    }
+
+    /// Oldest sample, without removing it
+    #[inline]
+    pub fn peek(&self) -> Option<u16> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.sample(self.ctrl.tail_index() & !CBUF_DATA_BIT))
+    }
+
+    /// Remove one sample
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<u16> {
+        let val = self.peek();
+        if val.is_some() {
+            let tail = cbuf_next(self.ctrl.tail_index(), self.len());
+            unsafe { self.ctrl.set_tail_index(tail) };
+        }
+        val
+    }
+
+    /// Add sample the buffer, keeping its low `BITS` bits
+    ///
+    /// Ignores the write if buffer is full.
+    pub fn put(&mut self, val: u16) {
+        if self.is_full() {
+            return;
+        }
+        let head = self.ctrl.head_index();
+        let pos = (head & !CBUF_DATA_BIT) * BITS;
+        let (word, shift) = (pos / WORD_BITS, pos % WORD_BITS);
+        let val = u64::from(val) & Self::MASK;
+        let mut pair = self.pair(word);
+        pair = pair & !(Self::MASK << shift) | val << shift;
+        self.words[word] = pair as u32;
+        if shift + BITS > WORD_BITS {
+            self.words[word + 1] = (pair >> WORD_BITS) as u32;
+        }
+        unsafe { self.ctrl.set_head_index(cbuf_next(head, self.len())) };
+    }
+
+    /// Remove samples into `dst` until buffer is empty or `dst` is full
+    ///
+    /// Returns number of samples removed.
+    pub fn unpack_into(&mut self, dst: &mut [u16]) -> usize {
+        let mut count = 0;
+        for d in dst.iter_mut() {
+            match self.get() {
+                Some(val) => *d = val,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    const MASK: u64 = (1 << BITS) - 1;
+
+    /// Word `word` and the one after it (if any) as one integer
+    #[inline]
+    fn pair(&self, word: usize) -> u64 {
+        let next = self.words.get(word + 1).map_or(0, |&w| u64::from(w));
+        u64::from(self.words[word]) | next << WORD_BITS
+    }
+
+    #[inline]
+    fn sample(&self, slot: usize) -> u16 {
+        let pos = slot * BITS;
+        ((self.pair(pos / WORD_BITS) >> (pos % WORD_BITS)) & Self::MASK) as u16
+    }
+}
+
+impl<'a, const BITS: usize> RingProducer<u16> for PackedRing<'a, BITS> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        PackedRing::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: u16) {
+        PackedRing::put(self, val)
+    }
+}
+
+impl<'a, const BITS: usize> RingConsumer<u16> for PackedRing<'a, BITS> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        PackedRing::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<u16> {
+        PackedRing::get(self)
+    }
+}
+
+impl<'a> RingProducer<bool> for BitRing<'a> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        BitRing::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: bool) {
+        BitRing::put(self, val)
+    }
+}
+
+impl<'a> RingConsumer<bool> for BitRing<'a> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        BitRing::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<bool> {
+        BitRing::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bit_ring() {
+        let words = &mut [0u32; 2];
+        let mut ring = BitRing::new(words);
+        assert_eq!(ring.len(), 64);
+
+        ring.put(true);
+        ring.put(false);
+        ring.put(true);
+        assert_eq!(ring.count(), 3);
+        assert_eq!(ring.peek(), Some(true));
+        assert_eq!(ring.get(), Some(true));
+        assert_eq!(ring.get(), Some(false));
+        assert_eq!(ring.get(), Some(true));
+        assert_eq!(ring.get(), None);
+
+        for _ in 0..64 {
+            ring.put(true);
+        }
+        assert!(ring.is_full());
+        ring.put(false); // will have no effect
+        assert_eq!(ring.count(), 64);
+    }
+
+    #[test]
+    fn bit_ring_words() {
+        let words = &mut [0u32; 2];
+        let mut ring = BitRing::new(words);
+
+        assert!(ring.put_word(0xdead_beef));
+        assert_eq!(ring.get_word(), Some(0xdead_beef));
+        assert_eq!(ring.get_word(), None);
+
+        // unaligned: bits straddle words and wrap around
+        ring.put(true);
+        assert!(ring.put_word(0x1234_5678));
+        assert!(!ring.put_word(0));
+        assert_eq!(ring.get(), Some(true));
+        assert_eq!(ring.get_word(), Some(0x1234_5678));
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn packed_ring() {
+        let words = &mut [0u32; 3];
+        let mut ring = PackedRing::<12>::new(words);
+        assert_eq!(ring.len(), 8);
+
+        for i in 0..9 {
+            ring.put(0xf00 | i); // 9th will have no effect
+        }
+        assert!(ring.is_full());
+        assert_eq!(ring.get(), Some(0xf00));
+        assert_eq!(ring.get(), Some(0xf01));
+        ring.put(0xabc);
+        ring.put(0x1def); // high bits dropped
+
+        let dst = &mut [0u16; 16];
+        assert_eq!(ring.unpack_into(dst), 8);
+        assert_eq!(&dst[..8], &[0xf02, 0xf03, 0xf04, 0xf05, 0xf06, 0xf07, 0xabc, 0xdef]);
+        assert!(ring.is_empty());
+
+        let words = &mut [0u32; 1];
+        let mut ring = PackedRing::<4>::new(words);
+        assert_eq!(ring.len(), 8);
+        ring.put(0x5);
+        assert_eq!(ring.peek(), Some(0x5));
+    }
+}
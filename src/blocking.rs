@@ -0,0 +1,187 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer with blocking operations, for `std` users
+
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::Duration;
+
+use ring::CBuf;
+
+/// Circular Buffer shareable between threads, with blocking operations
+///
+/// Wraps CBuf in a `Mutex`, with `Condvar`s to wait for data or free
+/// space: a tiny bounded channel without extra dependencies.
+pub struct BlockingCBuf<'a, T: 'a> {
+    inner: Mutex<CBuf<'a, T>>,
+    data: Condvar,
+    space: Condvar,
+}
+
+impl<'a, T: Clone> BlockingCBuf<'a, T> {
+    /// Create new BlockingCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> BlockingCBuf<'a, T> {
+        BlockingCBuf {
+            inner: Mutex::new(CBuf::new(buf)),
+            data: Condvar::new(),
+            space: Condvar::new(),
+        }
+    }
+
+    /// Lock the underlying CBuf
+    ///
+    /// CBuf is left consistent even if a thread panicked while holding
+    /// the lock, so poisoning is ignored.
+    fn lock(&self) -> MutexGuard<'_, CBuf<'a, T>> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Is buffer full?
+    pub fn is_full(&self) -> bool {
+        self.lock().is_full()
+    }
+
+    /// Is buffer empty?
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    pub fn put(&self, val: T) {
+        self.lock().put(val);
+        self.data.notify_one();
+    }
+
+    /// Add element the buffer, waiting for free space if it's full
+    pub fn put_blocking(&self, val: T) {
+        let mut cbuf = self.lock();
+        while cbuf.is_full() {
+            cbuf = self.space.wait(cbuf).unwrap_or_else(|e| e.into_inner());
+        }
+        cbuf.put(val);
+        drop(cbuf);
+        self.data.notify_one();
+    }
+
+    /// Add element the buffer, waiting at most `timeout` for free space
+    ///
+    /// Returns `Err` with the element if buffer is still full after
+    /// `timeout`.
+    pub fn put_timeout(&self, val: T, timeout: Duration) -> Result<(), T> {
+        let cbuf = self.lock();
+        let (mut cbuf, _) = self.space
+            .wait_timeout_while(cbuf, timeout, |cbuf| cbuf.is_full())
+            .unwrap_or_else(|e| e.into_inner());
+        if cbuf.is_full() {
+            return Err(val);
+        }
+        cbuf.put(val);
+        drop(cbuf);
+        self.data.notify_one();
+        Ok(())
+    }
+
+    /// Remove one element from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    pub fn get(&self) -> Option<T> {
+        let val = self.lock().get();
+        if val.is_some() {
+            self.space.notify_one();
+        }
+        val
+    }
+
+    /// Remove one element from the buffer, waiting for it if it's empty
+    pub fn get_blocking(&self) -> T {
+        let mut cbuf = self.lock();
+        loop {
+            if let Some(val) = cbuf.get() {
+                drop(cbuf);
+                self.space.notify_one();
+                return val;
+            }
+            cbuf = self.data.wait(cbuf).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+
+    /// Remove one element from the buffer, waiting at most `timeout`
+    ///
+    /// Returns `None` if buffer is still empty after `timeout`.
+    pub fn get_timeout(&self, timeout: Duration) -> Option<T> {
+        let cbuf = self.lock();
+        let (mut cbuf, _) = self.data
+            .wait_timeout_while(cbuf, timeout, |cbuf| cbuf.is_empty())
+            .unwrap_or_else(|e| e.into_inner());
+        let val = cbuf.get();
+        drop(cbuf);
+        if val.is_some() {
+            self.space.notify_one();
+        }
+        val
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn blocking() {
+        let buf = &mut [0u8; 2];
+        let cbuf = BlockingCBuf::new(buf);
+
+        assert!(cbuf.is_empty());
+        assert!(cbuf.get().is_none());
+        assert!(cbuf.get_timeout(Duration::from_millis(1)).is_none());
+        cbuf.put(1);
+        cbuf.put_blocking(2);
+        cbuf.put(42); // will have no effect
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.get_timeout(Duration::from_millis(1)), Some(1));
+        assert_eq!(cbuf.get_blocking(), 2);
+    }
+
+    #[test]
+    fn blocking_timeout() {
+        let buf = &mut [0u8; 1];
+        let cbuf = BlockingCBuf::new(buf);
+
+        assert_eq!(cbuf.put_timeout(1, Duration::from_millis(1)), Ok(()));
+        assert_eq!(cbuf.put_timeout(2, Duration::from_millis(1)), Err(2));
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                std::thread::sleep(Duration::from_millis(10));
+                assert_eq!(cbuf.get(), Some(1));
+            });
+            assert_eq!(cbuf.put_timeout(3, Duration::from_secs(10)), Ok(()));
+        });
+        assert_eq!(cbuf.get_timeout(Duration::from_millis(1)), Some(3));
+    }
+
+    #[test]
+    fn blocking_threads() {
+        let buf = &mut [0u32; 3];
+        let cbuf = BlockingCBuf::new(buf);
+
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..1000 {
+                    cbuf.put_blocking(i);
+                }
+            });
+
+            for i in 0..1000 {
+                assert_eq!(cbuf.get_blocking(), i);
+            }
+        });
+    }
+}
@@ -0,0 +1,478 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer with several independent readers
+
+use core::cell::Cell;
+
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer read by several independent readers
+///
+/// Every `Reader` has its own tail and sees every element put by the
+/// `Writer`; a slot is reused only after all readers have passed it. Tails
+/// need separate storage: one `usize` per reader.
+///
+/// Like `CBuf` it's not thread-shareable: writer and readers are used from
+/// one thread (or one async executor). Elements are `Copy` so slots can
+/// live in `Cell`s, without any unsafe code.
+///
+/// For logging, `Writer::force_put` overwrites the oldest element instead
+/// of waiting for slow readers; readers it lapped get `Lagged` once and
+/// continue from the oldest element still available.
+///
+/// Positions are free-running counters, so the length must be a power of
+/// two: slots are found by masking the counters, which stays consistent
+/// when they wrap (with any other length, positions `usize::MAX` and `0`
+/// would share a slot). `put` checks every tail, so it's not covered by
+/// the constant time promise of `CBuf`.
+///
+/// Readers get copies of the elements. For large frames, a ring built
+/// with `with_refcounts` lets readers `lease` them in place instead:
+/// every slot counts the readers borrowing it, and `force_put` leaves
+/// borrowed slots alone, so a slot is reused only once all readers have
+/// released it.
+///
+/// ```
+/// use cbuf::BroadcastCBuf;
+///
+/// let buf = &mut [0u16; 8];
+/// let tails = &mut [0; 2];
+/// let mut ring = BroadcastCBuf::new(buf, tails);
+/// let (mut telemetry, mut readers) = ring.split();
+/// let (mut radio, mut sd_card) = (readers.next().unwrap(), readers.next().unwrap());
+///
+/// telemetry.put(42);
+/// assert_eq!(radio.get(), Ok(Some(42)));
+/// assert_eq!(sd_card.get(), Ok(Some(42)));
+/// ```
+pub struct BroadcastCBuf<'a, T: 'a> {
+    buf: &'a [Cell<T>],
+    tails: &'a [Cell<usize>],
+    refs: &'a [Cell<usize>],
+    head: Cell<usize>,
+}
+
+/// Error of `Reader::get`: the writer overwrote this many unread elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Lagged(pub usize);
+
+/// Writer half of `BroadcastCBuf`
+pub struct Writer<'b, T: 'b> {
+    ring: &'b BroadcastCBuf<'b, T>,
+}
+
+/// One of the readers of `BroadcastCBuf`
+pub struct Reader<'b, T: 'b> {
+    ring: &'b BroadcastCBuf<'b, T>,
+    tail: &'b Cell<usize>,
+}
+
+/// Iterator over all readers, returned by `BroadcastCBuf::split`
+pub struct Readers<'b, T: 'b> {
+    ring: &'b BroadcastCBuf<'b, T>,
+    tails: core::slice::Iter<'b, Cell<usize>>,
+}
+
+impl<'a, T: Copy> BroadcastCBuf<'a, T> {
+    /// Create new BroadcastCBuf with one reader per element of `tails`
+    ///
+    /// panics if buf.len() is not a power of two or tails.len() == 0
+    pub fn new(buf: &'a mut [T], tails: &'a mut [usize]) -> BroadcastCBuf<'a, T> {
+        if !buf.len().is_power_of_two() {
+            panic!("len not a power of two")
+        }
+        if tails.is_empty() {
+            panic!("no readers")
+        }
+        for tail in tails.iter_mut() {
+            *tail = 0;
+        }
+
+        BroadcastCBuf {
+            buf: Cell::from_mut(buf).as_slice_of_cells(),
+            tails: Cell::from_mut(tails).as_slice_of_cells(),
+            refs: &[],
+            head: Cell::new(0),
+        }
+    }
+
+    /// Create new BroadcastCBuf whose readers can `lease` elements
+    ///
+    /// `refs` holds the number of readers borrowing each slot.
+    ///
+    /// panics if buf.len() is not a power of two, tails.len() == 0 or
+    /// refs.len() != buf.len()
+    #[cfg(not(feature = "safe"))]
+    pub fn with_refcounts(buf: &'a mut [T],
+                          tails: &'a mut [usize],
+                          refs: &'a mut [usize])
+                          -> BroadcastCBuf<'a, T> {
+        if refs.len() != buf.len() {
+            panic!("refs.len() != buf.len()")
+        }
+        for r in refs.iter_mut() {
+            *r = 0;
+        }
+
+        BroadcastCBuf {
+            refs: Cell::from_mut(refs).as_slice_of_cells(),
+            ..BroadcastCBuf::new(buf, tails)
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Slot of free-running position `pos`
+    #[inline]
+    fn slot(&self, pos: usize) -> &Cell<T> {
+        &self.buf[pos & (self.len() - 1)]
+    }
+
+    /// Number of readers borrowing the slot of `pos`
+    #[inline]
+    fn refcount(&self, pos: usize) -> Option<&Cell<usize>> {
+        self.refs.get(pos & (self.len() - 1))
+    }
+
+    /// Split into the writer and all readers
+    ///
+    /// Every reader holds back the writer until it has read an element, so
+    /// readers not needed should still be kept and drained.
+    pub fn split<'b>(&'b mut self) -> (Writer<'b, T>, Readers<'b, T>) {
+        (Writer { ring: self },
+         Readers {
+            ring: self,
+            tails: self.tails.iter(),
+        })
+    }
+}
+
+impl<'b, T: Copy> Writer<'b, T> {
+    /// Is buffer full, ie. is any reader a whole buffer behind?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        let head = self.ring.head.get();
+        self.ring.tails.iter().any(|tail| head.wrapping_sub(tail.get()) >= self.ring.len())
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        let head = self.ring.head.get();
+        self.ring.slot(head).set(val);
+        self.ring.head.set(head.wrapping_add(1));
+    }
+
+    /// Add element the buffer, overwriting the oldest one if it's full
+    ///
+    /// Readers that haven't read the overwritten element get `Lagged`.
+    /// Ignores the write if a reader still leases the oldest element.
+    #[inline]
+    pub fn force_put(&mut self, val: T) {
+        let head = self.ring.head.get();
+        if self.ring.refcount(head).is_some_and(|r| r.get() > 0) {
+            return;
+        }
+        self.ring.slot(head).set(val);
+        self.ring.head.set(head.wrapping_add(1));
+    }
+}
+
+impl<'b, T: Copy> Reader<'b, T> {
+    /// Number of elements this reader can still read
+    ///
+    /// Doesn't include elements overwritten before this reader got them.
+    #[inline]
+    pub fn count(&self) -> usize {
+        core::cmp::min(self.behind(), self.ring.len())
+    }
+
+    /// Distance to the writer, including overwritten elements
+    #[inline]
+    fn behind(&self) -> usize {
+        self.ring.head.get().wrapping_sub(self.tail.get())
+    }
+
+    /// Has this reader read everything put so far?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.head.get() == self.tail.get()
+    }
+
+    /// Next element for this reader, without removing it
+    ///
+    /// If the writer lapped this reader, that's the oldest element still
+    /// available. Returns `None` if this reader has read everything.
+    #[inline]
+    pub fn peek(&self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let pos = self.ring.head.get().wrapping_sub(self.count());
+        Some(self.ring.slot(pos).get())
+    }
+
+    /// Remove next element for this reader
+    ///
+    /// Other readers still get it. Returns `Ok(None)` if this reader has
+    /// read everything.
+    ///
+    /// If `Writer::force_put` overwrote elements this reader didn't read,
+    /// returns `Lagged` with their number and moves on to the oldest
+    /// element still available, which the next call returns.
+    #[inline]
+    pub fn get(&mut self) -> Result<Option<T>, Lagged> {
+        let behind = self.behind();
+        if behind > self.ring.len() {
+            self.tail.set(self.ring.head.get().wrapping_sub(self.ring.len()));
+            return Err(Lagged(behind - self.ring.len()));
+        }
+        let val = self.peek();
+        if val.is_some() {
+            self.tail.set(self.tail.get().wrapping_add(1));
+        }
+        Ok(val)
+    }
+
+    /// Borrow next element for this reader in place, without copying it
+    ///
+    /// Like `get`, but the element is removed only when the `Lease` is
+    /// dropped; until then the writer won't reuse its slot. Other readers
+    /// can lease the same element at the same time.
+    ///
+    /// panics if the ring wasn't created by `with_refcounts`
+    #[cfg(not(feature = "safe"))]
+    #[inline]
+    pub fn lease(&mut self) -> Result<Option<Lease<'_, 'b, T>>, Lagged> {
+        if self.ring.refs.is_empty() {
+            panic!("no refcounts")
+        }
+        let behind = self.behind();
+        if behind > self.ring.len() {
+            self.tail.set(self.ring.head.get().wrapping_sub(self.ring.len()));
+            return Err(Lagged(behind - self.ring.len()));
+        }
+        if self.is_empty() {
+            return Ok(None);
+        }
+        let pos = self.tail.get();
+        let refs = self.ring.refcount(pos).unwrap();
+        refs.set(refs.get() + 1);
+        Ok(Some(Lease { reader: self }))
+    }
+}
+
+/// Element of `BroadcastCBuf` borrowed by `Reader::lease`
+///
+/// Dropping it releases the slot and moves the reader on.
+#[cfg(not(feature = "safe"))]
+pub struct Lease<'r, 'b: 'r, T: 'b> {
+    reader: &'r mut Reader<'b, T>,
+}
+
+#[cfg(not(feature = "safe"))]
+impl<'r, 'b, T: Copy> core::ops::Deref for Lease<'r, 'b, T> {
+    type Target = T;
+
+    #[inline]
+    fn deref(&self) -> &T {
+        let ring = self.reader.ring;
+        // The slot is counted as borrowed: `put` doesn't reach it while the
+        // reader's tail points at it, and `force_put` checks the count.
+        unsafe { &*ring.slot(self.reader.tail.get()).as_ptr() }
+    }
+}
+
+#[cfg(not(feature = "safe"))]
+impl<'r, 'b, T> Drop for Lease<'r, 'b, T> {
+    #[inline]
+    fn drop(&mut self) {
+        let ring = self.reader.ring;
+        let tail = self.reader.tail.get();
+        let refs = &ring.refs[tail & (ring.buf.len() - 1)];
+        refs.set(refs.get() - 1);
+        self.reader.tail.set(tail.wrapping_add(1));
+    }
+}
+
+impl<'b, T: Copy> Iterator for Readers<'b, T> {
+    type Item = Reader<'b, T>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Reader<'b, T>> {
+        self.tails.next().map(|tail| {
+            Reader {
+                ring: self.ring,
+                tail,
+            }
+        })
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.tails.size_hint()
+    }
+}
+
+impl<'b, T: Copy> ExactSizeIterator for Readers<'b, T> {}
+
+impl<'b, T: Copy> RingProducer<T> for Writer<'b, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        Writer::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        Writer::put(self, val)
+    }
+}
+
+impl<'b, T: Copy> RingConsumer<T> for Reader<'b, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        Reader::is_empty(self)
+    }
+
+    /// Skips elements lost to `Writer::force_put`
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        Reader::get(self).unwrap_or_else(|_| Reader::get(self).unwrap_or(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broadcast() {
+        let buf = &mut [0u8; 2];
+        let tails = &mut [7; 2];
+        let mut ring = BroadcastCBuf::new(buf, tails);
+        let (mut writer, mut readers) = ring.split();
+        assert_eq!(readers.len(), 2);
+        let (mut fast, mut slow) = (readers.next().unwrap(), readers.next().unwrap());
+
+        writer.put(1);
+        writer.put(2);
+        assert!(writer.is_full());
+        assert_eq!((fast.get(), fast.get(), fast.get()), (Ok(Some(1)), Ok(Some(2)), Ok(None)));
+        // slow reader still holds both slots
+        assert!(writer.is_full());
+        writer.put(3); // will have no effect
+
+        assert_eq!(slow.count(), 2);
+        assert_eq!(slow.get(), Ok(Some(1)));
+        writer.put(3);
+        assert!(writer.is_full());
+        assert_eq!((slow.get(), slow.get(), slow.get()), (Ok(Some(2)), Ok(Some(3)), Ok(None)));
+        assert_eq!((fast.peek(), fast.get(), fast.get()), (Some(3), Ok(Some(3)), Ok(None)));
+        assert!(!writer.is_full());
+    }
+
+    #[test]
+    fn broadcast_lagged() {
+        let buf = &mut [0u8; 4];
+        let tails = &mut [0; 2];
+        let mut ring = BroadcastCBuf::new(buf, tails);
+        let (mut writer, mut readers) = ring.split();
+        let (mut fast, mut slow) = (readers.next().unwrap(), readers.next().unwrap());
+
+        for i in 1..8 {
+            writer.force_put(i);
+            assert_eq!(fast.get(), Ok(Some(i)));
+        }
+        assert_eq!((slow.count(), slow.peek()), (4, Some(4)));
+        assert_eq!(slow.get(), Err(Lagged(3)));
+        assert_eq!((slow.get(), slow.get()), (Ok(Some(4)), Ok(Some(5))));
+        assert_eq!(fast.get(), Ok(None));
+
+        writer.force_put(8);
+        writer.force_put(9);
+        writer.force_put(10);
+        // one missed, silently skipped through the trait
+        assert_eq!(RingConsumer::get(&mut slow), Some(7));
+    }
+
+    #[test]
+    fn counter_wrap() {
+        let buf = &mut [0u8; 4];
+        let tails = &mut [0; 1];
+        let mut ring = BroadcastCBuf::new(buf, tails);
+        ring.head.set(usize::MAX - 1);
+        ring.tails[0].set(usize::MAX - 1);
+        let (mut writer, mut readers) = ring.split();
+        let mut reader = readers.next().unwrap();
+
+        for i in 1..4 {
+            writer.put(i);
+        }
+        assert_eq!(reader.count(), 3);
+        for i in 1..4 {
+            assert_eq!(reader.get(), Ok(Some(i)));
+        }
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn lease() {
+        let buf = &mut [[0u8; 64]; 2];
+        let (tails, refs) = (&mut [0; 2], &mut [0; 2]);
+        let mut ring = BroadcastCBuf::with_refcounts(buf, tails, refs);
+        let (mut writer, mut readers) = ring.split();
+        let (mut radio, mut sd_card) = (readers.next().unwrap(), readers.next().unwrap());
+
+        writer.put([1; 64]);
+        writer.put([2; 64]);
+        {
+            let frame = radio.lease().unwrap().unwrap();
+            assert_eq!(frame[63], 1);
+            sd_card.get().unwrap();
+            sd_card.get().unwrap();
+            // radio still holds the slot of `1`
+            writer.force_put([3; 64]);
+            assert_eq!(frame[0], 1);
+        }
+        assert_eq!(radio.count(), 1);
+        writer.force_put([3; 64]);
+        {
+            let a = radio.lease().unwrap().unwrap();
+            let b = sd_card.lease().unwrap().unwrap();
+            assert_eq!((a[0], b[0]), (2, 3));
+        }
+        assert_eq!(radio.get(), Ok(Some([3; 64])));
+        assert!(radio.lease().unwrap().is_none());
+        assert!(sd_card.is_empty());
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    #[should_panic]
+    fn lease_without_refcounts() {
+        let (buf, tails) = (&mut [0u8; 2], &mut [0; 1]);
+        let mut ring = BroadcastCBuf::new(buf, tails);
+        let (_, mut readers) = ring.split();
+        let _ = readers.next().unwrap().lease();
+    }
+
+    #[test]
+    #[should_panic]
+    fn not_power_of_two() {
+        BroadcastCBuf::new(&mut [0u8; 3], &mut [0; 1]);
+    }
+}
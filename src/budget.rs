@@ -0,0 +1,203 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Global cap on bytes buffered across many Circular Buffers
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ring::{CBuf, NoSpace};
+
+/// Byte budget shared by several rings
+///
+/// Every ring registers with a guaranteed minimum, reserved up front.
+/// Bytes above its minimum come from a common pool: whatever part of
+/// `cap` isn't reserved. Combined buffered bytes never exceed `cap`.
+pub struct MemoryBudget {
+    cap: usize,
+    // reserved minimums plus pool bytes in use
+    committed: AtomicUsize,
+}
+
+/// One ring's registration with a `MemoryBudget`
+///
+/// Returns its minimum to the budget when dropped.
+pub struct BudgetShare<'b> {
+    budget: &'b MemoryBudget,
+    min: usize,
+    used: usize,
+}
+
+impl MemoryBudget {
+    /// Create new MemoryBudget of `cap` bytes
+    pub const fn new(cap: usize) -> MemoryBudget {
+        MemoryBudget {
+            cap,
+            committed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Bytes neither reserved nor taken from the pool
+    #[inline]
+    pub fn available(&self) -> usize {
+        self.cap - self.committed.load(Ordering::Relaxed)
+    }
+
+    /// Register a ring guaranteed at least `min` bytes
+    ///
+    /// Returns `None` if `min` is more than `available()`.
+    pub fn register(&self, min: usize) -> Option<BudgetShare<'_>> {
+        if !self.take(min) {
+            return None;
+        }
+        Some(BudgetShare {
+            budget: self,
+            min,
+            used: 0,
+        })
+    }
+
+    fn take(&self, n: usize) -> bool {
+        let mut committed = self.committed.load(Ordering::Relaxed);
+        loop {
+            if n > self.cap - committed {
+                return false;
+            }
+            match self.committed.compare_exchange_weak(committed,
+                                                       committed + n,
+                                                       Ordering::Relaxed,
+                                                       Ordering::Relaxed) {
+                Ok(_) => return true,
+                Err(cur) => committed = cur,
+            }
+        }
+    }
+
+    fn give(&self, n: usize) {
+        self.committed.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+impl<'b> BudgetShare<'b> {
+    /// Bytes currently accounted to this share
+    #[inline]
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Account `n` more bytes, drawing from the pool above the minimum
+    ///
+    /// Returns `false` and accounts nothing if the pool can't cover it.
+    pub fn try_acquire(&mut self, n: usize) -> bool {
+        let from_pool = (self.used + n).saturating_sub(self.min) -
+                        self.used.saturating_sub(self.min);
+        if from_pool > 0 && !self.budget.take(from_pool) {
+            return false;
+        }
+        self.used += n;
+        true
+    }
+
+    /// Stop accounting `n` bytes
+    ///
+    /// panics if n > used()
+    pub fn release(&mut self, n: usize) {
+        if n > self.used {
+            panic!("n > used")
+        }
+        let to_pool = self.used.saturating_sub(self.min) -
+                      (self.used - n).saturating_sub(self.min);
+        self.budget.give(to_pool);
+        self.used -= n;
+    }
+}
+
+impl<'b> Drop for BudgetShare<'b> {
+    fn drop(&mut self) {
+        let used = self.used;
+        self.release(used);
+        self.budget.give(self.min);
+    }
+}
+
+/// Byte Circular Buffer accounting its contents to a `BudgetShare`
+pub struct BudgetedCBuf<'a, 'b> {
+    inner: CBuf<'a, u8>,
+    share: BudgetShare<'b>,
+}
+
+impl<'a, 'b> BudgetedCBuf<'a, 'b> {
+    /// Create new BudgetedCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [u8], share: BudgetShare<'b>) -> BudgetedCBuf<'a, 'b> {
+        BudgetedCBuf {
+            inner: CBuf::new(buf),
+            share,
+        }
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Number of bytes in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Add all of `src`
+    ///
+    /// All or nothing: fails if the buffer or the budget can't take it.
+    pub fn put_slice(&mut self, src: &[u8]) -> Result<(), NoSpace> {
+        if src.len() > self.inner.len() - self.inner.count() ||
+           !self.share.try_acquire(src.len()) {
+            return Err(NoSpace);
+        }
+        self.inner.put_slice(src);
+        Ok(())
+    }
+
+    /// Remove bytes into `dst`, returning them to the budget
+    ///
+    /// Returns number of bytes removed.
+    pub fn pop_into(&mut self, dst: &mut [u8]) -> usize {
+        let n = self.inner.pop_into(dst);
+        self.share.release(n);
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget() {
+        let budget = MemoryBudget::new(10);
+        let (buf_a, buf_b) = (&mut [0u8; 8], &mut [0u8; 8]);
+        let mut a = BudgetedCBuf::new(buf_a, budget.register(4).unwrap());
+        let mut b = BudgetedCBuf::new(buf_b, budget.register(2).unwrap());
+        assert!(budget.register(5).is_none());
+        assert_eq!(budget.available(), 4);
+
+        assert_eq!(a.put_slice(&[1; 7]), Ok(()));
+        assert_eq!(b.put_slice(&[2; 3]), Ok(()));
+        assert_eq!(budget.available(), 0);
+        assert_eq!(b.put_slice(&[2]), Err(NoSpace));
+
+        assert_eq!(a.pop_into(&mut [0; 5]), 5);
+        assert_eq!(budget.available(), 3);
+        assert_eq!(b.put_slice(&[2]), Ok(()));
+        assert_eq!(budget.available(), 2);
+
+        drop(a);
+        assert_eq!(budget.available(), 6);
+        drop(b);
+        assert_eq!(budget.available(), 10);
+    }
+}
@@ -0,0 +1,145 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Builder for `CBuf` and the rings wrapping it
+
+use ring::{CBuf, OverflowPolicy};
+#[cfg(feature = "watermark")]
+use watermark::{Watermark, WatermarkCBuf};
+
+/// Builder collecting the configuration of a `CBuf`
+///
+/// ```
+/// use cbuf::{CBufBuilder, OverflowPolicy};
+///
+/// let storage = &mut [0u8; 64];
+/// let mut rx = CBufBuilder::new(storage).capacity(32).overwrite().build();
+/// assert_eq!((rx.len(), rx.policy()), (32, OverflowPolicy::Overwrite));
+/// rx.put(1);
+/// ```
+pub struct CBufBuilder<'a, T: 'a> {
+    buf: &'a mut [T],
+    policy: OverflowPolicy,
+    watermarks: Option<(usize, usize)>,
+}
+
+impl<'a, T: Clone> CBufBuilder<'a, T> {
+    /// Start building a CBuf over all of `buf`
+    pub fn new(buf: &'a mut [T]) -> CBufBuilder<'a, T> {
+        CBufBuilder {
+            buf,
+            policy: OverflowPolicy::Reject,
+            watermarks: None,
+        }
+    }
+
+    /// Use only the first `len` elements of the storage
+    ///
+    /// panics if len > the storage length
+    pub fn capacity(self, len: usize) -> CBufBuilder<'a, T> {
+        if len > self.buf.len() {
+            panic!("capacity above storage length")
+        }
+        CBufBuilder { buf: &mut self.buf[..len], ..self }
+    }
+
+    /// Set the behavior of `put` when full
+    pub fn policy(self, policy: OverflowPolicy) -> CBufBuilder<'a, T> {
+        CBufBuilder { policy, ..self }
+    }
+
+    /// Drop the oldest element when full, see `OverflowPolicy::Overwrite`
+    pub fn overwrite(self) -> CBufBuilder<'a, T> {
+        self.policy(OverflowPolicy::Overwrite)
+    }
+
+    /// Ignore new elements when full, see `OverflowPolicy::Reject`
+    pub fn reject(self) -> CBufBuilder<'a, T> {
+        self.policy(OverflowPolicy::Reject)
+    }
+
+    /// Report crossing `low` and `high` elements, see `WatermarkCBuf`
+    ///
+    /// Finish with `build_watermark`.
+    #[cfg(feature = "watermark")]
+    pub fn watermarks(self, low: usize, high: usize) -> CBufBuilder<'a, T> {
+        CBufBuilder { watermarks: Some((low, high)), ..self }
+    }
+
+    /// Create the CBuf
+    ///
+    /// panics if the capacity is 0 or above `MAX_CAPACITY`, or if
+    /// `watermarks` was set
+    pub fn build(self) -> CBuf<'a, T> {
+        if self.watermarks.is_some() {
+            panic!("watermarks need build_watermark")
+        }
+        CBuf::with_policy(self.buf, self.policy)
+    }
+
+    /// Create the WatermarkCBuf calling `on_cross`
+    ///
+    /// panics if the capacity is 0 or above `MAX_CAPACITY`, if
+    /// `watermarks` wasn't set or if they are bad for `WatermarkCBuf`
+    #[cfg(feature = "watermark")]
+    pub fn build_watermark<F: FnMut(Watermark)>(self, on_cross: F) -> WatermarkCBuf<'a, T, F> {
+        let (low, high) = match self.watermarks {
+            Some(marks) => marks,
+            None => panic!("no watermarks"),
+        };
+        WatermarkCBuf::from_cbuf(CBuf::with_policy(self.buf, self.policy), low, high, on_cross)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder() {
+        let buf = &mut [0u8; 8];
+        let mut cbuf = CBufBuilder::new(buf).capacity(2).overwrite().build();
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.put(3);
+        assert_eq!((cbuf.len(), cbuf.get(), cbuf.get()), (2, Some(2), Some(3)));
+
+        let buf = &mut [0u8; 2];
+        let cbuf = CBufBuilder::new(buf).overwrite().reject().build();
+        assert_eq!(cbuf.policy(), OverflowPolicy::Reject);
+    }
+
+    #[test]
+    #[cfg(feature = "watermark")]
+    fn builder_watermark() {
+        let mut high = 0;
+        {
+            let buf = &mut [0u8; 8];
+            let mut cbuf = CBufBuilder::new(buf)
+                               .capacity(4)
+                               .overwrite()
+                               .watermarks(1, 3)
+                               .build_watermark(|mark| if mark == Watermark::High { high += 1 });
+            for val in 0..6 {
+                cbuf.put(val);
+            }
+            assert_eq!((cbuf.len(), cbuf.get()), (4, Some(2)));
+        }
+        assert_eq!(high, 1);
+    }
+
+    #[test]
+    #[should_panic]
+    fn capacity_above_storage() {
+        CBufBuilder::new(&mut [0u8; 2]).capacity(3);
+    }
+
+    #[test]
+    #[should_panic]
+    #[cfg(feature = "watermark")]
+    fn build_ignoring_watermarks() {
+        CBufBuilder::new(&mut [0u8; 4]).watermarks(1, 3).build();
+    }
+}
@@ -0,0 +1,226 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Channel-style API on top of `spsc`
+//!
+//! Mirrors `std::sync::mpsc::sync_channel`, but without allocation: the
+//! caller owns the `Channel` and `split`s it into a `Sender` and a
+//! `Receiver`. Dropping either half disconnects the channel.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use spsc::{Consumer, Producer, SpscCBuf};
+
+/// Error of `Sender::send`: receiver is gone
+///
+/// Carries back the value that couldn't be sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SendError<T>(pub T);
+
+/// Error of `Sender::try_send`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TrySendError<T> {
+    /// Buffer is full
+    Full(T),
+    /// Receiver is gone
+    Disconnected(T),
+}
+
+/// Error of `Receiver::recv`: buffer is empty and sender is gone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct RecvError;
+
+/// Error of `Receiver::try_recv`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum TryRecvError {
+    /// Buffer is empty
+    Empty,
+    /// Buffer is empty and sender is gone
+    Disconnected,
+}
+
+/// Storage of a channel
+pub struct Channel<'a, T: 'a> {
+    ring: SpscCBuf<'a, T>,
+    sender_alive: AtomicBool,
+    receiver_alive: AtomicBool,
+}
+
+/// Sending half of a `Channel`
+pub struct Sender<'b, T: 'b> {
+    producer: Producer<'b, T>,
+    sender_alive: &'b AtomicBool,
+    receiver_alive: &'b AtomicBool,
+}
+
+/// Receiving half of a `Channel`
+pub struct Receiver<'b, T: 'b> {
+    consumer: Consumer<'b, T>,
+    sender_alive: &'b AtomicBool,
+    receiver_alive: &'b AtomicBool,
+}
+
+/// Wait a bit before checking the buffer again
+#[inline]
+fn relax() {
+    #[cfg(feature = "std")]
+    ::std::thread::yield_now();
+    #[cfg(not(feature = "std"))]
+    core::hint::spin_loop();
+}
+
+impl<'a, T: Clone> Channel<'a, T> {
+    /// Create new Channel
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> Channel<'a, T> {
+        Channel {
+            ring: SpscCBuf::new(buf),
+            sender_alive: AtomicBool::new(true),
+            receiver_alive: AtomicBool::new(true),
+        }
+    }
+
+    /// Split into sending and receiving halves
+    pub fn split<'b>(&'b mut self) -> (Sender<'b, T>, Receiver<'b, T>) {
+        self.sender_alive.store(true, Ordering::Relaxed);
+        self.receiver_alive.store(true, Ordering::Relaxed);
+        let (producer, consumer) = self.ring.split();
+        (Sender {
+            producer,
+            sender_alive: &self.sender_alive,
+            receiver_alive: &self.receiver_alive,
+        },
+         Receiver {
+            consumer,
+            sender_alive: &self.sender_alive,
+            receiver_alive: &self.receiver_alive,
+        })
+    }
+}
+
+impl<'b, T: Clone> Sender<'b, T> {
+    /// Send a value, waiting for free space if buffer is full
+    ///
+    /// Fails if receiver is gone.
+    pub fn send(&mut self, val: T) -> Result<(), SendError<T>> {
+        let mut val = val;
+        loop {
+            match self.try_send(val) {
+                Ok(()) => return Ok(()),
+                Err(TrySendError::Full(v)) => val = v,
+                Err(TrySendError::Disconnected(v)) => return Err(SendError(v)),
+            }
+            relax();
+        }
+    }
+
+    /// Send a value if there's free space in the buffer
+    pub fn try_send(&mut self, val: T) -> Result<(), TrySendError<T>> {
+        if !self.receiver_alive.load(Ordering::Acquire) {
+            return Err(TrySendError::Disconnected(val));
+        }
+        if self.producer.is_full() {
+            return Err(TrySendError::Full(val));
+        }
+        self.producer.put(val);
+        Ok(())
+    }
+}
+
+impl<'b, T: Clone> Receiver<'b, T> {
+    /// Receive a value, waiting for it if buffer is empty
+    ///
+    /// Fails once buffer is empty and sender is gone.
+    pub fn recv(&mut self) -> Result<T, RecvError> {
+        loop {
+            match self.try_recv() {
+                Ok(val) => return Ok(val),
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => return Err(RecvError),
+            }
+            relax();
+        }
+    }
+
+    /// Receive a value if buffer is not empty
+    pub fn try_recv(&mut self) -> Result<T, TryRecvError> {
+        if let Some(val) = self.consumer.get() {
+            return Ok(val);
+        }
+        if self.sender_alive.load(Ordering::Acquire) {
+            return Err(TryRecvError::Empty);
+        }
+        // Sender might have put something right before leaving
+        self.consumer.get().ok_or(TryRecvError::Disconnected)
+    }
+}
+
+impl<'b, T> Drop for Sender<'b, T> {
+    fn drop(&mut self) {
+        self.sender_alive.store(false, Ordering::Release);
+    }
+}
+
+impl<'b, T> Drop for Receiver<'b, T> {
+    fn drop(&mut self) {
+        self.receiver_alive.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn channel() {
+        let buf = &mut [0u8; 2];
+        let mut chan = Channel::new(buf);
+        let (mut tx, mut rx) = chan.split();
+
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Empty));
+        assert_eq!(tx.send(1), Ok(()));
+        assert_eq!(tx.try_send(2), Ok(()));
+        assert_eq!(tx.try_send(3), Err(TrySendError::Full(3)));
+        assert_eq!(rx.recv(), Ok(1));
+
+        drop(tx);
+        assert_eq!(rx.try_recv(), Ok(2));
+        assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+        assert_eq!(rx.recv(), Err(RecvError));
+        drop(rx);
+
+        let (mut tx, rx) = chan.split();
+        assert_eq!(tx.send(4), Ok(()));
+        drop(rx);
+        assert_eq!(tx.send(5), Err(SendError(5)));
+        assert_eq!(tx.try_send(5), Err(TrySendError::Disconnected(5)));
+    }
+
+    #[test]
+    fn channel_threads() {
+        let buf = &mut [0u32; 4];
+        let mut chan = Channel::new(buf);
+        let (mut tx, mut rx) = chan.split();
+
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..1000 {
+                    tx.send(i).unwrap();
+                }
+            });
+
+            for i in 0..1000 {
+                assert_eq!(rx.recv(), Ok(i));
+            }
+            assert_eq!(rx.recv(), Err(RecvError));
+        });
+    }
+}
@@ -0,0 +1,159 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! COBS packet framing over byte Circular Buffers
+//!
+//! Frames are COBS-encoded, so they never contain a zero byte, and
+//! terminated with one. Receivers can thus resynchronize on the next zero
+//! after garbage on UART/RS485 links.
+
+use ring::{CBuf, NoSpace};
+
+/// Longest run of non-zero bytes in one COBS block
+const MAX_RUN: usize = 254;
+
+impl<'a> CBuf<'a, u8> {
+    /// COBS-encode `data` and add it followed by a zero delimiter
+    ///
+    /// All or nothing: on error the buffer is left unchanged.
+    pub fn put_frame(&mut self, data: &[u8]) -> Result<(), NoSpace> {
+        let cp = self.checkpoint();
+        if self.encode(data) {
+            Ok(())
+        } else {
+            self.rollback(cp);
+            Err(NoSpace)
+        }
+    }
+
+    fn encode(&mut self, data: &[u8]) -> bool {
+        let mut rest = data;
+        loop {
+            let max = core::cmp::min(rest.len(), MAX_RUN);
+            let (run, next) = match rest[..max].iter().position(|&b| b == 0) {
+                Some(run) => (run, run + 1),
+                None => (max, max),
+            };
+            if self.is_full() {
+                return false;
+            }
+            self.put(run as u8 + 1);
+            if self.put_slice(&rest[..run]) != run {
+                return false;
+            }
+            // Only a run ended by a zero or a full 254 byte run is
+            // followed by another block
+            let last = next == run && (run < MAX_RUN || next == rest.len());
+            rest = &rest[next..];
+            if last {
+                break;
+            }
+        }
+        if self.is_full() {
+            return false;
+        }
+        self.put(0);
+        true
+    }
+
+    /// Remove next complete frame and decode it into `out`
+    ///
+    /// Returns decoded length, or `None` if there's no complete frame.
+    /// Malformed frames and frames longer than `out` are removed and
+    /// skipped.
+    pub fn get_frame(&mut self, out: &mut [u8]) -> Option<usize> {
+        loop {
+            let len = self.find(0)?;
+            let decoded = {
+                let (a, b) = self.as_slices();
+                let at = |k: usize| if k < a.len() { a[k] } else { b[k - a.len()] };
+                decode(at, len, out)
+            };
+            for _ in 0..len + 1 {
+                self.get();
+            }
+            if decoded.is_some() {
+                return decoded;
+            }
+        }
+    }
+}
+
+/// Decode `len` encoded bytes read through `at` into `out`
+fn decode<F: Fn(usize) -> u8>(at: F, len: usize, out: &mut [u8]) -> Option<usize> {
+    let (mut i, mut n) = (0, 0);
+    while i < len {
+        let code = at(i) as usize;
+        i += 1;
+        if code == 0 || i + code - 1 > len || n + code - 1 > out.len() {
+            return None;
+        }
+        for _ in 1..code {
+            out[n] = at(i);
+            i += 1;
+            n += 1;
+        }
+        if code <= MAX_RUN && i < len {
+            *out.get_mut(n)? = 0;
+            n += 1;
+        }
+    }
+    Some(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cobs() {
+        let buf = &mut [0u8; 16];
+        let mut cbuf = CBuf::new(buf);
+        let out = &mut [0u8; 16];
+
+        cbuf.put_frame(&[1, 0, 2, 0]).unwrap();
+        cbuf.put_frame(&[]).unwrap();
+        assert_eq!(cbuf.as_slices().0, &[2, 1, 2, 2, 1, 0, 1, 0]);
+        assert_eq!(cbuf.get_frame(out), Some(4));
+        assert_eq!(&out[..4], &[1, 0, 2, 0]);
+        assert_eq!(cbuf.get_frame(out), Some(0));
+        assert_eq!(cbuf.get_frame(out), None);
+
+        // garbage before the frame, then a partial one
+        cbuf.put_slice(&[5, 5, 0]);
+        cbuf.put_frame(&[3, 4]).unwrap();
+        cbuf.put_slice(&[2]);
+        assert_eq!(cbuf.get_frame(out), Some(2));
+        assert_eq!(&out[..2], &[3, 4]);
+        assert_eq!(cbuf.get_frame(out), None);
+        assert_eq!(cbuf.count(), 1);
+
+        assert_eq!(cbuf.put_frame(&[7; 15]), Err(NoSpace));
+        assert_eq!(cbuf.count(), 1);
+    }
+
+    #[test]
+    fn cobs_long_run() {
+        let buf = &mut [0u8; 600];
+        let mut cbuf = CBuf::new(buf);
+        let out = &mut [0u8; 600];
+
+        let mut data = [0u8; 520];
+        for (i, b) in data.iter_mut().enumerate() {
+            *b = (i % 255) as u8 + 1;
+        }
+        data[300] = 0;
+        for len in [254, 255, 300, 301, 520].iter().cloned() {
+            cbuf.put_frame(&data[..len]).unwrap();
+            assert_eq!(cbuf.get_frame(out), Some(len));
+            assert_eq!(&out[..len], &data[..len]);
+            assert!(cbuf.is_empty());
+        }
+
+        cbuf.put_frame(&data[..10]).unwrap();
+        assert_eq!(cbuf.get_frame(&mut out[..9]), None);
+        assert!(cbuf.is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Owned Circular Buffer usable in `const` contexts
+
+use super::{cbuf_next, CBUF_DATA_BIT};
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer of `N` `Copy` elements with `const fn` operations
+///
+/// Same logic as `CBuf`, but owning its storage, so lookup tables and
+/// schedules can be built at compile time with the same data structure
+/// used at runtime:
+///
+/// ```
+/// use cbuf::ConstRing;
+///
+/// const QUEUE: ConstRing<u8, 4> = {
+///     let mut ring = ConstRing::new(0);
+///     ring.put(1);
+///     ring.put(2);
+///     ring.get();
+///     ring.put(3);
+///     ring
+/// };
+///
+/// let mut queue = QUEUE;
+/// assert_eq!(queue.get(), Some(2));
+/// ```
+///
+/// `Debug` shows the queued elements oldest first, like `CBuf`.
+#[derive(Clone, Copy)]
+pub struct ConstRing<T, const N: usize> {
+    buf: [T; N],
+    head: usize,
+    tail: usize,
+}
+
+impl<T: Copy, const N: usize> ConstRing<T, N> {
+    /// Create new ConstRing with all slots set to `init`
+    ///
+    /// panics if N == 0, or if N doesn't fit below the index tag bit
+    /// (only possible for zero-sized `T`)
+    pub const fn new(init: T) -> ConstRing<T, N> {
+        if N == 0 {
+            panic!("len==0")
+        }
+        if N >= CBUF_DATA_BIT {
+            panic!("len too big")
+        }
+
+        ConstRing {
+            buf: [init; N],
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub const fn is_full(&self) -> bool {
+        (self.head ^ self.tail) == CBUF_DATA_BIT
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub const fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(&self.buf[self.tail & !CBUF_DATA_BIT])
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub const fn get(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let val = self.buf[self.tail & !CBUF_DATA_BIT];
+        self.tail = cbuf_next(self.tail, N);
+        Some(val)
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub const fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        self.buf[self.head & !CBUF_DATA_BIT] = val;
+        self.head = cbuf_next(self.head, N);
+    }
+}
+
+impl<T: Copy + core::fmt::Debug, const N: usize> core::fmt::Debug for ConstRing<T, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        struct Contents<T, const N: usize>(ConstRing<T, N>);
+
+        impl<T: Copy + core::fmt::Debug, const N: usize> core::fmt::Debug for Contents<T, N> {
+            fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                let mut ring = self.0;
+                f.debug_list().entries(core::iter::from_fn(|| ring.get())).finish()
+            }
+        }
+
+        let mut count = 0;
+        let mut ring = *self;
+        while ring.get().is_some() {
+            count += 1;
+        }
+        f.debug_struct("ConstRing")
+            .field("contents", &Contents(*self))
+            .field("count", &count)
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl<T: Copy + defmt::Format, const N: usize> defmt::Format for ConstRing<T, N> {
+    fn format(&self, f: defmt::Formatter) {
+        let (mut ring, mut count) = (*self, 0);
+        while ring.get().is_some() {
+            count += 1;
+        }
+        ring = *self;
+        ::defmt_ring(f, "ConstRing", core::iter::from_fn(|| ring.get()), count, N)
+    }
+}
+
+/// Compares queued elements, not raw slots or indexes
+impl<T: Copy + PartialEq<U>, U: Copy, const N: usize, const M: usize> PartialEq<ConstRing<U, M>> for ConstRing<T, N> {
+    fn eq(&self, other: &ConstRing<U, M>) -> bool {
+        let (mut a, mut b) = (*self, *other);
+        loop {
+            match (a.get(), b.get()) {
+                (Some(x), Some(y)) if x == y => {}
+                (None, None) => return true,
+                _ => return false,
+            }
+        }
+    }
+}
+
+impl<T: Copy + Eq, const N: usize> Eq for ConstRing<T, N> {}
+
+impl<T: Copy + PartialEq<U>, U, const N: usize> PartialEq<[U]> for ConstRing<T, N> {
+    fn eq(&self, other: &[U]) -> bool {
+        let mut ring = *self;
+        other.iter().all(|y| ring.get().is_some_and(|x| x == *y)) && ring.is_empty()
+    }
+}
+
+impl<T: Copy + PartialEq<U>, U, const N: usize, const M: usize> PartialEq<[U; M]> for ConstRing<T, N> {
+    fn eq(&self, other: &[U; M]) -> bool {
+        *self == other[..]
+    }
+}
+
+impl<T: Copy, const N: usize> RingProducer<T> for ConstRing<T, N> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        ConstRing::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        ConstRing::put(self, val)
+    }
+}
+
+impl<T: Copy, const N: usize> RingConsumer<T> for ConstRing<T, N> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        ConstRing::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        ConstRing::get(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: Copy + serde::Serialize, const N: usize> serde::Serialize for ConstRing<T, N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeSeq;
+
+        let mut ring = *self;
+        let mut seq = serializer.serialize_seq(None)?;
+        while let Some(val) = ring.get() {
+            seq.serialize_element(&val)?;
+        }
+        seq.end()
+    }
+}
+
+/// Fails with `invalid_length` on sequences longer than `N`
+#[cfg(feature = "serde")]
+impl<'de, T: Copy + Default + serde::Deserialize<'de>, const N: usize> serde::Deserialize<'de>
+    for ConstRing<T, N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor<T, const N: usize>(core::marker::PhantomData<T>);
+
+        impl<'de, T: Copy + Default + serde::Deserialize<'de>, const N: usize> serde::de::Visitor<'de>
+            for Visitor<T, N> {
+            type Value = ConstRing<T, N>;
+
+            fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+                write!(f, "a sequence of at most {} elements", N)
+            }
+
+            fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut ring = ConstRing::new(T::default());
+                while let Some(val) = seq.next_element()? {
+                    if ring.is_full() {
+                        return Err(serde::de::Error::invalid_length(N + 1, &self));
+                    }
+                    ring.put(val);
+                }
+                Ok(ring)
+            }
+        }
+
+        deserializer.deserialize_seq(Visitor(core::marker::PhantomData))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    /// Schedule built at compile time by running the queue
+    const SCHEDULE: [u8; 6] = {
+        let mut ring = ConstRing::<u8, 3>::new(0);
+        let mut out = [0u8; 6];
+        let mut i = 0;
+        while i < 6 {
+            ring.put(i as u8 * 2);
+            ring.put(i as u8 * 2 + 1);
+            out[i] = match ring.get() {
+                Some(val) => val,
+                None => 0xff,
+            };
+            i += 1;
+        }
+        out
+    };
+
+    #[test]
+    fn const_ring() {
+        assert_eq!(SCHEDULE, [0, 1, 2, 3, 4, 6]);
+
+        let mut ring = ConstRing::<u16, 2>::new(0);
+        assert!(ring.is_empty());
+        ring.put(1);
+        ring.put(2);
+        ring.put(3); // will have no effect
+        assert!(ring.is_full());
+        assert_eq!(ring.peek(), Some(&1));
+        assert_eq!(ring.get(), Some(1));
+        assert_eq!(ring.get(), Some(2));
+        assert_eq!(ring.get(), None);
+    }
+
+    #[test]
+    fn const_ring_eq() {
+        let mut a = ConstRing::<u8, 2>::new(0);
+        let mut b = ConstRing::<u8, 3>::new(5);
+        a.put(1);
+        a.put(2);
+        a.get();
+        b.put(2);
+
+        assert_eq!(a, b);
+        assert_eq!(a, [2]);
+        assert!(a != [2, 0]);
+        b.put(3);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn const_ring_debug() {
+        let mut ring = ConstRing::<u8, 3>::new(0);
+        ring.put(1);
+        ring.put(2);
+        ring.get();
+
+        assert_eq!(std::format!("{:?}", ring), "ConstRing { contents: [2], count: 1, capacity: 3 }");
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn const_ring_serde() {
+        use serde_test::{assert_tokens, Token};
+
+        let mut ring = ConstRing::<u8, 2>::new(0);
+        ring.put(1);
+        ring.put(2);
+        ring.get();
+        ring.put(3);
+        assert_tokens(&ring, &[Token::Seq { len: None }, Token::U8(2), Token::U8(3), Token::SeqEnd]);
+    }
+}
@@ -0,0 +1,239 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer keeping an explicit element counter
+
+use ring::CBuf;
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer with occupancy queries that are single loads
+///
+/// Maintains the number of elements next to head and tail, so `count`,
+/// `is_full` and `remaining_capacity` don't have to decode the indexes.
+/// Costs one more word and an update in every `put` and `get`.
+///
+/// Also keeps running totals of elements ever put and removed, for
+/// throughput measurement and sequence numbering. Elements evicted by
+/// `force_put` or dropped by `discard` or `clear` count as neither removed
+/// nor still queued, so `pushed_count() - popped_count() - count()` is the number
+/// of elements lost.
+pub struct CountedCBuf<'a, T: 'a> {
+    inner: CBuf<'a, T>,
+    count: usize,
+    pushed: u64,
+    popped: u64,
+}
+
+impl<'a, T: Clone> CountedCBuf<'a, T> {
+    /// Create new CountedCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> CountedCBuf<'a, T> {
+        CountedCBuf {
+            inner: CBuf::new(buf),
+            count: 0,
+            pushed: 0,
+            popped: 0,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Total number of elements ever put, wrapping on overflow
+    #[inline]
+    pub fn pushed_count(&self) -> u64 {
+        self.pushed
+    }
+
+    /// Total number of elements ever removed by `get` or `pop_into`,
+    /// wrapping on overflow
+    #[inline]
+    pub fn popped_count(&self) -> u64 {
+        self.popped
+    }
+
+    /// Number of free slots
+    #[inline]
+    pub fn remaining_capacity(&self) -> usize {
+        self.inner.len() - self.count
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.count == self.inner.len()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&mut self) -> Option<&T> {
+        self.inner.peek()
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        if self.count == 0 {
+            return None;
+        }
+        #[cfg(not(feature = "safe"))]
+        let val = unsafe { self.inner.get_unchecked() };
+        #[cfg(feature = "safe")]
+        let val = self.inner.get()?;
+        self.count -= 1;
+        self.popped = self.popped.wrapping_add(1);
+        Some(val)
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        self.count += 1;
+        self.pushed = self.pushed.wrapping_add(1);
+        #[cfg(not(feature = "safe"))]
+        unsafe { self.inner.put_unchecked(val) };
+        #[cfg(feature = "safe")]
+        self.inner.put(val);
+    }
+
+    /// Add element the buffer, evicting the oldest one if it's full
+    ///
+    /// Returns the evicted element.
+    pub fn force_put(&mut self, val: T) -> Option<T> {
+        let evicted = if self.is_full() {
+            self.count -= 1;
+            self.inner.get()
+        } else {
+            None
+        };
+        self.put(val);
+        evicted
+    }
+
+    /// See `CBuf::put_slice`
+    pub fn put_slice(&mut self, src: &[T]) -> usize {
+        let n = self.inner.put_slice(src);
+        self.count += n;
+        self.pushed = self.pushed.wrapping_add(n as u64);
+        n
+    }
+
+    /// See `CBuf::pop_into`
+    pub fn pop_into(&mut self, dst: &mut [T]) -> usize {
+        let n = self.inner.pop_into(dst);
+        self.count -= n;
+        self.popped = self.popped.wrapping_add(n as u64);
+        n
+    }
+
+    /// See `CBuf::discard`
+    pub fn discard(&mut self, n: usize) -> usize {
+        let n = self.inner.discard(n);
+        self.count -= n;
+        n
+    }
+
+    /// Remove all elements
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear();
+        self.count = 0;
+    }
+}
+
+impl<'a, T: Clone> RingProducer<T> for CountedCBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        CountedCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        CountedCBuf::put(self, val)
+    }
+}
+
+impl<'a, T: Clone> RingConsumer<T> for CountedCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        CountedCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        CountedCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counted() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CountedCBuf::new(buf);
+
+        assert_eq!(cbuf.put_slice(&[1, 2, 3]), 3);
+        assert_eq!((cbuf.count(), cbuf.remaining_capacity()), (3, 1));
+        assert_eq!(cbuf.get(), Some(1));
+        cbuf.put(4);
+        cbuf.put(5);
+        cbuf.put(6); // will have no effect
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.count(), cbuf.inner.count());
+
+        let dst = &mut [0u8; 3];
+        assert_eq!(cbuf.pop_into(dst), 3);
+        assert_eq!(dst, &[2, 3, 4]);
+        assert_eq!(cbuf.count(), 1);
+        cbuf.clear();
+        assert!(cbuf.is_empty());
+        assert_eq!(cbuf.get(), None);
+    }
+
+    #[test]
+    fn counted_totals() {
+        let buf = &mut [0u8; 2];
+        let mut cbuf = CountedCBuf::new(buf);
+
+        cbuf.put_slice(&[1, 2, 3]);
+        assert_eq!(cbuf.get(), Some(1));
+        assert_eq!(cbuf.force_put(4), None);
+        assert_eq!(cbuf.force_put(5), Some(2));
+        assert_eq!(cbuf.pop_into(&mut [0; 1]), 1);
+        assert_eq!((cbuf.pushed_count(), cbuf.popped_count(), cbuf.count()), (4, 2, 1));
+
+        cbuf.put(6);
+        assert_eq!(cbuf.discard(5), 2);
+        let lost = cbuf.pushed_count() - cbuf.popped_count() - cbuf.count() as u64;
+        assert_eq!(lost, 3);
+    }
+}
@@ -0,0 +1,179 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer guarded by `critical_section`
+
+use critical_section;
+
+use ring::CBuf;
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer shareable between interrupt handlers and thread context
+///
+/// Every operation runs inside `critical_section::with`, so it works on
+/// single-core MCUs that lack atomic CAS instructions (Cortex-M0, AVR).
+/// Requires `critical-section` feature.
+pub struct CriticalCBuf<'a, T: 'a> {
+    inner: critical_section::Mutex<core::cell::RefCell<CBuf<'a, T>>>,
+}
+
+impl<'a, T: Clone> CriticalCBuf<'a, T> {
+    /// Create new CriticalCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> CriticalCBuf<'a, T> {
+        CriticalCBuf {
+            inner: critical_section::Mutex::new(core::cell::RefCell::new(CBuf::new(buf))),
+        }
+    }
+
+    /// Access the underlying CBuf inside one critical section
+    ///
+    /// Keep `f` short: interrupts are masked while it runs.
+    ///
+    /// # Panics
+    ///
+    /// If `f` accesses this CriticalCBuf again (eg. a nested `with` or
+    /// `put`), on the already borrowed inner `RefCell`.
+    #[inline]
+    pub fn with<R, F: FnOnce(&mut CBuf<'a, T>) -> R>(&self, f: F) -> R {
+        critical_section::with(|cs| f(&mut self.inner.borrow_ref_mut(cs)))
+    }
+
+    /// Access the underlying CBuf without entering a critical section
+    ///
+    /// For call sites already running with interrupts masked (eg. inside
+    /// `critical_section::with` or an interrupt handler of the highest
+    /// priority touching this buffer), saving the cost of masking again.
+    ///
+    /// # Safety
+    ///
+    /// Nothing else may access this CriticalCBuf until `f` returns: no
+    /// interrupt handler or other core may touch the buffer for as long
+    /// as the `&mut CBuf` borrow lives, and `f` must not call back into
+    /// this buffer. Violating it is a data race; a nested access from `f`
+    /// panics on the inner `RefCell`.
+    #[inline]
+    pub unsafe fn with_assume_exclusive<R, F: FnOnce(&mut CBuf<'a, T>) -> R>(&self, f: F) -> R {
+        let cs = critical_section::CriticalSection::new();
+        f(&mut self.inner.borrow_ref_mut(cs))
+    }
+
+    /// `get` without entering a critical section
+    ///
+    /// # Safety
+    ///
+    /// No interrupt handler or other core may touch this CriticalCBuf
+    /// until the call returns, as for `with_assume_exclusive`.
+    #[inline]
+    pub unsafe fn get_assume_exclusive(&self) -> Option<T> {
+        self.with_assume_exclusive(|cbuf| cbuf.get())
+    }
+
+    /// `put` without entering a critical section
+    ///
+    /// # Safety
+    ///
+    /// No interrupt handler or other core may touch this CriticalCBuf
+    /// until the call returns, as for `with_assume_exclusive`.
+    #[inline]
+    pub unsafe fn put_assume_exclusive(&self, val: T) {
+        self.with_assume_exclusive(|cbuf| cbuf.put(val))
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.with(|cbuf| cbuf.is_full())
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.with(|cbuf| cbuf.is_empty())
+    }
+
+    /// Remove one element from the CBuf
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&self) -> Option<T> {
+        self.with(|cbuf| cbuf.get())
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&self, val: T) {
+        self.with(|cbuf| cbuf.put(val))
+    }
+}
+
+impl<'a, T: Clone> RingProducer<T> for CriticalCBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        CriticalCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        CriticalCBuf::put(self, val)
+    }
+}
+
+impl<'a, T: Clone> RingConsumer<T> for CriticalCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        CriticalCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        CriticalCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn critical() {
+        let buf = &mut [0u8; 2];
+        let cbuf = CriticalCBuf::new(buf);
+
+        assert!(cbuf.is_empty());
+        cbuf.put(1);
+        cbuf.with(|cbuf| cbuf.put(2));
+        cbuf.put(42); // will have no effect
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.get().unwrap(), 1);
+        assert_eq!(cbuf.get().unwrap(), 2);
+        assert!(cbuf.get().is_none());
+    }
+
+    #[test]
+    #[should_panic]
+    fn nested_with() {
+        let buf = &mut [0u8; 2];
+        let cbuf = CriticalCBuf::new(buf);
+        cbuf.with(|_| cbuf.put(1));
+    }
+
+    #[test]
+    fn assume_exclusive() {
+        let buf = &mut [0u8; 2];
+        let cbuf = CriticalCBuf::new(buf);
+
+        critical_section::with(|_| unsafe {
+            cbuf.put_assume_exclusive(1);
+            cbuf.put_assume_exclusive(2);
+            assert!(cbuf.with_assume_exclusive(|cbuf| cbuf.is_full()));
+            assert_eq!(cbuf.get_assume_exclusive(), Some(1));
+        });
+        assert_eq!(cbuf.get(), Some(2));
+    }
+}
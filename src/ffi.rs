@@ -0,0 +1,168 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! `extern "C"` byte Circular Buffer API for mixed C/Rust firmware
+//!
+//! C code declares the state with a matching layout and calls:
+//!
+//! ```c
+//! typedef struct { uint8_t *buf; size_t len; size_t head; size_t tail; } cbuf_t;
+//!
+//! bool cbuf_init(cbuf_t *cbuf, uint8_t *buf, size_t len);
+//! bool cbuf_put(cbuf_t *cbuf, uint8_t byte);
+//! bool cbuf_get(cbuf_t *cbuf, uint8_t *byte);
+//! size_t cbuf_len(const cbuf_t *cbuf);
+//! ```
+//!
+//! Both languages may use the same `cbuf_t`, but not concurrently: put
+//! and get are not synchronized.
+
+use super::{cbuf_next, CBUF_DATA_BIT};
+
+/// Byte Circular Buffer state shared with C as `cbuf_t`
+#[repr(C)]
+#[derive(Debug)]
+pub struct FfiCBuf {
+    buf: *mut u8,
+    len: usize,
+    head: usize,
+    tail: usize,
+}
+
+impl FfiCBuf {
+    /// Uninitialized state, to be set up by `cbuf_init`
+    pub const fn new() -> FfiCBuf {
+        FfiCBuf {
+            buf: core::ptr::null_mut(),
+            len: 0,
+            head: 0,
+            tail: 0,
+        }
+    }
+}
+
+impl Default for FfiCBuf {
+    fn default() -> FfiCBuf {
+        FfiCBuf::new()
+    }
+}
+
+/// Set up `cbuf` over `len` bytes at `buf`
+///
+/// Returns `false` if `cbuf` or `buf` is null, `cbuf` is misaligned,
+/// len == 0, or len is too big.
+///
+/// # Safety
+///
+/// `cbuf` must be valid for writes.
+/// `buf` must be valid for reads and writes of `len` bytes for as long
+/// as `cbuf` is used, and nothing else may access those bytes meanwhile
+/// except through the `cbuf_*` functions.
+#[no_mangle]
+pub unsafe extern "C" fn cbuf_init(cbuf: *mut FfiCBuf, buf: *mut u8, len: usize) -> bool {
+    if cbuf.is_null() || !cbuf.is_aligned() {
+        return false;
+    }
+    if buf.is_null() || len == 0 || len >= CBUF_DATA_BIT {
+        return false;
+    }
+    *cbuf = FfiCBuf {
+        buf,
+        len,
+        head: 0,
+        tail: 0,
+    };
+    true
+}
+
+/// Add `byte` to the buffer
+///
+/// Returns `false` and ignores the write if buffer is full.
+///
+/// # Safety
+///
+/// `cbuf` must be non-null, aligned and set up by `cbuf_init`, with the
+/// `buf` it was given still valid. No other call may use `cbuf` at the
+/// same time.
+#[no_mangle]
+pub unsafe extern "C" fn cbuf_put(cbuf: *mut FfiCBuf, byte: u8) -> bool {
+    let cbuf = &mut *cbuf;
+    if (cbuf.head ^ cbuf.tail) == CBUF_DATA_BIT {
+        return false;
+    }
+    cbuf.buf.add(cbuf.head & !CBUF_DATA_BIT).write(byte);
+    cbuf.head = cbuf_next(cbuf.head, cbuf.len);
+    true
+}
+
+/// Remove one byte into `*byte`
+///
+/// Returns `false` and leaves `*byte` alone if buffer is empty.
+///
+/// # Safety
+///
+/// `cbuf` must be non-null, aligned and set up by `cbuf_init`, with the
+/// `buf` it was given still valid. No other call may use `cbuf` at the
+/// same time. `byte` must be non-null, valid for writes and must not
+/// point into `buf`.
+#[no_mangle]
+pub unsafe extern "C" fn cbuf_get(cbuf: *mut FfiCBuf, byte: *mut u8) -> bool {
+    let cbuf = &mut *cbuf;
+    if cbuf.head == cbuf.tail {
+        return false;
+    }
+    *byte = cbuf.buf.add(cbuf.tail & !CBUF_DATA_BIT).read();
+    cbuf.tail = cbuf_next(cbuf.tail, cbuf.len);
+    true
+}
+
+/// Number of bytes in the buffer
+///
+/// # Safety
+///
+/// `cbuf` must be non-null, aligned and set up by `cbuf_init`, and no
+/// `cbuf_put`, `cbuf_get` or `cbuf_init` may modify it at the same time.
+#[no_mangle]
+pub unsafe extern "C" fn cbuf_len(cbuf: *const FfiCBuf) -> usize {
+    let cbuf = &*cbuf;
+    let (head, tail) = (cbuf.head & !CBUF_DATA_BIT, cbuf.tail & !CBUF_DATA_BIT);
+    if (cbuf.head ^ cbuf.tail) & CBUF_DATA_BIT == 0 {
+        head - tail
+    } else {
+        cbuf.len - tail + head
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ffi() {
+        let buf = &mut [0u8; 2];
+        let mut cbuf = FfiCBuf::new();
+        let mut byte = 0;
+        unsafe {
+            assert!(!cbuf_init(&mut cbuf, buf.as_mut_ptr(), 0));
+            assert!(!cbuf_init(&mut cbuf, core::ptr::null_mut(), 2));
+            assert!(!cbuf_init(core::ptr::null_mut(), buf.as_mut_ptr(), 2));
+            let misaligned = (&mut cbuf as *mut FfiCBuf as *mut u8).add(1) as *mut FfiCBuf;
+            assert!(!cbuf_init(misaligned, buf.as_mut_ptr(), 2));
+            assert!(cbuf_init(&mut cbuf, buf.as_mut_ptr(), buf.len()));
+            assert!(!cbuf_get(&mut cbuf, &mut byte));
+            assert!(cbuf_put(&mut cbuf, 1));
+            assert!(cbuf_put(&mut cbuf, 2));
+            assert!(!cbuf_put(&mut cbuf, 3));
+            assert_eq!(cbuf_len(&cbuf), 2);
+            assert!(cbuf_get(&mut cbuf, &mut byte));
+            assert_eq!(byte, 1);
+            assert!(cbuf_put(&mut cbuf, 4));
+            assert!(cbuf_get(&mut cbuf, &mut byte));
+            assert!(cbuf_get(&mut cbuf, &mut byte));
+            assert_eq!(byte, 4);
+            assert_eq!(cbuf_len(&cbuf), 0);
+        }
+    }
+}
@@ -0,0 +1,200 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer keeping the newest elements
+
+use core::fmt;
+
+use array::CBufArray;
+use ring::Iter;
+use traits::RingProducer;
+
+/// Circular Buffer of the last `N` elements put
+///
+/// `put` never fails: once full, every new element replaces the oldest
+/// one. Meant for "last N samples/events" records, where dropping the
+/// newest element, as `CBuf` does, loses the interesting part. Nothing is
+/// ever removed except by `clear`; read with `iter`, `oldest`, `newest`
+/// or by index, oldest first.
+///
+/// ```
+/// use cbuf::HistoryBuf;
+///
+/// let mut last: HistoryBuf<u32, 3> = Default::default();
+/// for sample in 1..=5 {
+///     last.put(sample);
+/// }
+/// assert!(last.iter().eq(&[3, 4, 5]));
+/// assert_eq!((last.oldest(), last.newest()), (Some(&3), Some(&5)));
+/// ```
+pub struct HistoryBuf<T, const N: usize> {
+    inner: CBufArray<T, N>,
+}
+
+impl<T: Clone, const N: usize> HistoryBuf<T, N> {
+    /// Create new HistoryBuf using `buf` as storage
+    ///
+    /// panics like `CBufArray::new`
+    pub const fn new(buf: [T; N]) -> HistoryBuf<T, N> {
+        HistoryBuf { inner: CBufArray::new(buf) }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        N
+    }
+
+    /// Number of elements recorded, at most `N`
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Has `N` elements been recorded yet?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Record element, replacing the oldest one if full
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.inner.is_full() {
+            self.inner.get();
+        }
+        self.inner.put(val)
+    }
+
+    /// Oldest element still recorded
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn oldest(&self) -> Option<&T> {
+        self.inner.get_at(0)
+    }
+
+    /// Newest element, last put
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn newest(&self) -> Option<&T> {
+        self.inner.get_at(self.count().checked_sub(1)?)
+    }
+
+    /// See `CBuf::get_at`
+    #[inline]
+    pub fn get_at(&self, n: usize) -> Option<&T> {
+        self.inner.get_at(n)
+    }
+
+    /// Iterate over elements, oldest first
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
+
+    /// See `CBuf::last_n`
+    #[inline]
+    pub fn last_n(&self, n: usize) -> Iter<'_, T> {
+        self.inner.last_n(n)
+    }
+
+    /// Elements as two slices, oldest first
+    #[inline]
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.inner.as_slices()
+    }
+
+    /// Forget all elements
+    #[inline]
+    pub fn clear(&mut self) {
+        self.inner.clear()
+    }
+}
+
+impl<T: Clone + Default, const N: usize> Default for HistoryBuf<T, N> {
+    fn default() -> HistoryBuf<T, N> {
+        HistoryBuf { inner: CBufArray::default() }
+    }
+}
+
+/// Shows the recorded elements oldest first, like `CBuf`
+impl<T: Clone + fmt::Debug, const N: usize> fmt::Debug for HistoryBuf<T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HistoryBuf")
+            .field("contents", &::DebugList(self.iter()))
+            .field("count", &self.count())
+            .field("capacity", &N)
+            .finish()
+    }
+}
+
+impl<'r, T: Clone, const N: usize> IntoIterator for &'r HistoryBuf<T, N> {
+    type Item = &'r T;
+    type IntoIter = Iter<'r, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'r, T> {
+        self.iter()
+    }
+}
+
+/// `n`-th oldest element, panics if there are no more than `n` elements
+impl<T: Clone, const N: usize> core::ops::Index<usize> for HistoryBuf<T, N> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, n: usize) -> &T {
+        &self.inner[n]
+    }
+}
+
+impl<T: Clone, const N: usize> RingProducer<T> for HistoryBuf<T, N> {
+    /// Always `false`: `put` makes room itself
+    #[inline]
+    fn is_full(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        HistoryBuf::put(self, val)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn history() {
+        let mut hist = HistoryBuf::<u8, 3>::new([0; 3]);
+        assert_eq!((hist.oldest(), hist.newest()), (None, None));
+        hist.put(1);
+        hist.put(2);
+        assert!(!hist.is_full());
+        assert_eq!((hist.oldest(), hist.newest(), hist[1]), (Some(&1), Some(&2), 2));
+
+        for val in 3..8 {
+            hist.put(val);
+        }
+        assert!(hist.iter().eq(&[5, 6, 7]));
+        assert!(hist.iter().rev().eq(&[7, 6, 5]));
+        assert!(hist.last_n(2).eq(&[6, 7]));
+        assert_eq!((hist.count(), hist.get_at(0), hist.newest()), (3, Some(&5), Some(&7)));
+        assert_eq!(std::format!("{:?}", hist), "HistoryBuf { contents: [5, 6, 7], count: 3, capacity: 3 }");
+
+        hist.clear();
+        assert!(hist.is_empty());
+    }
+}
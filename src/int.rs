@@ -0,0 +1,166 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Byte-order-aware integer access on byte Circular Buffers
+//!
+//! Integers may straddle the end of buf; they are reassembled without
+//! the caller having to linearize anything.
+
+use ring::{CBuf, NoSpace};
+
+/// Byte order of integers in the buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Least significant byte first
+    Little,
+    /// Most significant byte first (network order)
+    Big,
+}
+
+/// Integer types readable and writable with `read_int`/`write_int`
+pub trait RingInt: Sized {
+    /// Size in bytes
+    const SIZE: usize;
+
+    /// Convert from `SIZE` bytes
+    fn from_bytes(bytes: &[u8], order: ByteOrder) -> Self;
+
+    /// Convert into `SIZE` bytes
+    fn to_bytes(self, bytes: &mut [u8], order: ByteOrder);
+}
+
+macro_rules! ring_int {
+    ($($t:ty),*) => {$(
+        impl RingInt for $t {
+            const SIZE: usize = core::mem::size_of::<$t>();
+
+            #[inline]
+            fn from_bytes(bytes: &[u8], order: ByteOrder) -> $t {
+                let mut arr = [0u8; core::mem::size_of::<$t>()];
+                arr.copy_from_slice(bytes);
+                match order {
+                    ByteOrder::Little => <$t>::from_le_bytes(arr),
+                    ByteOrder::Big => <$t>::from_be_bytes(arr),
+                }
+            }
+
+            #[inline]
+            fn to_bytes(self, bytes: &mut [u8], order: ByteOrder) {
+                bytes.copy_from_slice(&match order {
+                    ByteOrder::Little => self.to_le_bytes(),
+                    ByteOrder::Big => self.to_be_bytes(),
+                })
+            }
+        }
+    )*}
+}
+
+ring_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+/// Longest `RingInt`
+const MAX_SIZE: usize = 16;
+
+impl<'a> CBuf<'a, u8> {
+    /// Remove an integer
+    ///
+    /// Returns `None` and removes nothing if fewer than `T::SIZE` bytes
+    /// are stored.
+    pub fn read_int<T: RingInt>(&mut self, order: ByteOrder) -> Option<T> {
+        if self.count() < T::SIZE {
+            return None;
+        }
+        let bytes = &mut [0u8; MAX_SIZE][..T::SIZE];
+        self.pop_into(bytes);
+        Some(T::from_bytes(bytes, order))
+    }
+
+    /// Add an integer
+    ///
+    /// All or nothing: on error the buffer is left unchanged.
+    pub fn write_int<T: RingInt>(&mut self, val: T, order: ByteOrder) -> Result<(), NoSpace> {
+        if self.len() - self.count() < T::SIZE {
+            return Err(NoSpace);
+        }
+        let bytes = &mut [0u8; MAX_SIZE][..T::SIZE];
+        val.to_bytes(bytes, order);
+        self.put_slice(bytes);
+        Ok(())
+    }
+
+    /// See `read_int`
+    #[inline]
+    pub fn read_u16_le(&mut self) -> Option<u16> {
+        self.read_int(ByteOrder::Little)
+    }
+
+    /// See `read_int`
+    #[inline]
+    pub fn read_u16_be(&mut self) -> Option<u16> {
+        self.read_int(ByteOrder::Big)
+    }
+
+    /// See `read_int`
+    #[inline]
+    pub fn read_u32_le(&mut self) -> Option<u32> {
+        self.read_int(ByteOrder::Little)
+    }
+
+    /// See `read_int`
+    #[inline]
+    pub fn read_u32_be(&mut self) -> Option<u32> {
+        self.read_int(ByteOrder::Big)
+    }
+
+    /// See `write_int`
+    #[inline]
+    pub fn write_u16_le(&mut self, val: u16) -> Result<(), NoSpace> {
+        self.write_int(val, ByteOrder::Little)
+    }
+
+    /// See `write_int`
+    #[inline]
+    pub fn write_u16_be(&mut self, val: u16) -> Result<(), NoSpace> {
+        self.write_int(val, ByteOrder::Big)
+    }
+
+    /// See `write_int`
+    #[inline]
+    pub fn write_u32_le(&mut self, val: u32) -> Result<(), NoSpace> {
+        self.write_int(val, ByteOrder::Little)
+    }
+
+    /// See `write_int`
+    #[inline]
+    pub fn write_u32_be(&mut self, val: u32) -> Result<(), NoSpace> {
+        self.write_int(val, ByteOrder::Big)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ints() {
+        let buf = &mut [0u8; 6];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[0; 5]);
+        cbuf.pop_into(&mut [0; 5]);
+
+        // straddles the end of buf
+        cbuf.write_u32_be(0x01020304).unwrap();
+        assert_eq!(cbuf.as_slices(), (&[1][..], &[2, 3, 4][..]));
+        assert_eq!(cbuf.write_u32_le(0), Err(NoSpace));
+        cbuf.write_u16_le(0xbeef).unwrap();
+        assert_eq!(cbuf.read_u16_le(), Some(0x0201));
+        assert_eq!(cbuf.read_u16_be(), Some(0x0304));
+        assert_eq!(cbuf.read_u32_le(), None);
+        assert_eq!(cbuf.count(), 2);
+        assert_eq!(cbuf.read_int::<i16>(ByteOrder::Little), Some(0xbeefu16 as i16));
+
+        cbuf.write_int(-2i32, ByteOrder::Little).unwrap();
+        assert_eq!(cbuf.read_u32_be(), Some(0xfeffffff));
+    }
+}
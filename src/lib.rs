@@ -9,377 +9,445 @@
 //!
 //! Uses only `core` so can be used in `#[no_std]` projects by using
 //! `no_std` feature.
+//!
+//! # Modules and features
+//!
+//! Each subsystem lives in its own module behind an additive feature, so
+//! only the needed parts get compiled. All public types are also
+//! re-exported at the crate root.
+//!
+//! | Module     | Feature            | Default | Requires                      |
+//! |------------|--------------------|---------|-------------------------------|
+//! | `ring`     | -                  | yes     | `core` only                   |
+//! | `rom`      | -                  | yes     | `core` only                   |
+//! | `const_ring` | -                | yes     | `core` only                   |
+//! | `aligned`  | -                  | yes     | `core` only                   |
+//! | `array`    | -                  | yes     | `core` only                   |
+//! | `builder`  | -                  | yes     | `core` only                   |
+//! | `history`  | -                  | yes     | `core` only                   |
+//! | `pow2`     | -                  | yes     | `core` only                   |
+//! | `int`      | -                  | yes     | `core` only                   |
+//! | `traits`   | -                  | yes     | `core` only                   |
+//! | `pressure` | -                  | yes     | `core` only                   |
+//! | `swap`     | `swap`             | yes     | atomic CAS                    |
+//! | `spsc`     | `spsc`             | yes     | atomic load/store             |
+//! | `mpmc`     | `mpmc`             | yes     | atomic CAS                    |
+//! | `critical` | `critical-section` | no      | `critical-section` impl       |
+//! | `blocking` | `std`              | no      | `std`                         |
+//! | `channel`  | `channel`          | no      | `spsc`                        |
+//! | `parity`   | `parity`           | no      | twice the RAM                 |
+//! | `mirrored` | `mirrored`         | no      | `std`, Linux x86_64/aarch64   |
+//! | `cobs`     | `cobs`             | no      | `core` only                   |
+//! | `msg`      | `msg`              | no      | `core` only                   |
+//! | `weighted` | `weighted`         | no      | `core` only                   |
+//! | `budget`   | `budget`           | no      | atomic CAS                    |
+//! | `pingpong` | `pingpong`         | no      | `core` only                   |
+//! | `volatile` | `volatile`         | no      | `core` only                   |
+//! | `bits`     | `bits`             | no      | `core` only                   |
+//! | `ffi`      | `ffi`              | no      | `core` only                   |
+//! | `serial`   | `serial`           | no      | `spsc`, `embedded-hal-nb`     |
+//! | `counted`  | `counted`          | no      | `core` only                   |
+//! | `broadcast` | `broadcast`       | no      | `core` only                   |
+//! | `local`    | `local`            | no      | `core` only                   |
+//! | `watermark` | `watermark`       | no      | `core` only                   |
+//! | `timed`    | `timed`            | no      | `core` only                   |
+//!
+//! Other features:
+//!
+//! * `async` (implies `spsc`) - waker-based `poll_*` methods and futures
+//!   on `spsc` halves,
+//! * `stream` (implies `async`) - `futures_core::Stream` for `spsc::Consumer`,
+//! * `sink` (implies `async`) - `futures_sink::Sink` for `spsc::Producer`,
+//! * `scrub` - debug aid overwriting every slot freed by `get`, `clear`,
+//!   `drain_into`, `ReadCursor::commit` and the other consuming methods
+//!   (except `lines`) with `0xDE` bytes, so code reading already consumed
+//!   slots (eg. DMA pointed at a stale region) sees obvious garbage. Only
+//!   primitive integer and float elements are scrubbed; slots of other
+//!   types are left alone. `CBuf::rollback` can't bring back scrubbed
+//!   elements, so it panics if any were removed since the checkpoint.
+//! * `std` - besides `blocking`, implements `std::io::Read`,
+//!   `std::io::BufRead` and `std::io::Write` for `CBuf<u8>`,
+//! * `embedded-io` - implements `embedded_io` `Read`, `ReadReady`,
+//!   `Write` and `WriteReady` for `CBuf<u8>`; reading an empty or writing
+//!   a full buffer fails with `WouldBlock`,
+//! * `simd` - scans for delimiters in `CBuf<u8>` (`find`, `read_until`,
+//!   `lines`) 16 bytes at a time with `core::simd`,
+//! * `bytes` - implements `bytes::Buf` and `bytes::BufMut` for `CBuf<u8>`,
+//! * `serde` - implements `Serialize` and `Deserialize` for `CBufArray`
+//!   and `ConstRing` as a sequence of the queued elements, oldest first,
+//! * `defmt` - implements `defmt::Format` for `CBuf`, `CBufArray`,
+//!   `ConstRing` and the error types; rings show count and capacity, plus
+//!   their contents when holding at most 8 elements,
+//! * `stats` - counts elements `CBuf::put` loses to a full buffer,
+//!   queryable with `CBuf::stats` and `CBuf::snapshot_and_reset`; off by
+//!   default to keep `CBuf` small,
+//! * `instrument` - tracks `CBuf::high_water_mark` on every write and adds
+//!   `OccupancyHistogram`, sampled with `CBuf::record_occupancy`, for
+//!   sizing buffers from field data,
+//! * `nb` - `nb_put` and `nb_get` on every buffer through
+//!   `RingProducerExt` and `RingConsumerExt`, returning `nb::Result` for
+//!   `block!` and embedded-hal style driver loops,
+//! * `safe` - forbids unsafe code in the crate: `CBuf` checks slot bounds
+//!   (which never fail) instead of skipping the checks, and the `unsafe fn`
+//!   escape hatches (`put_unchecked`, `set_head_index`, ...) become safe
+//!   fns with checked bodies, so existing callers still compile. Features
+//!   that can't work without unsafe code (`swap`, `spsc`, `mpmc` and
+//!   everything built on them, `critical-section`, `mirrored`,
+//!   `volatile`, `bits`, `ffi`, `scrub`, `bytes`) are a compile error
+//!   together with it, so it needs `default-features = false`,
+//! * `cache_padded` (default) - keeps atomic indices of `spsc` and `mpmc`
+//!   on separate cache lines; disable to save RAM on cache-less MCUs.
+//!
+//! # Timing
+//!
+//! `put`, `get`, `is_full` and `is_empty` of `CBuf`, `CBufControl` and
+//! `spsc` halves run in constant time: no loops and no division (index
+//! wrap-around is one compare and subtract), so they are fine to call from
+//! interrupt handlers; there are no separate `put_isr`/`get_isr`. Cycle
+//! counts depend on target, `T`, flash wait states and caches, so no
+//! absolute bound is promised. It couldn't be tested in CI either: QEMU
+//! doesn't model Cortex-M cycle timing, so measure on the target.
+//!
+//! # Panic freedom
+//!
+//! For builds that must not contain panic paths, this subset of `CBuf` is
+//! free of them when `T::clone` is: `try_new`, `try_put`, `put_slice`,
+//! `get`, `peek`, `front`, `back`, `get_at`, `pop_into`, `discard`,
+//! `as_slices`, `count`, `remaining_capacity`, `is_full`, `is_empty` and
+//! `clear`. `examples/no_panic.rs` checks it at link time in release
+//! builds (`RELEASE=true make no_panic`).
 #![no_std]
-#![feature(const_fn)]
+#![cfg_attr(feature = "safe", forbid(unsafe_code))]
 #![feature(test)]
+#![cfg_attr(all(feature = "simd", not(feature = "no_std")), feature(portable_simd))]
 
-use core::option::Option::{self, Some, None};
-use core::marker::PhantomData;
+#[cfg(all(feature = "safe",
+          any(feature = "swap", feature = "spsc", feature = "mpmc",
+              feature = "critical-section", feature = "mirrored", feature = "volatile",
+              feature = "bits", feature = "ffi", feature = "scrub", feature = "bytes")))]
+compile_error!("`safe` forbids unsafe code, so it can't be combined with swap, spsc, mpmc, \
+                critical-section, mirrored, volatile, bits, ffi, scrub or bytes; \
+                use default-features = false");
 
 extern crate test as test;
-
-const CBUF_DATA_BIT: usize = !((usize::max_value() << 1) >> 1);
-
-/// Circular Buffer
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+#[cfg(feature = "critical-section")]
+extern crate critical_section;
+#[cfg(feature = "stream")]
+extern crate futures_core;
+#[cfg(feature = "sink")]
+extern crate futures_sink;
+#[cfg(feature = "embedded-io")]
+extern crate embedded_io;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "embedded-hal-nb")]
+extern crate embedded_hal_nb;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(feature = "defmt")]
+extern crate defmt;
+#[cfg(feature = "nb")]
+extern crate nb;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
+
+pub mod aligned;
+pub mod array;
+#[cfg(not(feature = "no_std"))]
+pub mod builder;
+pub mod const_ring;
+pub mod history;
+#[cfg(not(feature = "no_std"))]
+pub mod int;
+pub mod pow2;
+pub mod pressure;
+pub mod ring;
+pub mod rom;
+pub mod traits;
+#[cfg(all(feature = "swap", not(feature = "no_std")))]
+pub mod swap;
+#[cfg(feature = "spsc")]
+pub mod spsc;
+#[cfg(feature = "mpmc")]
+pub mod mpmc;
+#[cfg(all(feature = "critical-section", not(feature = "no_std")))]
+pub mod critical;
+#[cfg(all(feature = "std", not(feature = "no_std")))]
+pub mod blocking;
+#[cfg(feature = "channel")]
+pub mod channel;
+#[cfg(all(feature = "parity", not(feature = "no_std")))]
+pub mod parity;
+#[cfg(all(feature = "mirrored", target_os = "linux",
+          any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub mod mirrored;
+#[cfg(all(feature = "cobs", not(feature = "no_std")))]
+pub mod cobs;
+#[cfg(all(feature = "msg", not(feature = "no_std")))]
+pub mod msg;
+#[cfg(all(feature = "weighted", not(feature = "no_std")))]
+pub mod weighted;
+#[cfg(all(feature = "budget", not(feature = "no_std")))]
+pub mod budget;
+#[cfg(all(feature = "pingpong", not(feature = "no_std")))]
+pub mod pingpong;
+#[cfg(feature = "volatile")]
+pub mod volatile;
+#[cfg(all(feature = "bits", not(feature = "no_std")))]
+pub mod bits;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "serial")]
+pub mod serial;
+#[cfg(all(feature = "counted", not(feature = "no_std")))]
+pub mod counted;
+#[cfg(feature = "broadcast")]
+pub mod broadcast;
+#[cfg(all(feature = "local", not(feature = "no_std")))]
+pub mod local;
+#[cfg(all(feature = "watermark", not(feature = "no_std")))]
+pub mod watermark;
+#[cfg(all(feature = "timed", not(feature = "no_std")))]
+pub mod timed;
+#[cfg(feature = "async")]
+mod waker;
+
+pub use aligned::{Align128, Align16, Align32, Align64, Align8};
+pub use array::CBufArray;
+#[cfg(not(feature = "no_std"))]
+pub use builder::CBufBuilder;
+pub use history::HistoryBuf;
+pub use const_ring::ConstRing;
+#[cfg(not(feature = "no_std"))]
+pub use int::{ByteOrder, RingInt};
+pub use pow2::Pow2CBuf;
+pub use pressure::{AdaptiveBatch, BatchPolicy, Pressure, PressureGauge};
+#[cfg(feature = "instrument")]
+pub use pressure::OccupancyHistogram;
+pub use ring::{CBuf, CBufControl, ChangeSet, Checkpoint, Corruption, DropOrder, Iter, NoSpace,
+               OverflowPolicy, RingIndex};
+#[cfg(not(feature = "no_std"))]
+pub use ring::{Drain, Lines, ReadCursor, WriteRegions};
+#[cfg(feature = "stats")]
+pub use ring::OverflowStats;
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+pub use ring::WouldBlock;
+pub use rom::ConstCBuf;
+pub use traits::{ByteSink, ByteSource, GetIter, RingBuffer, RingConsumer, RingConsumerExt,
+                 RingProducer, RingProducerExt};
+#[cfg(feature = "async")]
+pub use traits::{AsyncRingConsumer, AsyncRingProducer};
+#[cfg(all(feature = "swap", not(feature = "no_std")))]
+pub use swap::{SwapConsumer, SwapPair, SwapProducer};
+#[cfg(feature = "spsc")]
+pub use spsc::{BatchedConsumer, BatchedProducer, Consumer, Producer, SpscCBuf};
+#[cfg(feature = "async")]
+pub use spsc::{GetFuture, PutFuture};
+#[cfg(feature = "mpmc")]
+pub use mpmc::MpmcCBuf;
+#[cfg(all(feature = "critical-section", not(feature = "no_std")))]
+pub use critical::CriticalCBuf;
+#[cfg(all(feature = "std", not(feature = "no_std")))]
+pub use blocking::BlockingCBuf;
+#[cfg(feature = "channel")]
+pub use channel::{Channel, Receiver, Sender};
+#[cfg(all(feature = "parity", not(feature = "no_std")))]
+pub use parity::ParityCBuf;
+#[cfg(all(feature = "mirrored", target_os = "linux",
+          any(target_arch = "x86_64", target_arch = "aarch64")))]
+pub use mirrored::MirroredCBuf;
+#[cfg(all(feature = "msg", not(feature = "no_std")))]
+pub use msg::MsgCBuf;
+#[cfg(all(feature = "weighted", not(feature = "no_std")))]
+pub use weighted::{Weight, WeightedCBuf};
+#[cfg(all(feature = "budget", not(feature = "no_std")))]
+pub use budget::{BudgetShare, BudgetedCBuf, MemoryBudget};
+#[cfg(all(feature = "pingpong", not(feature = "no_std")))]
+pub use pingpong::PingPong;
+#[cfg(feature = "volatile")]
+pub use volatile::VolatileCBuf;
+#[cfg(all(feature = "bits", not(feature = "no_std")))]
+pub use bits::{BitRing, PackedRing};
+#[cfg(feature = "ffi")]
+pub use ffi::FfiCBuf;
+#[cfg(feature = "serial")]
+pub use serial::{SerialRxQueue, SerialTxQueue};
+#[cfg(all(feature = "counted", not(feature = "no_std")))]
+pub use counted::CountedCBuf;
+#[cfg(feature = "broadcast")]
+pub use broadcast::{BroadcastCBuf, Lagged, Reader, Readers, Writer};
+#[cfg(all(feature = "broadcast", not(feature = "safe")))]
+pub use broadcast::Lease;
+#[cfg(all(feature = "local", not(feature = "no_std")))]
+pub use local::{LocalCBuf, LocalReader, LocalWriter};
+#[cfg(all(feature = "watermark", not(feature = "no_std")))]
+pub use watermark::{Watermark, WatermarkCBuf};
+#[cfg(all(feature = "timed", not(feature = "no_std")))]
+pub use timed::{TimedCBuf, TimedIter};
+
+/// Lap tag of head and tail indexes
 ///
-/// Turns a slice into a Circular buffer with head and tail indexes.
-#[derive(Debug)]
-pub struct CBuf<'a, T: 'a> {
-    buf: &'a mut [T],
-    ctrl: CBufControl<T>,
+/// Indexes hold the slot number plus this bit, flipped on every lap, so
+/// wrapping is a compare and subtract for any length. The price is half
+/// the index range: `MAX_CAPACITY` is 32767 on 16-bit targets, and
+/// `CBufControl` with `u16` or `u8` indexes holds at most 32767 or 127
+/// elements. Free-running counters would keep the whole range but need a
+/// modulo (a division) unless the length is a power of two; that case is
+/// served by `Pow2CBuf`.
+const CBUF_DATA_BIT: usize = !((usize::MAX << 1) >> 1);
+
+/// Advance head or tail index by one in a buffer of length `len`
+#[inline]
+const fn cbuf_next(idx: usize, len: usize) -> usize {
+    let idx = idx + 1;
+    if (idx & !CBUF_DATA_BIT) >= len {
+        (idx - len) ^ CBUF_DATA_BIT
+    } else {
+        idx
+    }
 }
 
-/// Circular Buffer Control
+/// Largest buffer length usable with `usize` indexes
 ///
-/// Implements the actual logic of Circular Buffer, but requires passing &[T]
-/// to `get` and `put`.
-#[derive(Debug)]
-pub struct CBufControl<T> {
-    head: usize,
-    tail: usize,
-    phantom: PhantomData<T>,
-}
+/// One less than the tag bit: 32767 on 16-bit targets (MSP430, AVR).
+/// Constructors panic above it; see `CBuf::try_new` for a checked one.
+pub const MAX_CAPACITY: usize = CBUF_DATA_BIT - 1;
 
+/// Advance head or tail index by `n <= len` in a buffer of length `len`
 #[cfg(not(feature = "no_std"))]
-impl<'a, T: Clone> CBuf<'a, T>
-{
-    /// Create new CBuf
-    ///
-    /// Length (not capacity) will be used to store elements
-    /// in the circular buffer.
-    ///
-    /// panics if buf.len() == 0
-    pub fn new(buf: &'a mut [T]) -> CBuf<T> {
-        debug_assert!(buf.len() < CBUF_DATA_BIT);
-        if buf.len() == 0 {
-            panic!("len==0")
-        }
-
-        CBuf {
-            buf: buf,
-            ctrl: CBufControl::new(),
-        }
-    }
-
-    /// get the buffer length
-    #[inline]
-    pub fn len(&self) -> usize {
-        self.buf.len()
-    }
-
-    /// Returns an raw pointer to the cbuf's buffer
-    ///
-    /// The caller must ensure that the cbuf outlives the pointer this function
-    /// returns, or else it will end up pointing to garbage.
-    #[inline]
-    pub fn as_ptr(&self) -> *const T {
-        &self.buf[0] as *const T
-    }
-
-    /// Returns an unsafe mutable pointer to the cbuf's buffer.
-    ///
-    /// The caller must ensure that the cbuf outlives the pointer this function
-    /// returns, or else it will end up pointing to garbage.
-    #[inline]
-    pub fn as_mut_ptr(&mut self) -> *mut T {
-        &mut self.buf[0] as *mut T
-    }
-
-    /// Is buffer full?
-    #[inline]
-    pub fn is_full(&self) -> bool {
-        self.ctrl.is_full()
-    }
-
-    /// Is buffer empty?
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.ctrl.is_empty()
-    }
-
-
-    /// Peek next element from the CBuf without removing it
-    ///
-    /// Returns `None` if buffer is empty.
-    #[inline]
-    pub fn peek(&mut self) -> Option<&T> {
-        self.ctrl.peek(self.buf)
-    }
-
-    /// Peek next element from the CBuf without removing it
-    ///
-    /// unsafe: if the buffer is empty, undefined data will be
-    /// returned.
-    #[inline]
-    pub unsafe fn peek_unchecked(&mut self) -> &T {
-        self.ctrl.peek_unchecked(self.buf)
-    }
-
-
-    /// Remove one element from the CBuf
-    ///
-    /// Returns `None` if buffer is empty.
-    #[inline]
-    pub fn get(&mut self) -> Option<T> {
-        self.ctrl.get(self.buf)
-    }
-
-    /// Remove one element from the CBuf
-    ///
-    /// unsafe: Makes the buffer misbehave if it's empty.
-    #[inline]
-    pub unsafe fn get_unchecked(&mut self) -> T {
-        self.ctrl.get_unchecked(self.buf)
-    }
-
-    /// Add element the buffer
-    ///
-    /// Ignores the write if buffer is full.
-    #[inline]
-    pub fn put(&mut self, val: T) {
-        self.ctrl.put(self.buf, val)
-    }
-
-    /// Add element the buffer
-    ///
-    /// unsafe: Makes the buffer misbehave if it's full.
-    #[inline]
-    pub unsafe fn put_unchecked(&mut self, val: T) {
-        self.ctrl.put_unchecked(self.buf, val)
+#[inline]
+fn cbuf_advance(idx: usize, n: usize, len: usize) -> usize {
+    // Add to the slot bits only: the whole index plus `n` overflows for
+    // lengths above half of `MAX_CAPACITY`
+    let slot = (idx & !CBUF_DATA_BIT) + n;
+    if slot >= len {
+        (slot - len) | ((idx & CBUF_DATA_BIT) ^ CBUF_DATA_BIT)
+    } else {
+        slot | (idx & CBUF_DATA_BIT)
     }
 }
 
-impl<T: Clone> CBufControl<T> {
-    pub fn new() -> CBufControl<T> {
-        CBufControl {
-            tail: 0,
-            head: 0,
-            phantom: PhantomData,
-        }
-    }
-
-    /// See corresponding method of CBuf
-    #[inline]
-    pub fn is_empty(&self) -> bool {
-        self.head == self.tail
-    }
-
-    /// See corresponding method of CBuf
-    #[inline]
-    pub fn is_full(&self) -> bool {
-        (self.head ^ self.tail) == CBUF_DATA_BIT
-    }
+/// Rings holding more elements than this log only count and capacity
+#[cfg(feature = "defmt")]
+const DEFMT_MAX_CONTENTS: usize = 8;
 
-    /// See corresponding method of CBuf
-    pub fn get(&mut self, buf: &[T]) -> Option<T> {
-        if self.is_empty() {
-            return None;
-        }
-        Some(self.get_unchecked(buf))
-    }
-
-    /// See corresponding method of CBuf
-    pub fn get_unchecked(&mut self, buf: &[T]) -> T {
-        let val = buf[self.tail & !CBUF_DATA_BIT].clone();
-
-        self.tail += 1;
-
-        if (self.tail & !CBUF_DATA_BIT) >= buf.len() {
-            self.tail = (self.tail - buf.len()) ^ CBUF_DATA_BIT;
-        }
-
-        val
-    }
-
-    /// See corresponding method of CBuf
-    pub fn peek<'a>(&mut self, buf: &'a [T]) -> Option<&'a T> {
-        if self.is_empty() {
-            return None;
-        }
-        Some(self.peek_unchecked(buf))
-    }
-
-    /// See corresponding method of CBuf
-    pub fn peek_unchecked<'a>(&mut self, buf: &'a [T]) -> &'a T {
-        &buf[self.tail & !CBUF_DATA_BIT]
-    }
-
-    /// See corresponding method of CBuf
-    pub fn put(&mut self, buf: &mut [T], val: T) {
-        if self.is_full() {
-            return;
-        }
-        self.put_unchecked(buf, val)
+/// `defmt::Format` body shared by the ring types
+#[cfg(feature = "defmt")]
+fn defmt_ring<I>(f: defmt::Formatter, name: &str, contents: I, count: usize, capacity: usize)
+    where I: Iterator,
+          I::Item: defmt::Format
+{
+    if count > DEFMT_MAX_CONTENTS {
+        defmt::write!(f, "{=str} {{ count: {=usize}, capacity: {=usize} }}", name, count, capacity);
+        return;
     }
-
-    /// See corresponding method of CBuf
-    pub fn put_unchecked(&mut self, buf: &mut [T], val: T) {
-        buf[self.head & !CBUF_DATA_BIT] = val;
-
-        self.head += 1;
-
-        if (self.head & !CBUF_DATA_BIT) >= buf.len() {
-            self.head = (self.head - buf.len()) ^ CBUF_DATA_BIT;
+    defmt::write!(f, "{=str} {{ contents: [", name);
+    for (i, val) in contents.enumerate() {
+        if i > 0 {
+            defmt::write!(f, ", ");
         }
+        defmt::write!(f, "{}", val);
     }
+    defmt::write!(f, "], count: {=usize}, capacity: {=usize} }}", count, capacity);
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use test::Bencher;
-    use test;
-
-    #[test]
-    fn basic_ctl() {
-        let mut buf = &mut [0u8, 2];
-        let mut cbuf = CBufControl::<u8>::new();
-
-        assert!(cbuf.is_empty());
-        assert!(!cbuf.is_full());
-
-        cbuf.put(buf, 3);
-        cbuf.put(buf, 4);
-        cbuf.put(buf, 42); // will have no effect
-        cbuf.put(buf, 42); // will have no effect
-        assert!(!cbuf.is_empty());
-        assert!(cbuf.is_full());
+/// Byte written over freed slots with `scrub` feature
+#[cfg(feature = "scrub")]
+pub const SCRUB_PATTERN: u8 = 0xde;
 
-        assert_eq!(cbuf.peek(buf).unwrap(), &3);
-        cbuf.peek(buf).unwrap();
-        assert!(!cbuf.is_empty());
-        assert!(cbuf.is_full());
-
-        assert_eq!(cbuf.get(buf).unwrap(), 3);
-        assert_eq!(cbuf.get(buf).unwrap(), 4);
-        assert!(cbuf.is_empty());
-        assert!(!cbuf.is_full());
-
-        assert!(cbuf.get(buf).is_none());
-        assert!(cbuf.get(buf).is_none());
-        cbuf.put(buf, 42);
-        assert_eq!(cbuf.get(buf).unwrap(), 42);
-    }
-
-    #[test]
-    fn basic_cbuf() {
-        let mut buf = &mut [0u8, 0u8];
-        let mut cbuf = CBuf::new(buf);
-
-        assert!(cbuf.len() == 2);
-        assert!(cbuf.is_empty());
-        assert!(!cbuf.is_full());
-
-        cbuf.put(3);
-        cbuf.put(4);
-        cbuf.put(42); // will have no effect
-        cbuf.put(42); // will have no effect
-        assert!(!cbuf.is_empty());
-        assert!(cbuf.is_full());
-
-        assert_eq!(cbuf.peek().unwrap(), &3);
-        cbuf.peek().unwrap();
-        assert!(!cbuf.is_empty());
-        assert!(cbuf.is_full());
-
-        assert_eq!(cbuf.get().unwrap(), 3);
-        assert_eq!(cbuf.get().unwrap(), 4);
-        assert!(cbuf.is_empty());
-        assert!(!cbuf.is_full());
-
-        assert!(cbuf.get().is_none());
-        assert!(cbuf.get().is_none());
-        cbuf.put(42);
-        assert_eq!(cbuf.get().unwrap(), 42);
+/// Is `T` a primitive integer or float, valid for any bit pattern?
+///
+/// `needs_drop` isn't enough: `bool`, `char`, references, `NonZero*` and
+/// most enums have invalid bit patterns, so `SCRUB_PATTERN` would turn
+/// them into UB. Comparing type names needs no `T: 'static`, unlike
+/// `TypeId`, and user types always carry their path so can't match.
+#[cfg(feature = "scrub")]
+#[inline]
+fn scrub_plain<T>() -> bool {
+    use core::any::type_name;
+    let name = type_name::<T>();
+    macro_rules! any_of {
+        ($($t:ty),*) => { false $(|| name == type_name::<$t>())* }
     }
+    any_of!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64)
+}
 
-    #[test]
-    fn test_ptr() {
-        let mut buf = &mut [0u8, 0u8];
-        let mut cbuf = CBuf::new(buf);
-        cbuf.put(3);
-        cbuf.put(4);
+/// Overwrite a freed slot with `SCRUB_PATTERN`
+///
+/// Slots of anything but primitive integers and floats are left alone.
+#[cfg(feature = "scrub")]
+#[inline]
+fn scrub_slot<T>(slot: &mut T) {
+    if scrub_plain::<T>() {
         unsafe {
-            let b = cbuf.as_ptr();
-            assert_eq!(*b, 3);
-            assert_eq!(*b.offset(1), 4);
-
-            let b = cbuf.as_mut_ptr();
-            *b.offset(1) = 42;
+            core::ptr::write_bytes(slot as *mut T as *mut u8,
+                                   SCRUB_PATTERN,
+                                   core::mem::size_of::<T>());
         }
-        assert_eq!(cbuf.get().unwrap(), 3);
-        assert_eq!(cbuf.get().unwrap(), 42);
     }
+}
 
-    #[test]
-    fn patterns() {
-        let mut buf = [0u8, 7];
-        let mut cbuf = CBufControl::<u8>::new();
-
-        let mut cur_len = 0;
-        let mut put_val = 0;
-        let mut get_val = 0;
-
-        for pattern in 0..256 {
-            if cur_len == 0 {
-                assert!(cbuf.is_empty());
-            }
-            if cur_len == buf.len() {
-                assert!(cbuf.is_full());
-            }
+/// Value aligned to its own cache line
+///
+/// Without `cache_padded` feature it's just the value, saving RAM on
+/// small MCUs that don't have caches anyway.
+#[cfg(any(feature = "spsc", feature = "mpmc"))]
+#[cfg_attr(all(feature = "cache_padded",
+               any(target_arch = "x86_64", target_arch = "aarch64")),
+           repr(align(128)))]
+#[cfg_attr(all(feature = "cache_padded",
+               not(any(target_arch = "x86_64", target_arch = "aarch64"))),
+           repr(align(64)))]
+struct CachePadded<T> {
+    value: T,
+}
 
-            for bit_i in 0..8 {
-                match pattern & (1 << bit_i) == 0 {
-                    true => {
-                        if cbuf.is_empty() {
-                            assert!(cbuf.peek(&buf).is_none());
-                            assert!(cbuf.get(&buf).is_none());
-                        } else {
-                            assert!(cbuf.peek(&buf).unwrap() == &get_val);
-                            let val = cbuf.get(&buf).unwrap();
-                            assert!(val == get_val);
-                            get_val = get_val.wrapping_add(1);
-                            cur_len -= 1;
-                        }
-                    }
-                    false => {
-                        if cbuf.is_full() {
-                            cbuf.put(&mut buf, put_val);
-                            assert!(cbuf.is_full());
-                        } else {
-                            cbuf.put(&mut buf, put_val);
-                            put_val = put_val.wrapping_add(1);
-                            cur_len += 1;
-                        }
-                    }
-                }
-            }
-        }
+#[cfg(any(feature = "spsc", feature = "mpmc"))]
+impl<T> CachePadded<T> {
+    fn new(value: T) -> CachePadded<T> {
+        CachePadded { value }
     }
+}
 
-    #[bench]
-    pub fn put_and_get(b: &mut Bencher) {
-        let buf = &mut [0u8; 256];
-        let mut cbuf = CBuf::new(buf);
-
-        b.iter(|| {
-            cbuf.put(0u8);
-            cbuf.get();
-        });
+#[cfg(any(feature = "spsc", feature = "mpmc"))]
+impl<T> core::ops::Deref for CachePadded<T> {
+    type Target = T;
 
-        test::black_box(cbuf.get());
+    #[inline]
+    fn deref(&self) -> &T {
+        &self.value
     }
+}
 
-    #[bench]
-    pub fn put_unchecked_and_get(b: &mut Bencher) {
-        let buf = &mut [0u8; 256];
-        let mut cbuf = CBuf::new(buf);
-
-        b.iter(|| unsafe {
-            cbuf.put_unchecked(0u8);
-            cbuf.get_unchecked();
-        });
+/// Elements formatted as one list, for the `Debug` impls of the rings
+struct DebugList<I>(I);
 
-        test::black_box(cbuf.get());
+impl<I: Iterator + Clone> core::fmt::Debug for DebugList<I>
+    where I::Item: core::fmt::Debug
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.0.clone()).finish()
     }
 }
+
+/// Traits for generic code working across all Circular Buffer variants
+///
+/// ```
+/// use cbuf::prelude::*;
+///
+/// fn forward<C: RingConsumer<u8>, P: RingProducer<u8>>(from: &mut C, to: &mut P) {
+///     while !to.is_full() {
+///         match from.get() {
+///             Some(val) => to.put(val),
+///             None => break,
+///         }
+///     }
+/// }
+/// ```
+pub mod prelude {
+    pub use super::{ByteSink, ByteSource, RingBuffer, RingConsumer, RingConsumerExt, RingProducer, RingProducerExt};
+    #[cfg(feature = "async")]
+    pub use super::{AsyncRingConsumer, AsyncRingProducer};
+}
@@ -0,0 +1,177 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Single-thread Circular Buffer with separately owned halves
+
+use core::cell::RefCell;
+
+use ring::CBuf;
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer split into writer and reader halves on one thread
+///
+/// `spsc` without atomics: the halves share the `CBuf` through a
+/// `RefCell`, so two components (eg. a parser and a protocol engine) can
+/// each own their half instead of passing `&mut CBuf` around. Halves are
+/// neither `Send` nor `Sync`.
+///
+/// ```
+/// use cbuf::LocalCBuf;
+///
+/// let buf = &mut [0u8; 4];
+/// let mut ring = LocalCBuf::new(buf);
+/// let (mut writer, mut reader) = ring.split();
+///
+/// writer.put(1);
+/// assert_eq!(reader.get(), Some(1));
+/// ```
+pub struct LocalCBuf<'a, T: 'a> {
+    ring: RefCell<CBuf<'a, T>>,
+}
+
+/// Writer half of `LocalCBuf`
+pub struct LocalWriter<'b, 'a: 'b, T: 'a> {
+    ring: &'b RefCell<CBuf<'a, T>>,
+}
+
+/// Reader half of `LocalCBuf`
+pub struct LocalReader<'b, 'a: 'b, T: 'a> {
+    ring: &'b RefCell<CBuf<'a, T>>,
+}
+
+impl<'a, T: Clone> LocalCBuf<'a, T> {
+    /// Create new LocalCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> LocalCBuf<'a, T> {
+        LocalCBuf { ring: RefCell::new(CBuf::new(buf)) }
+    }
+
+    /// Split into writer and reader halves
+    pub fn split<'b>(&'b mut self) -> (LocalWriter<'b, 'a, T>, LocalReader<'b, 'a, T>) {
+        (LocalWriter { ring: &self.ring }, LocalReader { ring: &self.ring })
+    }
+}
+
+impl<'b, 'a, T: Clone> LocalWriter<'b, 'a, T> {
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ring.borrow().is_full()
+    }
+
+    /// Number of free slots
+    #[inline]
+    pub fn remaining_capacity(&self) -> usize {
+        self.ring.borrow().remaining_capacity()
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        self.ring.borrow_mut().put(val)
+    }
+
+    /// See `CBuf::put_slice`
+    #[inline]
+    pub fn put_slice(&mut self, src: &[T]) -> usize {
+        self.ring.borrow_mut().put_slice(src)
+    }
+}
+
+impl<'b, 'a, T: Clone> LocalReader<'b, 'a, T> {
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.borrow().is_empty()
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.ring.borrow().count()
+    }
+
+    /// Copy of next element, without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&self) -> Option<T> {
+        self.ring.borrow().front().cloned()
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        self.ring.borrow_mut().get()
+    }
+
+    /// See `CBuf::pop_into`
+    #[inline]
+    pub fn pop_into(&mut self, dst: &mut [T]) -> usize {
+        self.ring.borrow_mut().pop_into(dst)
+    }
+}
+
+impl<'b, 'a, T: Clone> RingProducer<T> for LocalWriter<'b, 'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        LocalWriter::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        LocalWriter::put(self, val)
+    }
+}
+
+impl<'b, 'a, T: Clone> RingConsumer<T> for LocalReader<'b, 'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        LocalReader::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        LocalReader::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Parser<'b, 'a: 'b> {
+        out: LocalWriter<'b, 'a, u8>,
+    }
+
+    struct Engine<'b, 'a: 'b> {
+        inp: LocalReader<'b, 'a, u8>,
+    }
+
+    #[test]
+    fn local() {
+        let buf = &mut [0u8; 3];
+        let mut ring = LocalCBuf::new(buf);
+        let (writer, reader) = ring.split();
+        let (mut parser, mut engine) = (Parser { out: writer }, Engine { inp: reader });
+
+        assert_eq!(parser.out.put_slice(&[1, 2, 3, 4]), 3);
+        assert!(parser.out.is_full());
+        assert_eq!((engine.inp.count(), engine.inp.peek()), (3, Some(1)));
+        assert_eq!(engine.inp.get(), Some(1));
+        assert_eq!(parser.out.remaining_capacity(), 1);
+        parser.out.put(5);
+
+        let dst = &mut [0u8; 4];
+        assert_eq!(engine.inp.pop_into(dst), 3);
+        assert_eq!(&dst[..3], &[2, 3, 5]);
+        assert!(engine.inp.is_empty());
+    }
+}
@@ -0,0 +1,241 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Byte Circular Buffer mapped twice in virtual memory
+
+use std::io;
+use std::os::raw::{c_char, c_int, c_long, c_uint, c_void};
+
+use traits::{RingConsumer, RingProducer};
+
+// Values of the generic Linux ABI, checked for x86_64 and aarch64 only
+// (the module is compiled for nothing else); eg. MIPS and SPARC differ
+const PROT_NONE: c_int = 0;
+const PROT_READ: c_int = 1;
+const PROT_WRITE: c_int = 2;
+const MAP_SHARED: c_int = 0x01;
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_FIXED: c_int = 0x10;
+const MAP_ANONYMOUS: c_int = 0x20;
+const SC_PAGESIZE: c_int = 30;
+
+extern "C" {
+    fn sysconf(name: c_int) -> c_long;
+    fn memfd_create(name: *const c_char, flags: c_uint) -> c_int;
+    fn ftruncate(fd: c_int, length: c_long) -> c_int;
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: c_long)
+            -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> c_int;
+    fn close(fd: c_int) -> c_int;
+}
+
+fn map_failed(ptr: *mut c_void) -> bool {
+    ptr as usize == !0
+}
+
+/// Byte Circular Buffer whose contents and free space are always contiguous
+///
+/// The same physical pages are mapped twice, back to back, so a run
+/// starting anywhere in the first mapping can continue into the second
+/// one. `readable` and `writable` thus never need to be split at the end
+/// of the buffer. Requires `mirrored` feature, Linux on x86_64 or
+/// aarch64 only.
+pub struct MirroredCBuf {
+    ptr: *mut u8,
+    len: usize,
+    tail: usize,
+    count: usize,
+}
+
+unsafe impl Send for MirroredCBuf {}
+
+impl MirroredCBuf {
+    /// Create new MirroredCBuf of at least `min_len` bytes
+    ///
+    /// Length is rounded up to a multiple of page size.
+    pub fn new(min_len: usize) -> io::Result<MirroredCBuf> {
+        let page = unsafe { sysconf(SC_PAGESIZE) } as usize;
+        let len = core::cmp::max(1, min_len.div_ceil(page)) * page;
+
+        unsafe {
+            let fd = memfd_create(b"cbuf\0".as_ptr() as *const c_char, 0);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let res = Self::map(fd, len);
+            close(fd);
+            res.map(|ptr| {
+                MirroredCBuf {
+                    ptr,
+                    len,
+                    tail: 0,
+                    count: 0,
+                }
+            })
+        }
+    }
+
+    /// Reserve `2 * len` of address space and map `fd` into both halves
+    unsafe fn map(fd: c_int, len: usize) -> io::Result<*mut u8> {
+        if ftruncate(fd, len as c_long) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let base = mmap(core::ptr::null_mut(), 2 * len, PROT_NONE,
+                        MAP_PRIVATE | MAP_ANONYMOUS, -1, 0);
+        if map_failed(base) {
+            return Err(io::Error::last_os_error());
+        }
+        for half in 0..2 {
+            let addr = (base as *mut u8).add(half * len) as *mut c_void;
+            let ptr = mmap(addr, len, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_FIXED, fd, 0);
+            if map_failed(ptr) {
+                let err = io::Error::last_os_error();
+                munmap(base, 2 * len);
+                return Err(err);
+            }
+        }
+        Ok(base as *mut u8)
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Number of bytes in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.count == self.len
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// All bytes in the buffer, oldest first, as one slice
+    #[inline]
+    pub fn readable(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.ptr.add(self.tail), self.count) }
+    }
+
+    /// Remove `n` oldest bytes, eg. after processing `readable`
+    ///
+    /// panics if n > count()
+    #[inline]
+    pub fn consume(&mut self, n: usize) {
+        if n > self.count {
+            panic!("n > count")
+        }
+        self.tail = (self.tail + n) % self.len;
+        self.count -= n;
+    }
+
+    /// All free space in the buffer as one slice
+    #[inline]
+    pub fn writable(&mut self) -> &mut [u8] {
+        let head = (self.tail + self.count) % self.len;
+        unsafe { core::slice::from_raw_parts_mut(self.ptr.add(head), self.len - self.count) }
+    }
+
+    /// Add `n` bytes written into `writable`
+    ///
+    /// panics if n > len() - count()
+    #[inline]
+    pub fn commit(&mut self, n: usize) {
+        if n > self.len - self.count {
+            panic!("n > free space")
+        }
+        self.count += n;
+    }
+
+    /// Remove one byte from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<u8> {
+        let val = *self.readable().first()?;
+        self.consume(1);
+        Some(val)
+    }
+
+    /// Add byte to the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: u8) {
+        if let Some(b) = self.writable().first_mut() {
+            *b = val;
+            self.commit(1);
+        }
+    }
+}
+
+impl Drop for MirroredCBuf {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.ptr as *mut c_void, 2 * self.len);
+        }
+    }
+}
+
+impl RingProducer<u8> for MirroredCBuf {
+    #[inline]
+    fn is_full(&self) -> bool {
+        MirroredCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: u8) {
+        MirroredCBuf::put(self, val)
+    }
+}
+
+impl RingConsumer<u8> for MirroredCBuf {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        MirroredCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<u8> {
+        MirroredCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mirrored() {
+        let mut cbuf = MirroredCBuf::new(1).unwrap();
+        let len = cbuf.len();
+        assert!(len >= 1);
+
+        cbuf.commit(len - 2);
+        cbuf.consume(len - 2);
+        assert!(cbuf.is_empty());
+
+        // free space wraps around the end, yet is one slice
+        assert_eq!(cbuf.writable().len(), len);
+        cbuf.writable()[..4].copy_from_slice(&[1, 2, 3, 4]);
+        cbuf.commit(4);
+        assert_eq!(cbuf.readable(), &[1, 2, 3, 4]);
+        assert_eq!(cbuf.get(), Some(1));
+        cbuf.put(5);
+        assert_eq!(cbuf.readable(), &[2, 3, 4, 5]);
+        cbuf.consume(4);
+        assert_eq!(cbuf.get(), None);
+    }
+}
@@ -0,0 +1,260 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Lock-free multi-producer multi-consumer Circular Buffer
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{self, AtomicUsize, Ordering};
+
+use super::CachePadded;
+#[cfg(feature = "scrub")]
+use super::scrub_slot;
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer shareable between many producers and many consumers
+///
+/// Bounded queue where every slot has a sequence stamp telling whether it
+/// is ready to be written or read in the current lap around the buffer.
+/// Producers and consumers claim slots with CAS on head/tail.
+///
+/// Stamps need separate storage: one `AtomicUsize` per element.
+pub struct MpmcCBuf<'a, T: 'a> {
+    buf: &'a [UnsafeCell<T>],
+    stamps: &'a [AtomicUsize],
+    one_lap: usize,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+unsafe impl<'a, T: Send> Sync for MpmcCBuf<'a, T> {}
+
+impl<'a, T: Clone> MpmcCBuf<'a, T> {
+    /// Create new MpmcCBuf
+    ///
+    /// panics if buf.len() == 0 or stamps.len() != buf.len()
+    pub fn new(buf: &'a mut [T], stamps: &'a mut [AtomicUsize]) -> MpmcCBuf<'a, T> {
+        if buf.is_empty() {
+            panic!("len==0")
+        }
+        if stamps.len() != buf.len() {
+            panic!("stamps.len() != buf.len()")
+        }
+        for (i, stamp) in stamps.iter_mut().enumerate() {
+            *stamp = AtomicUsize::new(i);
+        }
+
+        // `UnsafeCell<T>` has the same in-memory representation as `T`
+        let buf = unsafe { &*(buf as *mut [T] as *const [UnsafeCell<T>]) };
+
+        MpmcCBuf {
+            one_lap: (buf.len() + 1).next_power_of_two(),
+            buf,
+            stamps,
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Position following `pos`
+    #[inline]
+    fn next(&self, pos: usize) -> usize {
+        let index = pos & (self.one_lap - 1);
+        if index + 1 < self.buf.len() {
+            pos + 1
+        } else {
+            (pos & !(self.one_lap - 1)).wrapping_add(self.one_lap)
+        }
+    }
+
+    /// Is buffer empty?
+    ///
+    /// Only a snapshot: other threads may change it right away.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.load(Ordering::SeqCst);
+        let tail = self.tail.load(Ordering::SeqCst);
+        head == tail
+    }
+
+    /// Is buffer full?
+    ///
+    /// Only a snapshot: other threads may change it right away.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        let head = self.head.load(Ordering::SeqCst);
+        let tail = self.tail.load(Ordering::SeqCst);
+        head.wrapping_add(self.one_lap) == tail
+    }
+
+    /// Add element the buffer
+    ///
+    /// Returns the element back if buffer is full.
+    pub fn put(&self, val: T) -> Result<(), T> {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let index = tail & (self.one_lap - 1);
+            let stamp = self.stamps[index].load(Ordering::Acquire);
+
+            if tail == stamp {
+                // Slot is free in this lap, try to claim it
+                match self.tail.compare_exchange_weak(tail,
+                                                      self.next(tail),
+                                                      Ordering::SeqCst,
+                                                      Ordering::Relaxed) {
+                    Ok(_) => {
+                        unsafe {
+                            *self.buf[index].get() = val;
+                        }
+                        self.stamps[index].store(tail + 1, Ordering::Release);
+                        return Ok(());
+                    }
+                    Err(t) => tail = t,
+                }
+            } else if stamp.wrapping_add(self.one_lap) == tail + 1 {
+                // Slot still holds an element from the previous lap
+                atomic::fence(Ordering::SeqCst);
+                let head = self.head.load(Ordering::Relaxed);
+                if head.wrapping_add(self.one_lap) == tail {
+                    return Err(val);
+                }
+                tail = self.tail.load(Ordering::Relaxed);
+            } else {
+                tail = self.tail.load(Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Remove one element from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    pub fn get(&self) -> Option<T> {
+        let mut head = self.head.load(Ordering::Relaxed);
+        loop {
+            let index = head & (self.one_lap - 1);
+            let stamp = self.stamps[index].load(Ordering::Acquire);
+
+            if head + 1 == stamp {
+                // Slot was written in this lap, try to claim it
+                match self.head.compare_exchange_weak(head,
+                                                      self.next(head),
+                                                      Ordering::SeqCst,
+                                                      Ordering::Relaxed) {
+                    Ok(_) => {
+                        let val = unsafe { (*self.buf[index].get()).clone() };
+                        #[cfg(feature = "scrub")]
+                        unsafe {
+                            scrub_slot(&mut *self.buf[index].get());
+                        }
+                        self.stamps[index]
+                            .store(head.wrapping_add(self.one_lap), Ordering::Release);
+                        return Some(val);
+                    }
+                    Err(h) => head = h,
+                }
+            } else if stamp == head {
+                // Slot not written yet
+                atomic::fence(Ordering::SeqCst);
+                let tail = self.tail.load(Ordering::Relaxed);
+                if tail == head {
+                    return None;
+                }
+                head = self.head.load(Ordering::Relaxed);
+            } else {
+                head = self.head.load(Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+impl<'r, 'a, T: Clone> RingProducer<T> for &'r MpmcCBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        MpmcCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        let _ = MpmcCBuf::put(self, val);
+    }
+}
+
+impl<'r, 'a, T: Clone> RingConsumer<T> for &'r MpmcCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        MpmcCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        MpmcCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn mpmc() {
+        let buf = &mut [0u8; 3];
+        let stamps = &mut [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+        let ring = MpmcCBuf::new(buf, stamps);
+
+        for i in 0..10 {
+            assert!(ring.is_empty());
+            assert!(ring.get().is_none());
+            assert_eq!(ring.put(i), Ok(()));
+            assert_eq!(ring.put(i + 1), Ok(()));
+            assert_eq!(ring.put(i + 2), Ok(()));
+            assert_eq!(ring.put(42), Err(42));
+            assert!(ring.is_full());
+            assert_eq!(ring.get(), Some(i));
+            assert_eq!(ring.get(), Some(i + 1));
+            assert_eq!(ring.get(), Some(i + 2));
+        }
+    }
+
+    #[test]
+    fn mpmc_threads() {
+        let buf = &mut [0usize; 5];
+        let stamps: &mut [AtomicUsize; 5] = &mut core::array::from_fn(|_| AtomicUsize::new(0));
+        let ring = MpmcCBuf::new(buf, stamps);
+        let sum = AtomicUsize::new(0);
+        let count = AtomicUsize::new(0);
+
+        std::thread::scope(|s| {
+            for t in 0..2 {
+                let ring = &ring;
+                s.spawn(move || {
+                    for i in 0..1000 {
+                        let mut val = t * 1000 + i;
+                        while let Err(v) = ring.put(val) {
+                            val = v;
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+            for _ in 0..2 {
+                let (ring, sum, count) = (&ring, &sum, &count);
+                s.spawn(move || {
+                    while count.load(Ordering::SeqCst) < 2000 {
+                        match ring.get() {
+                            Some(val) => {
+                                sum.fetch_add(val, Ordering::SeqCst);
+                                count.fetch_add(1, Ordering::SeqCst);
+                            }
+                            None => std::thread::yield_now(),
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(sum.load(Ordering::SeqCst), (0..2000).sum());
+        assert!(ring.is_empty());
+    }
+}
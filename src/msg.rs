@@ -0,0 +1,122 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Variable-length messages over a byte Circular Buffer
+
+use ring::{CBuf, NoSpace};
+
+/// Byte Circular Buffer queuing whole messages
+///
+/// Every message is stored after its length, encoded as LEB128 varint
+/// (one byte up to 127, two up to 16383, ...).
+pub struct MsgCBuf<'a> {
+    inner: CBuf<'a, u8>,
+}
+
+/// Length of the varint encoding of `len`
+fn prefix_len(len: usize) -> usize {
+    let mut n = 1;
+    let mut len = len >> 7;
+    while len != 0 {
+        n += 1;
+        len >>= 7;
+    }
+    n
+}
+
+impl<'a> MsgCBuf<'a> {
+    /// Create new MsgCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [u8]) -> MsgCBuf<'a> {
+        MsgCBuf { inner: CBuf::new(buf) }
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Add a message
+    ///
+    /// All or nothing: on error the buffer is left unchanged.
+    pub fn put_msg(&mut self, msg: &[u8]) -> Result<(), NoSpace> {
+        let free = self.inner.len() - self.inner.count();
+        if prefix_len(msg.len()) + msg.len() > free {
+            return Err(NoSpace);
+        }
+        let mut len = msg.len();
+        while len >= 0x80 {
+            self.inner.put((len as u8) | 0x80);
+            len >>= 7;
+        }
+        self.inner.put(len as u8);
+        self.inner.put_slice(msg);
+        Ok(())
+    }
+
+    /// Remove next message, copying it to `out`
+    ///
+    /// Returns message length, which may exceed `out.len()` in which case
+    /// the rest of the message is discarded. Returns `None` if buffer is
+    /// empty.
+    pub fn get_msg(&mut self, out: &mut [u8]) -> Option<usize> {
+        let mut len = 0;
+        let mut shift = 0;
+        loop {
+            let b = self.inner.get()?;
+            len |= ((b & 0x7f) as usize) << shift;
+            shift += 7;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+        let n = core::cmp::min(len, out.len());
+        self.inner.pop_into(&mut out[..n]);
+        for _ in n..len {
+            self.inner.get();
+        }
+        Some(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn msg() {
+        let buf = &mut [0u8; 8];
+        let mut msgs = MsgCBuf::new(buf);
+        let out = &mut [0u8; 8];
+
+        assert_eq!(msgs.get_msg(out), None);
+        msgs.put_msg(&[1, 2, 3]).unwrap();
+        msgs.put_msg(&[]).unwrap();
+        assert_eq!(msgs.put_msg(&[4, 5, 6]), Err(NoSpace));
+        msgs.put_msg(&[4, 5]).unwrap();
+
+        assert_eq!(msgs.get_msg(out), Some(3));
+        assert_eq!(&out[..3], &[1, 2, 3]);
+        assert_eq!(msgs.get_msg(out), Some(0));
+        assert_eq!(msgs.get_msg(&mut out[..1]), Some(2));
+        assert_eq!(out[0], 4);
+        assert!(msgs.is_empty());
+    }
+
+    #[test]
+    fn msg_long() {
+        let buf = &mut [0u8; 300];
+        let mut msgs = MsgCBuf::new(buf);
+        let out = &mut [0u8; 300];
+
+        let data = [7u8; 200];
+        msgs.put_msg(&data).unwrap();
+        assert_eq!(msgs.inner.count(), 202);
+        assert_eq!(msgs.get_msg(out), Some(200));
+        assert_eq!(&out[..200], &data[..]);
+    }
+}
@@ -0,0 +1,188 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer keeping a duplicate of every element
+
+use ring::{CBuf, Corruption};
+use traits::{RingConsumer, RingProducer};
+
+/// Error of `ParityCBuf::get`: the two copies of an element differ
+///
+/// There's no telling which copy is right, so the element is dropped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct ParityError;
+
+/// Circular Buffer detecting corrupted elements
+///
+/// Every element is stored twice, in `buf` and in `copy`, and the copies
+/// are compared on read. Meant for long-lived rings in RAM exposed to
+/// single event upsets (space, industrial). Only slots are protected,
+/// not head and tail indexes.
+pub struct ParityCBuf<'a, T: 'a> {
+    buf: CBuf<'a, T>,
+    copy: CBuf<'a, T>,
+    errors: usize,
+}
+
+impl<'a, T: Clone + PartialEq> ParityCBuf<'a, T> {
+    /// Create new ParityCBuf
+    ///
+    /// panics if buf.len() == 0, or if buf.len() != copy.len()
+    pub fn new(buf: &'a mut [T], copy: &'a mut [T]) -> ParityCBuf<'a, T> {
+        if buf.len() != copy.len() {
+            panic!("copy.len() != buf.len()")
+        }
+        ParityCBuf {
+            buf: CBuf::new(buf),
+            copy: CBuf::new(copy),
+            errors: 0,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.buf.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Number of corrupted elements found so far
+    #[inline]
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Remove one element from the buffer, verifying it
+    ///
+    /// Returns `Ok(None)` if buffer is empty. A corrupted element is
+    /// removed anyway and counted in `errors`.
+    pub fn get(&mut self) -> Result<Option<T>, ParityError> {
+        let val = match self.buf.get() {
+            Some(val) => val,
+            None => return Ok(None),
+        };
+        if self.copy.get().as_ref() != Some(&val) {
+            self.errors += 1;
+            return Err(ParityError);
+        }
+        Ok(Some(val))
+    }
+
+    /// Validate indexes and compare both copies of every element
+    ///
+    /// Unlike `get` nothing is removed, so a watchdog task can scrub the
+    /// whole ring for upsets. Takes time linear in the number of elements.
+    pub fn health_check(&self) -> Result<(), Corruption> {
+        self.buf.health_check()?;
+        self.copy.health_check()?;
+        if self.buf.checkpoint() != self.copy.checkpoint() {
+            return Err(Corruption::Index);
+        }
+        let (a, b) = self.buf.as_slices();
+        let (copy_a, copy_b) = self.copy.as_slices();
+        match a.iter().chain(b).zip(copy_a.iter().chain(copy_b)).position(|(x, y)| x != y) {
+            Some(offset) => Err(Corruption::Slot(offset)),
+            None => Ok(()),
+        }
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        self.copy.put(val.clone());
+        self.buf.put(val);
+    }
+}
+
+impl<'a, T: Clone + PartialEq> RingProducer<T> for ParityCBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        ParityCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        ParityCBuf::put(self, val)
+    }
+}
+
+/// Skips corrupted elements; they are still counted in `errors`
+impl<'a, T: Clone + PartialEq> RingConsumer<T> for ParityCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        ParityCBuf::is_empty(self)
+    }
+
+    fn get(&mut self) -> Option<T> {
+        loop {
+            if let Ok(val) = ParityCBuf::get(self) {
+                return val;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn parity() {
+        let (buf, copy) = (&mut [0u32; 3], &mut [0u32; 3]);
+        let mut cbuf = ParityCBuf::new(buf, copy);
+
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.put(3);
+        cbuf.put(4); // will have no effect
+        assert!(cbuf.is_full());
+
+        // flip a bit in the copy of `2`
+        unsafe {
+            *cbuf.copy.as_non_null().as_ptr().offset(1) ^= 0x100;
+        }
+        assert_eq!(cbuf.get(), Ok(Some(1)));
+        assert_eq!(cbuf.get(), Err(ParityError));
+        assert_eq!(cbuf.errors(), 1);
+        assert_eq!(cbuf.get(), Ok(Some(3)));
+        assert_eq!(cbuf.get(), Ok(None));
+
+        cbuf.put(5);
+        cbuf.put(6);
+        assert_eq!(cbuf.health_check(), Ok(()));
+        unsafe {
+            *cbuf.buf.as_non_null().as_ptr() = 0;
+        }
+        assert_eq!(cbuf.health_check(), Err(Corruption::Slot(0)));
+        assert_eq!(RingConsumer::get(&mut cbuf), Some(6));
+        assert_eq!(cbuf.errors(), 2);
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn parity_len_mismatch() {
+        let (buf, copy) = (&mut [0u8; 3], &mut [0u8; 2]);
+        ParityCBuf::new(buf, copy);
+    }
+}
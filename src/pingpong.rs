@@ -0,0 +1,102 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Double buffering over the two halves of one Circular Buffer
+
+use ring::CBuf;
+
+/// Ping-pong double buffer for audio/ADC style processing
+///
+/// Producer puts samples one by one into the active half. Once it's
+/// full, writing continues in the other half and the full one is handed
+/// to the consumer as one contiguous slice via `ready`, until `release`d.
+/// If the consumer hasn't released its half by the time the producer
+/// fills the other one, further puts are ignored.
+pub struct PingPong<'a, T: 'a> {
+    ring: CBuf<'a, T>,
+    half: usize,
+}
+
+impl<'a, T: Clone> PingPong<'a, T> {
+    /// Create new PingPong, using each half of `buf` in turn
+    ///
+    /// panics if buf.len() == 0 or buf.len() is odd
+    pub fn new(buf: &'a mut [T]) -> PingPong<'a, T> {
+        if !buf.len().is_multiple_of(2) {
+            panic!("odd len")
+        }
+        let half = buf.len() / 2;
+        PingPong {
+            ring: CBuf::new(buf),
+            half,
+        }
+    }
+
+    /// Is the producer blocked on the consumer releasing its half?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ring.is_full()
+    }
+
+    /// Add element to the active half
+    ///
+    /// Ignores the write if both halves are full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        self.ring.put(val)
+    }
+
+    /// Half filled by the producer, oldest element first
+    ///
+    /// Returns `None` if no half is full yet.
+    #[inline]
+    pub fn ready(&self) -> Option<&[T]> {
+        if self.ring.count() < self.half {
+            return None;
+        }
+        // Tail only ever moves by whole halves, so they never wrap
+        Some(&self.ring.as_slices().0[..self.half])
+    }
+
+    /// Give the `ready` half back to the producer
+    ///
+    /// Does nothing if no half is ready.
+    pub fn release(&mut self) {
+        if self.ready().is_none() {
+            return;
+        }
+        self.ring.discard(self.half);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pingpong() {
+        let buf = &mut [0i16; 6];
+        let mut pp = PingPong::new(buf);
+
+        for i in 0..4 {
+            pp.put(i);
+        }
+        assert_eq!(pp.ready(), Some(&[0, 1, 2][..]));
+        pp.put(4);
+        pp.put(5);
+        pp.put(6); // both halves full: ignored
+        assert!(pp.is_full());
+        assert_eq!(pp.ready(), Some(&[0, 1, 2][..]));
+
+        pp.release();
+        assert_eq!(pp.ready(), Some(&[3, 4, 5][..]));
+        pp.put(7);
+        pp.release();
+        assert_eq!(pp.ready(), None);
+        pp.put(8);
+        pp.put(9);
+        assert_eq!(pp.ready(), Some(&[7, 8, 9][..]));
+    }
+}
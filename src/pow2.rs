@@ -0,0 +1,295 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer with power-of-two length, wrapping indexes by masking
+
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer for power-of-two lengths
+///
+/// Head and tail are free-running counters and slots are found by
+/// masking them, so `put` and `get` have no wrap-around branch at all.
+/// Meant for high-rate streaming (SPI, ADC) where per-element cost
+/// matters; compare `put_and_get` benchmarks of `CBuf` and `Pow2CBuf`.
+///
+/// `Debug` shows the queued elements oldest first, like `CBuf`.
+pub struct Pow2CBuf<'a, T: 'a> {
+    buf: &'a mut [T],
+    head: usize,
+    tail: usize,
+}
+
+impl<'a, T: Clone> Pow2CBuf<'a, T> {
+    /// Create new Pow2CBuf
+    ///
+    /// panics if buf.len() is not a power of two
+    pub fn new(buf: &'a mut [T]) -> Pow2CBuf<'a, T> {
+        if !buf.len().is_power_of_two() {
+            panic!("len not a power of two")
+        }
+
+        Pow2CBuf {
+            buf,
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.head.wrapping_sub(self.tail)
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.count() == self.buf.len()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(&self.buf[self.tail & (self.buf.len() - 1)])
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        let val = self.peek().cloned();
+        if val.is_some() {
+            self.tail = self.tail.wrapping_add(1);
+        }
+        val
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        let mask = self.buf.len() - 1;
+        self.buf[self.head & mask] = val;
+        self.head = self.head.wrapping_add(1);
+    }
+}
+
+impl<'a, T: core::fmt::Debug> core::fmt::Debug for Pow2CBuf<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let count = self.head.wrapping_sub(self.tail);
+        let mask = self.buf.len() - 1;
+        let contents = (0..count).map(|i| &self.buf[self.tail.wrapping_add(i) & mask]);
+        f.debug_struct("Pow2CBuf")
+            .field("contents", &format_args!("{:?}", DebugIter(contents)))
+            .field("count", &count)
+            .field("capacity", &self.buf.len())
+            .finish()
+    }
+}
+
+/// Compares queued elements, not raw slots or counters
+impl<'a, 'b, T: PartialEq<U>, U> PartialEq<Pow2CBuf<'b, U>> for Pow2CBuf<'a, T> {
+    fn eq(&self, other: &Pow2CBuf<'b, U>) -> bool {
+        let count = self.head.wrapping_sub(self.tail);
+        let (mask, other_mask) = (self.buf.len() - 1, other.buf.len() - 1);
+        count == other.head.wrapping_sub(other.tail) &&
+        (0..count).all(|i| {
+            self.buf[self.tail.wrapping_add(i) & mask] ==
+            other.buf[other.tail.wrapping_add(i) & other_mask]
+        })
+    }
+}
+
+impl<'a, T: Eq> Eq for Pow2CBuf<'a, T> {}
+
+impl<'a, T: PartialEq<U>, U> PartialEq<[U]> for Pow2CBuf<'a, T> {
+    fn eq(&self, other: &[U]) -> bool {
+        let mask = self.buf.len() - 1;
+        self.head.wrapping_sub(self.tail) == other.len() &&
+        other.iter().enumerate().all(|(i, y)| self.buf[self.tail.wrapping_add(i) & mask] == *y)
+    }
+}
+
+impl<'a, T: PartialEq<U>, U, const N: usize> PartialEq<[U; N]> for Pow2CBuf<'a, T> {
+    fn eq(&self, other: &[U; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+/// Elements of a cloneable iterator formatted as a list
+struct DebugIter<I>(I);
+
+impl<I: Iterator + Clone> core::fmt::Debug for DebugIter<I>
+    where I::Item: core::fmt::Debug
+{
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_list().entries(self.0.clone()).finish()
+    }
+}
+
+impl<'a, T: Clone> RingProducer<T> for Pow2CBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        Pow2CBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        Pow2CBuf::put(self, val)
+    }
+}
+
+impl<'a, T: Clone> RingConsumer<T> for Pow2CBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        Pow2CBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        Pow2CBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+    use test::Bencher;
+    use test;
+
+    #[test]
+    fn pow2() {
+        let buf = &mut [0u8; 2];
+        let mut cbuf = Pow2CBuf::new(buf);
+
+        assert!(cbuf.is_empty());
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.put(3); // will have no effect
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.get(), Some(1));
+        cbuf.put(4);
+        assert_eq!(cbuf.count(), 2);
+        assert_eq!(cbuf.get(), Some(2));
+        assert_eq!(cbuf.get(), Some(4));
+        assert_eq!(cbuf.get(), None);
+    }
+
+    #[test]
+    fn pow2_debug() {
+        let buf = &mut [0u8; 2];
+        let mut cbuf = Pow2CBuf::new(buf);
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.get();
+        cbuf.put(3);
+
+        assert_eq!(std::format!("{:?}", cbuf), "Pow2CBuf { contents: [2, 3], count: 2, capacity: 2 }");
+    }
+
+    #[test]
+    fn pow2_eq() {
+        let (buf_a, buf_b) = (&mut [0u8; 2], &mut [0u8; 4]);
+        let (mut a, mut b) = (Pow2CBuf::new(buf_a), Pow2CBuf::new(buf_b));
+        a.put(1);
+        a.put(2);
+        a.get();
+        a.put(3);
+        b.put(2);
+        b.put(3);
+
+        assert_eq!(a, b);
+        assert_eq!(a, [2, 3]);
+        b.put(4);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn pow2_counter_wrap() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = Pow2CBuf::new(buf);
+        cbuf.head = usize::MAX - 1;
+        cbuf.tail = cbuf.head;
+
+        for i in 0..4 {
+            cbuf.put(i);
+        }
+        assert!(cbuf.is_full());
+        for i in 0..4 {
+            assert_eq!(cbuf.get(), Some(i));
+        }
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    fn pow2_matches_cbuf() {
+        use ring::CBuf;
+
+        for &len in &[1, 2, 4, 8] {
+            let (buf, pow2_buf) = (&mut [0u8; 8][..len], &mut [0u8; 8][..len]);
+            let (mut cbuf, mut pow2) = (CBuf::new(buf), Pow2CBuf::new(pow2_buf));
+            let mut put_val = 0u8;
+
+            // same op patterns as `ring::tests::patterns`
+            for pattern in 0..256 {
+                for bit_i in 0..8 {
+                    if pattern & (1 << bit_i) == 0 {
+                        assert_eq!(pow2.get(), cbuf.get());
+                    } else {
+                        cbuf.put(put_val);
+                        pow2.put(put_val);
+                        put_val = put_val.wrapping_add(1);
+                    }
+                    assert_eq!(pow2.count(), cbuf.count());
+                    assert_eq!(pow2.is_full(), cbuf.is_full());
+                    assert_eq!(pow2.is_empty(), cbuf.is_empty());
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn pow2_not_pow2() {
+        let buf = &mut [0u8; 3];
+        Pow2CBuf::new(buf);
+    }
+
+    #[bench]
+    pub fn put_and_get(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut cbuf = Pow2CBuf::new(buf);
+
+        b.iter(|| {
+            cbuf.put(0u8);
+            cbuf.get();
+        });
+
+        test::black_box(cbuf.get());
+    }
+}
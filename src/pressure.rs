@@ -0,0 +1,292 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Occupancy-based hints: pressure for power management, batch size for
+//! consumers, histograms for sizing buffers
+
+/// Coarse occupancy class of a buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pressure {
+    /// Consumer keeps up easily: fine to sleep longer or lower clocks
+    Low,
+    /// Nothing to change
+    Normal,
+    /// Consumer is falling behind: boost clocks or wake up more often
+    High,
+}
+
+/// Turns occupancy samples into a `Pressure` that doesn't flap
+///
+/// Occupancy is smoothed over the last few samples, then compared
+/// against `low` and `high` thresholds (in percent of capacity). The
+/// current class is only left once the smoothed occupancy moves
+/// `hysteresis` percent past the threshold that was crossed to enter it.
+#[derive(Debug, Clone)]
+pub struct PressureGauge {
+    low: u8,
+    high: u8,
+    hysteresis: u8,
+    // smoothed occupancy in 1/16 percent
+    avg: u32,
+    pressure: Pressure,
+}
+
+impl PressureGauge {
+    /// Create new PressureGauge
+    ///
+    /// panics if high > 100 or low >= high
+    pub const fn new(low: u8, high: u8, hysteresis: u8) -> PressureGauge {
+        if high > 100 || low >= high {
+            panic!("bad thresholds")
+        }
+
+        PressureGauge {
+            low,
+            high,
+            hysteresis,
+            avg: 0,
+            pressure: Pressure::Normal,
+        }
+    }
+
+    /// Last classification
+    #[inline]
+    pub fn pressure(&self) -> Pressure {
+        self.pressure
+    }
+
+    /// Feed an occupancy sample and classify it
+    ///
+    /// panics if capacity == 0
+    pub fn update(&mut self, count: usize, capacity: usize) -> Pressure {
+        let level = (count * 100 * 16 / capacity) as u32;
+        // Exponential moving average with 1/4 weight of new sample
+        self.avg = self.avg - self.avg / 4 + level / 4;
+
+        let avg = self.avg / 16;
+        let (low, high, hyst) = (self.low as u32, self.high as u32, self.hysteresis as u32);
+        self.pressure = match self.pressure {
+            Pressure::High if avg + hyst >= high => Pressure::High,
+            Pressure::Low if avg <= low + hyst => Pressure::Low,
+            _ if avg >= high => Pressure::High,
+            _ if avg <= low => Pressure::Low,
+            _ => Pressure::Normal,
+        };
+        self.pressure
+    }
+}
+
+/// Counts of occupancy samples in `B` equal ranges of capacity
+///
+/// Bucket `i` counts samples with `i * capacity / B` up to (not
+/// including) `(i + 1) * capacity / B` elements; a full buffer goes to the
+/// last bucket. Counters saturate instead of wrapping.
+#[cfg(feature = "instrument")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OccupancyHistogram<const B: usize> {
+    buckets: [u32; B],
+}
+
+#[cfg(feature = "instrument")]
+impl<const B: usize> OccupancyHistogram<B> {
+    /// Create new empty OccupancyHistogram
+    ///
+    /// panics if B == 0
+    pub const fn new() -> OccupancyHistogram<B> {
+        if B == 0 {
+            panic!("no buckets")
+        }
+
+        OccupancyHistogram { buckets: [0; B] }
+    }
+
+    /// Count an occupancy sample
+    ///
+    /// panics if capacity == 0
+    pub fn record(&mut self, count: usize, capacity: usize) {
+        let i = core::cmp::min(count * B / capacity, B - 1);
+        self.buckets[i] = self.buckets[i].saturating_add(1);
+    }
+
+    /// Sample counts, emptiest range first
+    #[inline]
+    pub fn buckets(&self) -> &[u32; B] {
+        &self.buckets
+    }
+
+    /// Forget all samples
+    #[inline]
+    pub fn clear(&mut self) {
+        self.buckets = [0; B];
+    }
+}
+
+#[cfg(feature = "instrument")]
+impl<const B: usize> Default for OccupancyHistogram<B> {
+    fn default() -> OccupancyHistogram<B> {
+        OccupancyHistogram::new()
+    }
+}
+
+/// Heuristic choosing how many elements a consumer should read at once
+pub trait BatchPolicy {
+    /// Suggest a batch size given `count` stored elements out of
+    /// `capacity`
+    fn suggest(&mut self, count: usize, capacity: usize) -> usize;
+}
+
+/// Default `BatchPolicy`: small batches when idle, large when busy
+///
+/// Tracks a moving average of arrivals between calls, assuming the
+/// consumer reads what was suggested last time, and suggests that plus
+/// half of the backlog, clamped to `min..=max` and to what's stored.
+#[derive(Debug, Clone)]
+pub struct AdaptiveBatch {
+    min: usize,
+    max: usize,
+    // moving average of arrivals per call, in 1/16 elements
+    rate: usize,
+    last_count: usize,
+    last_batch: usize,
+}
+
+impl AdaptiveBatch {
+    /// Create new AdaptiveBatch
+    ///
+    /// panics if min == 0 or min > max
+    pub const fn new(min: usize, max: usize) -> AdaptiveBatch {
+        if min == 0 || min > max {
+            panic!("bad batch range")
+        }
+
+        AdaptiveBatch {
+            min,
+            max,
+            rate: 0,
+            last_count: 0,
+            last_batch: 0,
+        }
+    }
+}
+
+impl BatchPolicy for AdaptiveBatch {
+    fn suggest(&mut self, count: usize, _capacity: usize) -> usize {
+        let left = self.last_count.saturating_sub(self.last_batch);
+        let arrived = count.saturating_sub(left);
+        self.rate = self.rate - self.rate / 4 + arrived * 16 / 4;
+
+        let batch = self.rate / 16 + count / 2;
+        let batch = core::cmp::min(core::cmp::max(batch, self.min), self.max);
+        let batch = core::cmp::min(batch, count);
+        self.last_count = count;
+        self.last_batch = batch;
+        batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pressure() {
+        let mut gauge = PressureGauge::new(20, 80, 10);
+        assert_eq!(gauge.pressure(), Pressure::Normal);
+
+        // smoothing: one full sample is not enough
+        assert_eq!(gauge.update(10, 10), Pressure::Normal);
+        for _ in 0..10 {
+            gauge.update(10, 10);
+        }
+        assert_eq!(gauge.pressure(), Pressure::High);
+
+        // hysteresis: stays high until below 70%
+        for _ in 0..20 {
+            assert_eq!(gauge.update(3, 4), Pressure::High);
+        }
+        for _ in 0..20 {
+            gauge.update(6, 10);
+        }
+        assert_eq!(gauge.pressure(), Pressure::Normal);
+
+        for _ in 0..20 {
+            gauge.update(0, 10);
+        }
+        assert_eq!(gauge.pressure(), Pressure::Low);
+        for _ in 0..20 {
+            assert_eq!(gauge.update(1, 4), Pressure::Low);
+        }
+        for _ in 0..20 {
+            gauge.update(4, 10);
+        }
+        assert_eq!(gauge.pressure(), Pressure::Normal);
+    }
+
+    #[test]
+    fn adaptive_batch() {
+        let mut policy = AdaptiveBatch::new(1, 16);
+        assert_eq!(policy.suggest(0, 64), 0);
+
+        // trickle: read one at a time
+        for _ in 0..10 {
+            assert_eq!(policy.suggest(1, 64), 1);
+        }
+
+        // burst: batches grow with arrival rate and backlog
+        let mut count = 0;
+        let mut batch = 0;
+        for _ in 0..10 {
+            count = count - batch + 12;
+            batch = policy.suggest(count, 64);
+        }
+        assert!((12..=16).contains(&batch));
+    }
+
+    #[test]
+    #[cfg(all(feature = "instrument", not(feature = "no_std")))]
+    fn occupancy_metrics() {
+        use ring::CBuf;
+
+        let buf = &mut [0u8; 8];
+        let mut cbuf = CBuf::new(buf);
+        let mut hist = OccupancyHistogram::<4>::new();
+        cbuf.record_occupancy(&mut hist);
+        cbuf.put_slice(&[0; 7]);
+        cbuf.pop_into(&mut [0; 5]);
+        cbuf.put(1);
+        cbuf.record_occupancy(&mut hist);
+        cbuf.put_slice(&[0; 5]);
+        cbuf.record_occupancy(&mut hist);
+        assert_eq!(hist.buckets(), &[1, 1, 0, 1]);
+        assert_eq!(cbuf.high_water_mark(), 8);
+
+        cbuf.clear();
+        cbuf.reset_high_water_mark();
+        cbuf.put(2);
+        assert_eq!(cbuf.high_water_mark(), 1);
+        hist.clear();
+        assert_eq!(hist, OccupancyHistogram::default());
+    }
+
+    #[test]
+    #[cfg(not(feature = "no_std"))]
+    fn cbuf_pressure() {
+        use ring::CBuf;
+
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        let mut gauge = PressureGauge::new(25, 75, 0);
+        for _ in 0..20 {
+            cbuf.pressure(&mut gauge);
+        }
+        assert_eq!(gauge.pressure(), Pressure::Low);
+        cbuf.put_slice(&[1, 2, 3, 4]);
+        for _ in 0..20 {
+            cbuf.pressure(&mut gauge);
+        }
+        assert_eq!(gauge.pressure(), Pressure::High);
+        assert_eq!(cbuf.suggested_batch(&mut AdaptiveBatch::new(1, 2)), 2);
+    }
+}
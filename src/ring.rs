@@ -0,0 +1,3305 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Single-threaded Circular Buffer and its control block
+
+use core::marker::PhantomData;
+
+use super::CBUF_DATA_BIT;
+#[cfg(not(feature = "no_std"))]
+use super::cbuf_next;
+#[cfg(all(feature = "scrub", not(feature = "no_std")))]
+use super::scrub_slot;
+#[cfg(not(feature = "no_std"))]
+use traits::{RingConsumer, RingProducer};
+#[cfg(not(feature = "no_std"))]
+use rom::ConstCBuf;
+#[cfg(not(feature = "no_std"))]
+use pressure::{BatchPolicy, Pressure, PressureGauge};
+#[cfg(all(feature = "instrument", not(feature = "no_std")))]
+use pressure::OccupancyHistogram;
+#[cfg(not(feature = "no_std"))]
+use super::cbuf_advance;
+#[cfg(all(feature = "std", not(feature = "no_std")))]
+use std::io;
+
+/// Circular Buffer
+///
+/// Turns a slice into a Circular buffer with head and tail indexes.
+///
+/// Elements are always accessed in place through the slice, so
+/// over-aligned `T` (eg. `#[repr(align(64))]`) keeps its alignment in
+/// every buffer variant.
+///
+/// `Debug` shows the queued elements oldest first, not the raw slots.
+#[cfg_attr(feature = "no_std", derive(Debug))]
+pub struct CBuf<'a, T: 'a> {
+    buf: &'a mut [T],
+    ctrl: CBufControl<T>,
+    policy: OverflowPolicy,
+    #[cfg(feature = "stats")]
+    stats: OverflowStats,
+    #[cfg(feature = "instrument")]
+    high_water: usize,
+}
+
+/// Circular Buffer Control
+///
+/// Implements the actual logic of Circular Buffer, but requires passing &[T]
+/// to `get` and `put`.
+///
+/// Layout is stable, so the control block can live in shared memory next
+/// to the slots and be interpreted by C code on another core:
+///
+/// ```c
+/// typedef struct { size_t head; size_t tail; } cbuf_control_t;
+/// ```
+///
+/// With a narrower index type `I` both fields have that type instead (eg.
+/// `uint8_t`). See `head_index` for the encoding of both fields. Accesses
+/// aren't synchronized; the cores need their own handshake (eg. a
+/// mailbox).
+///
+/// `I` is the index type: `u8` or `u16` shrink the control block on
+/// 8/16-bit MCUs, as long as the buffer is shorter than `I`'s tag bit (eg.
+/// at most 127 elements for `u8`; debug builds assert it on every `get`,
+/// `peek` and `put`). `checkpoint`, `rollback` and `diff` need the
+/// default `usize`.
+#[derive(Debug)]
+#[repr(C)]
+pub struct CBufControl<T, I = usize> {
+    head: I,
+    tail: I,
+    phantom: PhantomData<T>,
+}
+
+impl<T: Clone, I: RingIndex> Default for CBufControl<T, I> {
+    fn default() -> CBufControl<T, I> {
+        CBufControl::new()
+    }
+}
+
+impl<T, I: Clone> Clone for CBufControl<T, I> {
+    fn clone(&self) -> CBufControl<T, I> {
+        CBufControl {
+            head: self.head.clone(),
+            tail: self.tail.clone(),
+            phantom: PhantomData,
+        }
+    }
+}
+
+/// Integer type usable for `CBufControl` indexes
+///
+/// Its highest bit is the lap tag, the lower ones hold the slot number,
+/// so `MAX_CAPACITY` is half the range of the type.
+///
+/// Indexes are computed in `usize`, so types wider than it (`u32` on
+/// 16-bit targets) are not implemented.
+pub trait RingIndex: Copy + Eq {
+    /// Zero index
+    const ZERO: Self;
+    /// Highest bit, flipped on every lap
+    const DATA_BIT: Self;
+    /// Largest buffer length usable with this index type
+    const MAX_CAPACITY: usize;
+
+    /// Widen to `usize`
+    fn to_usize(self) -> usize;
+
+    /// Narrow from `usize`; only called with values that fit
+    fn from_usize(val: usize) -> Self;
+}
+
+macro_rules! ring_index {
+    ($($t:ty),*) => {$(
+        impl RingIndex for $t {
+            const ZERO: $t = 0;
+            const DATA_BIT: $t = !(<$t>::max_value() >> 1);
+            const MAX_CAPACITY: usize = (<$t>::max_value() >> 1) as usize;
+
+            #[inline]
+            fn to_usize(self) -> usize {
+                self as usize
+            }
+
+            #[inline]
+            fn from_usize(val: usize) -> $t {
+                val as $t
+            }
+        }
+    )*}
+}
+
+ring_index!(u8, u16, usize);
+#[cfg(not(target_pointer_width = "16"))]
+ring_index!(u32);
+
+/// Advance index of type `I` by one in a buffer of length `len`
+#[inline]
+fn index_next<I: RingIndex>(idx: I, len: usize) -> I {
+    let (idx, bit) = (idx.to_usize() + 1, I::DATA_BIT.to_usize());
+    I::from_usize(if (idx & !bit) >= len { (idx - len) ^ bit } else { idx })
+}
+
+/// Slot number of index of type `I`
+#[inline]
+fn index_slot<I: RingIndex>(idx: I) -> usize {
+    idx.to_usize() & !I::DATA_BIT.to_usize()
+}
+
+/// Saved position of a Circular Buffer
+///
+/// Returned by `checkpoint` and consumed by `rollback`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    head: usize,
+    tail: usize,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> CBuf<'a, T>
+{
+    /// Create new CBuf
+    ///
+    /// Length (not capacity) will be used to store elements
+    /// in the circular buffer.
+    ///
+    /// Zero-sized `T` is supported: only the counters matter then.
+    ///
+    /// panics if buf.len() == 0, or if buf.len() is above `MAX_CAPACITY`
+    /// (only possible for zero-sized `T`, or large buffers on 16-bit
+    /// targets)
+    pub fn new(buf: &'a mut [T]) -> CBuf<'a, T> {
+        if buf.is_empty() {
+            panic!("len==0")
+        }
+        if buf.len() >= CBUF_DATA_BIT {
+            panic!("len too big")
+        }
+
+        CBuf {
+            buf,
+            ctrl: CBufControl::new(),
+            policy: OverflowPolicy::Reject,
+            #[cfg(feature = "stats")]
+            stats: OverflowStats::default(),
+            #[cfg(feature = "instrument")]
+            high_water: 0,
+        }
+    }
+
+    /// Create new CBuf with given behavior of `put` when full
+    ///
+    /// Lets the same driver code build a strict queue or a lossy trace
+    /// buffer. Panics like `new`.
+    pub fn with_policy(buf: &'a mut [T], policy: OverflowPolicy) -> CBuf<'a, T> {
+        let mut cbuf = CBuf::new(buf);
+        cbuf.policy = policy;
+        cbuf
+    }
+
+    /// Behavior of `put` when full
+    #[inline]
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Number of elements `put` lost to a full buffer so far
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn stats(&self) -> OverflowStats {
+        self.stats
+    }
+
+    /// Zero the counters of `stats`, eg. after reporting them
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn reset_stats(&mut self) {
+        self.stats = OverflowStats::default();
+    }
+
+    /// Return the counters of `stats` and zero them
+    ///
+    /// Taking `&mut self` keeps any `put` from landing between the copy and
+    /// the reset, so periodic scraping neither loses nor double-counts
+    /// elements.
+    #[cfg(feature = "stats")]
+    #[inline]
+    pub fn snapshot_and_reset(&mut self) -> OverflowStats {
+        core::mem::take(&mut self.stats)
+    }
+
+    /// Create new CBuf, checking the length instead of panicking
+    ///
+    /// Returns `None` if buf.len() == 0 or buf.len() is above
+    /// `MAX_CAPACITY`.
+    pub fn try_new(buf: &'a mut [T]) -> Option<CBuf<'a, T>> {
+        if buf.is_empty() || buf.len() >= CBUF_DATA_BIT {
+            return None;
+        }
+        Some(CBuf::new(buf))
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Returns an raw pointer to the cbuf's buffer
+    ///
+    /// The caller must ensure that the cbuf outlives the pointer this function
+    /// returns, or else it will end up pointing to garbage.
+    #[deprecated(note = "use `storage_ptr_range`")]
+    #[inline]
+    pub fn as_ptr(&self) -> *const T {
+        self.buf.as_ptr()
+    }
+
+    /// Returns an unsafe mutable pointer to the cbuf's buffer.
+    ///
+    /// The caller must ensure that the cbuf outlives the pointer this function
+    /// returns, or else it will end up pointing to garbage.
+    #[deprecated(note = "use `as_non_null` or `storage_ptr_range`")]
+    #[inline]
+    pub fn as_mut_ptr(&mut self) -> *mut T {
+        self.buf.as_mut_ptr()
+    }
+
+    /// Returns a non-null pointer to the start of the cbuf's buffer
+    ///
+    /// The pointer is derived from the whole backing slice, so it may be
+    /// offset to, read and written at any slot `0..len()` (eg. to register
+    /// the buffer with FFI code or a DMA engine). It stays valid until the
+    /// cbuf is used or moved again.
+    #[inline]
+    pub fn as_non_null(&mut self) -> core::ptr::NonNull<T> {
+        // Slices are never null, even when empty
+        core::ptr::NonNull::from(&mut *self.buf).cast()
+    }
+
+    /// Returns the half-open range of pointers spanning the cbuf's buffer
+    ///
+    /// Same provenance guarantees as `as_non_null`; `end` is one past the
+    /// last slot and must not be dereferenced.
+    #[inline]
+    pub fn storage_ptr_range(&mut self) -> core::ops::Range<*mut T> {
+        self.buf.as_mut_ptr_range()
+    }
+
+    /// Alias of `len`, matching `VecDeque`/`heapless` naming
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.ctrl.count(self.buf.len())
+    }
+
+    /// Number of free slots
+    #[inline]
+    pub fn remaining_capacity(&self) -> usize {
+        self.buf.len() - self.count()
+    }
+
+    /// Classify current occupancy through `gauge`
+    ///
+    /// Call periodically (eg. from the idle loop) to get a power
+    /// management hint; see `PressureGauge`.
+    #[inline]
+    pub fn pressure(&self, gauge: &mut PressureGauge) -> Pressure {
+        gauge.update(self.count(), self.len())
+    }
+
+    /// Highest number of elements the buffer ever held
+    ///
+    /// Raised by every write, so it catches bursts that periodic sampling
+    /// misses.
+    #[cfg(feature = "instrument")]
+    #[inline]
+    pub fn high_water_mark(&self) -> usize {
+        self.high_water
+    }
+
+    /// Restart `high_water_mark` from current occupancy
+    #[cfg(feature = "instrument")]
+    #[inline]
+    pub fn reset_high_water_mark(&mut self) {
+        self.high_water = self.count();
+    }
+
+    /// Add current occupancy to `hist`
+    ///
+    /// Call periodically (eg. from a timer) to see how full the buffer
+    /// usually is; see `OccupancyHistogram`.
+    #[cfg(feature = "instrument")]
+    #[inline]
+    pub fn record_occupancy<const B: usize>(&self, hist: &mut OccupancyHistogram<B>) {
+        hist.record(self.count(), self.len())
+    }
+
+    /// Raise the high-water mark after a write
+    #[inline(always)]
+    fn note_put(&mut self) {
+        #[cfg(feature = "instrument")]
+        {
+            let count = self.count();
+            if count > self.high_water {
+                self.high_water = count;
+            }
+        }
+    }
+
+    /// How many elements to read now, according to `policy`
+    ///
+    /// See `AdaptiveBatch` for the default heuristic.
+    #[inline]
+    pub fn suggested_batch<P: BatchPolicy + ?Sized>(&self, policy: &mut P) -> usize {
+        policy.suggest(self.count(), self.len())
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ctrl.is_full()
+    }
+
+    /// Alias of `is_full`, matching `heapless` naming
+    #[inline]
+    pub fn is_at_capacity(&self) -> bool {
+        self.ctrl.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ctrl.is_empty()
+    }
+
+    /// Oldest element, next to be removed
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn front(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.buf.get(self.ctrl.tail & !CBUF_DATA_BIT)
+    }
+
+    /// Newest element, last put
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn back(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        let head = self.ctrl.head & !CBUF_DATA_BIT;
+        let idx = if head == 0 { self.buf.len() - 1 } else { head - 1 };
+        self.buf.get(idx)
+    }
+
+
+    /// `n`-th oldest element, without removing it
+    ///
+    /// Offset 0 is the next element `get` would return. Returns `None`
+    /// if there are no more than `n` elements. Indexing with `cbuf[n]`
+    /// does the same, but panics instead.
+    #[inline]
+    pub fn get_at(&self, n: usize) -> Option<&T> {
+        self.ctrl.get_at(self.buf, n)
+    }
+
+    /// Mutable `n`-th oldest element, see `get_at`
+    #[inline]
+    pub fn get_at_mut(&mut self, n: usize) -> Option<&mut T> {
+        self.ctrl.get_at_mut(self.buf, n)
+    }
+
+    /// Peek next element from the CBuf without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&mut self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.slot_mut(self.ctrl.tail).map(|slot| &*slot)
+    }
+
+    /// Peek next element from the CBuf without removing it
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    ///
+    /// # Safety
+    ///
+    /// If the buffer is empty, undefined data will be returned.
+    #[cfg(not(feature = "safe"))]
+    #[inline]
+    pub unsafe fn peek_unchecked(&mut self) -> &T {
+        match self.slot_mut(self.ctrl.tail) {
+            Some(slot) => slot,
+            None => core::hint::unreachable_unchecked(),
+        }
+    }
+
+    /// Peek next element from the CBuf without removing it
+    ///
+    /// If the buffer is empty, undefined data will be returned. With
+    /// `safe` feature it's a safe fn whose body checks bounds.
+    #[cfg(feature = "safe")]
+    #[inline]
+    pub fn peek_unchecked(&mut self) -> &T {
+        match self.slot_mut(self.ctrl.tail) {
+            Some(slot) => slot,
+            None => unreachable!(),
+        }
+    }
+
+
+    /// Remove one element from the CBuf
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        self.take_tail()
+    }
+
+    /// Remove one element from the CBuf
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    ///
+    /// # Safety
+    ///
+    /// Makes the buffer misbehave if it's empty.
+    #[cfg(not(feature = "safe"))]
+    #[inline]
+    pub unsafe fn get_unchecked(&mut self) -> T {
+        match self.take_tail() {
+            Some(val) => val,
+            None => core::hint::unreachable_unchecked(),
+        }
+    }
+
+    /// Remove one element from the CBuf
+    ///
+    /// Makes the buffer misbehave if it's empty. With `safe` feature it's a safe fn whose body
+    /// checks bounds.
+    #[cfg(feature = "safe")]
+    #[inline]
+    pub fn get_unchecked(&mut self) -> T {
+        match self.take_tail() {
+            Some(val) => val,
+            None => unreachable!(),
+        }
+    }
+
+    /// Slot at raw index `idx`, whose slot bits are always below buf.len()
+    ///
+    /// Never `None`; with `safe` feature bounds are still checked.
+    #[cfg(not(feature = "safe"))]
+    #[inline(always)]
+    fn slot_mut(&mut self, idx: usize) -> Option<&mut T> {
+        Some(unsafe { self.buf.get_unchecked_mut(idx & !CBUF_DATA_BIT) })
+    }
+
+    /// Slot at raw index `idx`, whose slot bits are always below buf.len()
+    ///
+    /// Never `None`; with `safe` feature bounds are still checked.
+    #[cfg(feature = "safe")]
+    #[inline(always)]
+    fn slot_mut(&mut self, idx: usize) -> Option<&mut T> {
+        self.buf.get_mut(idx & !CBUF_DATA_BIT)
+    }
+
+    /// Remove the oldest element, without checking if there is one
+    #[inline]
+    fn take_tail(&mut self) -> Option<T> {
+        let slot = self.slot_mut(self.ctrl.tail)?;
+        let val = slot.clone();
+        #[cfg(feature = "scrub")]
+        scrub_slot(slot);
+        self.ctrl.tail = cbuf_next(self.ctrl.tail, self.buf.len());
+        Some(val)
+    }
+
+    /// Add element at head, without checking for free space
+    #[inline]
+    fn put_head(&mut self, val: T) {
+        if let Some(slot) = self.slot_mut(self.ctrl.head) {
+            *slot = val;
+        }
+        self.ctrl.head = cbuf_next(self.ctrl.head, self.buf.len());
+        self.note_put();
+    }
+
+    /// Cancel the most recent `put`, removing and returning its element
+    ///
+    /// Write-side inverse of `get`, eg. to withdraw the last queued
+    /// command when an abort arrives. Returns `None` if buffer is empty.
+    #[inline]
+    pub fn revert_put(&mut self) -> Option<T> {
+        let val = self.ctrl.revert_put(self.buf)?;
+        #[cfg(feature = "scrub")]
+        scrub_slot(&mut self.buf[self.ctrl.head & !CBUF_DATA_BIT]);
+        Some(val)
+    }
+
+    /// Iterate over elements, oldest first, without removing them
+    #[inline]
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.ctrl.iter(self.buf)
+    }
+
+    /// Iterate over clones of elements, oldest first, removing only on
+    /// `commit`
+    ///
+    /// Meant for parsers that consume input only once a whole frame
+    /// decoded: drop the cursor to leave everything in place, or call
+    /// `ReadCursor::commit` to remove exactly the elements iterated over.
+    /// Elements are cloned so no reference outlives the cursor, which lets
+    /// `commit` scrub the slots.
+    #[inline]
+    pub fn read_cursor(&mut self) -> ReadCursor<'_, T> {
+        ReadCursor {
+            buf: self.buf,
+            ctrl: &mut self.ctrl,
+            pos: 0,
+        }
+    }
+
+    /// Iterate over the newest `n` elements (or all, if fewer), oldest
+    /// first, without removing them
+    ///
+    /// Eg. for showing the last lines of a log or a sliding window.
+    #[inline]
+    pub fn last_n(&self, n: usize) -> Iter<'_, T> {
+        self.ctrl.last_n(self.buf, n)
+    }
+
+    /// Remove elements while iterating over them, oldest first
+    ///
+    /// Elements not consumed when the iterator is dropped are removed
+    /// too. Iterating from the back removes newest elements first.
+    #[inline]
+    pub fn drain(&mut self) -> Drain<'_, 'a, T> {
+        Drain { cbuf: self }
+    }
+
+    /// Add element the buffer
+    ///
+    /// If buffer is full, does what its `OverflowPolicy` says; by default
+    /// ignores the write.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            match self.policy {
+                OverflowPolicy::Reject => {
+                    #[cfg(feature = "stats")]
+                    {
+                        self.stats.rejected = self.stats.rejected.wrapping_add(1);
+                    }
+                    return;
+                }
+                OverflowPolicy::Overwrite => {
+                    #[cfg(feature = "stats")]
+                    {
+                        self.stats.overwritten = self.stats.overwritten.wrapping_add(1);
+                    }
+                    self.discard(1);
+                }
+                OverflowPolicy::Panic => panic!("buffer full"),
+            }
+        }
+        self.put_head(val)
+    }
+
+    /// Add element the buffer, regardless of `OverflowPolicy`
+    ///
+    /// Returns `Err` with the element if buffer is full.
+    #[inline]
+    pub fn try_put(&mut self, val: T) -> Result<(), T> {
+        if self.is_full() {
+            return Err(val);
+        }
+        self.put_head(val);
+        Ok(())
+    }
+
+    /// Add element the buffer
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    ///
+    /// # Safety
+    ///
+    /// Makes the buffer misbehave if it's full.
+    #[cfg(not(feature = "safe"))]
+    #[inline]
+    pub unsafe fn put_unchecked(&mut self, val: T) {
+        self.put_head(val)
+    }
+
+    /// Add element the buffer
+    ///
+    /// Makes the buffer misbehave if it's full. With `safe` feature it's a safe fn whose body
+    /// checks bounds.
+    #[cfg(feature = "safe")]
+    #[inline]
+    pub fn put_unchecked(&mut self, val: T) {
+        self.put_head(val)
+    }
+
+    /// Add elements from `src` until buffer is full
+    ///
+    /// Copies in at most two parts (up to the end of buf, then from its
+    /// start). Returns number of elements put.
+    pub fn put_slice(&mut self, src: &[T]) -> usize {
+        let mut count = 0;
+        for _ in 0..2 {
+            let part = self.ctrl.free_part(self.buf.len());
+            let n = core::cmp::min(part.len(), src.len() - count);
+            // Checked accesses keep `put_slice` provably panic-free; both
+            // ranges are always in bounds
+            let dst = self.buf.get_mut(part.start..part.start + n);
+            if let (Some(dst), Some(src)) = (dst, src.get(count..count + n)) {
+                dst.clone_from_slice(src);
+            }
+            self.ctrl.head = cbuf_advance(self.ctrl.head, n, self.buf.len());
+            count += n;
+        }
+        self.note_put();
+        count
+    }
+
+    /// Remove elements into `dst` until buffer is empty or `dst` is full
+    ///
+    /// Copies in at most two parts, like `put_slice`. Returns number of
+    /// elements removed.
+    pub fn pop_into(&mut self, dst: &mut [T]) -> usize {
+        let mut count = 0;
+        for _ in 0..2 {
+            let part = self.ctrl.filled_part(self.buf.len());
+            let n = core::cmp::min(part.len(), dst.len() - count);
+            // Checked accesses, like in `put_slice`
+            let src = self.buf.get_mut(part.start..part.start + n);
+            if let (Some(src), Some(dst)) = (src, dst.get_mut(count..count + n)) {
+                dst.clone_from_slice(src);
+                #[cfg(feature = "scrub")]
+                for slot in src.iter_mut() {
+                    scrub_slot(slot);
+                }
+            }
+            self.ctrl.tail = cbuf_advance(self.ctrl.tail, n, self.buf.len());
+            count += n;
+        }
+        count
+    }
+
+    /// Remove up to `n` oldest elements without reading them
+    ///
+    /// Returns number of elements removed. Nothing is cloned and the tail
+    /// moves in one step; with `scrub` the slots are still overwritten one
+    /// by one.
+    pub fn discard(&mut self, n: usize) -> usize {
+        let n = core::cmp::min(n, self.count());
+        #[cfg(feature = "scrub")]
+        for i in 0..n {
+            if let Some(slot) = self.ctrl.get_at_mut(self.buf, i) {
+                scrub_slot(slot);
+            }
+        }
+        self.ctrl.tail = cbuf_advance(self.ctrl.tail, n, self.buf.len());
+        n
+    }
+
+    /// Add elements from each of `srcs` in turn until buffer is full
+    ///
+    /// Scatter-gather version of `put_slice`. Returns total number of
+    /// elements put.
+    pub fn put_vectored(&mut self, srcs: &[&[T]]) -> usize {
+        let mut count = 0;
+        for src in srcs {
+            let n = self.put_slice(src);
+            count += n;
+            if n < src.len() {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Remove elements into each of `dsts` in turn until buffer is empty
+    ///
+    /// Scatter-gather version of `pop_into`. Returns total number of
+    /// elements removed.
+    pub fn pop_into_vectored(&mut self, dsts: &mut [&mut [T]]) -> usize {
+        let mut count = 0;
+        for dst in dsts.iter_mut() {
+            let n = self.pop_into(dst);
+            count += n;
+            if n < dst.len() {
+                break;
+            }
+        }
+        count
+    }
+
+    /// Elements in the buffer as two slices, oldest first
+    ///
+    /// Second slice is non-empty only when contents wrap around the end
+    /// of buf.
+    pub fn as_slices(&self) -> (&[T], &[T]) {
+        self.ctrl.as_slices(self.buf)
+    }
+
+    /// Reserve `n` contiguous free slots to be filled in place
+    ///
+    /// Returns `None` if there aren't `n` free slots in one run starting
+    /// at the back of the buffer (free space wrapping around the end of
+    /// buf is two runs). Nothing is added until `commit`.
+    #[inline]
+    pub fn grant(&mut self, n: usize) -> Option<&mut [T]> {
+        let part = self.ctrl.free_part(self.buf.len());
+        if part.len() < n {
+            return None;
+        }
+        Some(&mut self.buf[part.start..part.start + n])
+    }
+
+    /// Add first `written` elements of the last `grant`
+    ///
+    /// panics if `written` is more than the free run `grant` reserves from
+    #[inline]
+    pub fn commit(&mut self, written: usize) {
+        if written > self.ctrl.free_part(self.buf.len()).len() {
+            panic!("written > granted")
+        }
+        self.ctrl.head = cbuf_advance(self.ctrl.head, written, self.buf.len());
+        self.note_put();
+    }
+
+    /// Contiguous free region for a DMA engine to write into
+    ///
+    /// Returns pointer to the back of the buffer and number of free slots
+    /// following it up to the end of buf. The region never wraps: free
+    /// space past the end of buf continues at its start and is returned by
+    /// the next call after `dma_commit`. Nothing is added until
+    /// `dma_commit`; the CBuf must not be moved or used for writing in the
+    /// meantime.
+    #[inline]
+    pub fn dma_write_region(&mut self) -> (*mut T, usize) {
+        let part = self.ctrl.free_part(self.buf.len());
+        (self.buf[part.start..].as_mut_ptr(), part.len())
+    }
+
+    /// Add `len` elements written by DMA into `dma_write_region`
+    ///
+    /// panics if len is more than the region length
+    #[inline]
+    pub fn dma_commit(&mut self, len: usize) {
+        self.commit(len)
+    }
+
+    /// Free regions, in order, for placing `total_len` elements
+    ///
+    /// Yields `(ptr, len)` pairs ready for scatter-gather DMA descriptors,
+    /// covering `total_len` elements or all free slots, whichever is
+    /// less. Nothing is added until `commit_regions`; the CBuf must not be
+    /// moved or used for writing in the meantime.
+    pub fn write_regions_iter(&mut self, total_len: usize) -> WriteRegions<'_, T> {
+        let len = self.buf.len();
+        WriteRegions {
+            base: self.buf.as_mut_ptr(),
+            len,
+            pos: self.ctrl.head & !CBUF_DATA_BIT,
+            remaining: core::cmp::min(total_len, len - self.ctrl.count(len)),
+            phantom: PhantomData,
+        }
+    }
+
+    /// Add `written` elements placed into `write_regions_iter` regions
+    ///
+    /// panics if `written` is more than the number of free slots
+    #[inline]
+    pub fn commit_regions(&mut self, written: usize) {
+        if written > self.buf.len() - self.count() {
+            panic!("written > free")
+        }
+        self.ctrl.head = cbuf_advance(self.ctrl.head, written, self.buf.len());
+        self.note_put();
+    }
+
+    /// Logical offset of the oldest element matching `pred`
+    ///
+    /// Searches across the end of buf; offset 0 is the next element
+    /// `get` would return.
+    pub fn position<P: FnMut(&T) -> bool>(&self, pred: P) -> Option<usize> {
+        let (a, b) = self.as_slices();
+        a.iter().chain(b).position(pred)
+    }
+
+    /// Read-only view of current contents
+    ///
+    /// Needs only `&self`, so any number of views can be held at once,
+    /// eg. by code that only inspects the buffer. Elements can also be
+    /// played back, eg. with `ConstCBuf::get_looping`, without removing
+    /// them from the CBuf.
+    #[inline]
+    pub fn view(&self) -> ConstCBuf<'_, T> {
+        ConstCBuf::new(self.buf, self.ctrl.tail & !CBUF_DATA_BIT, self.ctrl.count(self.buf.len()))
+    }
+
+    /// Validate head and tail indexes
+    ///
+    /// Cheap enough to call periodically, eg. from a watchdog task, to
+    /// catch memory corruption before it produces bogus elements. Grants
+    /// keep no state of their own, so there's nothing more to check.
+    #[inline]
+    pub fn health_check(&self) -> Result<(), Corruption> {
+        self.ctrl.health_check(self.buf.len())
+    }
+
+    /// Save current head and tail positions
+    #[inline]
+    pub fn checkpoint(&self) -> Checkpoint {
+        self.ctrl.checkpoint()
+    }
+
+    /// Count elements put and removed since `before` was taken
+    ///
+    /// Positions wrap around every `2 * len()` operations, so counts are
+    /// exact only if fewer than that many puts (and removals) happened
+    /// since; plenty for asserting on the effects of code under test.
+    #[inline]
+    pub fn diff(&self, before: &Checkpoint) -> ChangeSet {
+        self.ctrl.diff(before, self.buf.len())
+    }
+
+    /// Restore head and tail positions saved with `checkpoint`
+    ///
+    /// Elements put since the checkpoint are dropped and elements removed
+    /// since become available again. Only positions are restored, not
+    /// contents: if removed slots were reused by `put` in the meantime,
+    /// the new values will be seen.
+    ///
+    /// panics if `checkpoint` was taken from a longer buffer, or, with
+    /// `scrub` feature, if elements were removed since (their slots are
+    /// already scrubbed)
+    #[inline]
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        let len = self.buf.len();
+        if (checkpoint.head & !CBUF_DATA_BIT) >= len || (checkpoint.tail & !CBUF_DATA_BIT) >= len {
+            panic!("checkpoint out of range")
+        }
+        #[cfg(feature = "scrub")]
+        {
+            if checkpoint.tail != self.ctrl.tail {
+                panic!("rollback would restore scrubbed elements")
+            }
+        }
+        self.ctrl.rollback(checkpoint)
+    }
+}
+
+impl<T: Clone, I: RingIndex> CBufControl<T, I> {
+    pub const fn new() -> CBufControl<T, I> {
+        CBufControl {
+            tail: I::ZERO,
+            head: I::ZERO,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Control block with given raw indexes, over existing storage
+    ///
+    /// Reattaches to data queued before, when `head_index` and
+    /// `tail_index` were saved in retained RAM, shared memory or a
+    /// register pair. Returns `Corruption::Index` if they don't describe
+    /// a valid state of a buffer of length `len`.
+    pub fn with_offsets(head: I, tail: I, len: usize) -> Result<CBufControl<T, I>, Corruption> {
+        let ctrl = CBufControl {
+            tail,
+            head,
+            phantom: PhantomData,
+        };
+        ctrl.health_check(len)?;
+        Ok(ctrl)
+    }
+
+    /// Raw head index, where the next element will be put
+    ///
+    /// Highest bit flips on every lap around the buffer; the remaining
+    /// bits are the slot number in `0..len`. Equal head and tail mean
+    /// empty, indexes differing only in the highest bit mean full.
+    #[inline]
+    pub fn head_index(&self) -> I {
+        self.head
+    }
+
+    /// Raw tail index, where the next element will be removed from
+    ///
+    /// See `head_index` for the encoding.
+    #[inline]
+    pub fn tail_index(&self) -> I {
+        self.tail
+    }
+
+    /// Overwrite raw head index, eg. from a DMA controller's counter
+    ///
+    /// # Safety
+    ///
+    /// Slot bits must be below the buffer length and head must be at most
+    /// one lap ahead of tail, else `get`/`put` index out of bounds or
+    /// return stale elements.
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    #[cfg(not(feature = "safe"))]
+    #[inline]
+    pub unsafe fn set_head_index(&mut self, head: I) {
+        self.head = head;
+    }
+
+    /// Overwrite raw head index, eg. from a DMA controller's counter
+    ///
+    /// # Safety
+    ///
+    /// Slot bits must be below the buffer length and head must be at most
+    /// one lap ahead of tail, else `get`/`put` index out of bounds or
+    /// return stale elements.
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    #[cfg(feature = "safe")]
+    #[inline]
+    pub fn set_head_index(&mut self, head: I) {
+        self.head = head;
+    }
+
+    /// Overwrite raw tail index
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `set_head_index`.
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    #[cfg(not(feature = "safe"))]
+    #[inline]
+    pub unsafe fn set_tail_index(&mut self, tail: I) {
+        self.tail = tail;
+    }
+
+    /// Overwrite raw tail index
+    ///
+    /// # Safety
+    ///
+    /// Same requirements as `set_head_index`.
+    ///
+    /// With `safe` feature it's a safe fn whose body checks bounds.
+    #[cfg(feature = "safe")]
+    #[inline]
+    pub fn set_tail_index(&mut self, tail: I) {
+        self.tail = tail;
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        (self.head.to_usize() ^ self.tail.to_usize()) == I::DATA_BIT.to_usize()
+    }
+
+    /// See corresponding method of CBuf
+    pub fn get(&mut self, buf: &[T]) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.get_unchecked(buf))
+    }
+
+    /// See corresponding method of CBuf
+    pub fn get_unchecked(&mut self, buf: &[T]) -> T {
+        Self::debug_check_len(buf.len());
+        let val = buf[index_slot(self.tail)].clone();
+
+        self.tail = index_next(self.tail, buf.len());
+
+        val
+    }
+
+    /// See corresponding method of CBuf
+    pub fn peek<'a>(&mut self, buf: &'a [T]) -> Option<&'a T> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(self.peek_unchecked(buf))
+    }
+
+    /// See corresponding method of CBuf
+    pub fn peek_unchecked<'a>(&mut self, buf: &'a [T]) -> &'a T {
+        Self::debug_check_len(buf.len());
+        &buf[index_slot(self.tail)]
+    }
+
+    /// See corresponding method of CBuf
+    pub fn put(&mut self, buf: &mut [T], val: T) {
+        if self.is_full() {
+            return;
+        }
+        self.put_unchecked(buf, val)
+    }
+
+    /// See corresponding method of CBuf
+    pub fn put_unchecked(&mut self, buf: &mut [T], val: T) {
+        Self::debug_check_len(buf.len());
+        buf[index_slot(self.head)] = val;
+
+        self.head = index_next(self.head, buf.len());
+    }
+
+    /// Lengths above `I::MAX_CAPACITY` would carry into the lap bit
+    #[inline(always)]
+    fn debug_check_len(len: usize) {
+        debug_assert!(len <= I::MAX_CAPACITY, "buf.len() above MAX_CAPACITY of the index type");
+    }
+
+    /// Save the control state, eg. before a speculative read
+    ///
+    /// Like `checkpoint`, but works with any index type.
+    #[inline]
+    pub fn snapshot(&self) -> CBufControl<T, I> {
+        self.clone()
+    }
+
+    /// Go back to a state saved by `snapshot`
+    ///
+    /// Elements read since are queued again; elements put since are
+    /// dropped.
+    #[inline]
+    pub fn restore(&mut self, snapshot: CBufControl<T, I>) {
+        *self = snapshot;
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn health_check(&self, len: usize) -> Result<(), Corruption> {
+        let (head, tail) = (index_slot(self.head), index_slot(self.tail));
+        if head >= len || tail >= len {
+            return Err(Corruption::Index);
+        }
+        // On the same lap head can't be behind tail, one lap ahead it
+        // can't have passed it
+        let same_lap = (self.head.to_usize() ^ self.tail.to_usize()) & I::DATA_BIT.to_usize() == 0;
+        if (same_lap && head < tail) || (!same_lap && head > tail) {
+            return Err(Corruption::Index);
+        }
+        Ok(())
+    }
+}
+
+/// `const fn` operations, eg. to build replay tables at compile time
+///
+/// ```
+/// use cbuf::CBufControl;
+///
+/// const TABLE: [u8; 3] = {
+///     let mut buf = [0u8; 3];
+///     let mut ctrl = CBufControl::<u8>::new();
+///     ctrl.const_put(&mut buf, 1);
+///     ctrl.const_put(&mut buf, 2);
+///     ctrl.const_get(&buf);
+///     ctrl.const_put(&mut buf, 3);
+///     ctrl.const_put(&mut buf, 4);
+///     buf
+/// };
+/// assert_eq!(TABLE, [4, 2, 3]);
+/// ```
+impl<T: Copy> CBufControl<T> {
+    /// `is_empty` usable in const contexts
+    #[inline]
+    pub const fn const_is_empty(&self) -> bool {
+        self.head == self.tail
+    }
+
+    /// `is_full` usable in const contexts
+    #[inline]
+    pub const fn const_is_full(&self) -> bool {
+        (self.head ^ self.tail) == CBUF_DATA_BIT
+    }
+
+    /// `get` usable in const contexts
+    #[inline]
+    pub const fn const_get(&mut self, buf: &[T]) -> Option<T> {
+        if self.const_is_empty() {
+            return None;
+        }
+        let val = buf[self.tail & !CBUF_DATA_BIT];
+        self.tail = ::cbuf_next(self.tail, buf.len());
+        Some(val)
+    }
+
+    /// `put` usable in const contexts
+    #[inline]
+    pub const fn const_put(&mut self, buf: &mut [T], val: T) {
+        if self.const_is_full() {
+            return;
+        }
+        buf[self.head & !CBUF_DATA_BIT] = val;
+        self.head = ::cbuf_next(self.head, buf.len());
+    }
+}
+
+impl<T: Clone> CBufControl<T> {
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            head: self.head,
+            tail: self.tail,
+        }
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        self.head = checkpoint.head;
+        self.tail = checkpoint.tail;
+    }
+
+    /// See corresponding method of CBuf
+    pub fn diff(&self, before: &Checkpoint, len: usize) -> ChangeSet {
+        // Position in 0..2*len, the tag bit standing for one lap
+        let pos = |idx: usize| if idx & CBUF_DATA_BIT == 0 {
+            idx
+        } else {
+            (idx & !CBUF_DATA_BIT) + len
+        };
+        let dist = |from: usize, to: usize| if pos(to) >= pos(from) {
+            pos(to) - pos(from)
+        } else {
+            2 * len - (pos(from) - pos(to))
+        };
+        ChangeSet {
+            added: dist(before.head, self.head),
+            removed: dist(before.tail, self.tail),
+        }
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn get_at<'a>(&self, buf: &'a [T], n: usize) -> Option<&'a T> {
+        self.slot_at(n, buf.len()).and_then(|slot| buf.get(slot))
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn get_at_mut<'a>(&self, buf: &'a mut [T], n: usize) -> Option<&'a mut T> {
+        self.slot_at(n, buf.len()).and_then(move |slot| buf.get_mut(slot))
+    }
+
+    /// See corresponding method of CBuf
+    pub fn revert_put(&mut self, buf: &[T]) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let slot = self.head & !CBUF_DATA_BIT;
+        self.head = if slot == 0 {
+            (self.head & CBUF_DATA_BIT ^ CBUF_DATA_BIT) | (buf.len() - 1)
+        } else {
+            self.head - 1
+        };
+        Some(buf[self.head & !CBUF_DATA_BIT].clone())
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn iter<'a>(&self, buf: &'a [T]) -> Iter<'a, T> {
+        let (a, b) = self.as_slices(buf);
+        Iter {
+            first: a.iter(),
+            second: b.iter(),
+        }
+    }
+
+    /// See corresponding method of CBuf
+    #[inline]
+    pub fn last_n<'a>(&self, buf: &'a [T], n: usize) -> Iter<'a, T> {
+        let (a, b) = self.as_slices(buf);
+        let skip = (a.len() + b.len()).saturating_sub(n);
+        if skip >= a.len() {
+            Iter {
+                first: b[skip - a.len()..].iter(),
+                second: [].iter(),
+            }
+        } else {
+            Iter {
+                first: a[skip..].iter(),
+                second: b.iter(),
+            }
+        }
+    }
+
+    /// Slot of the `n`-th oldest element in a buffer of length `len`
+    #[inline]
+    fn slot_at(&self, n: usize, len: usize) -> Option<usize> {
+        if n >= self.count(len) {
+            return None;
+        }
+        let slot = (self.tail & !CBUF_DATA_BIT) + n;
+        Some(if slot >= len { slot - len } else { slot })
+    }
+
+    /// Elements in `buf` as two slices, oldest first
+    #[inline]
+    pub fn as_slices<'b>(&self, buf: &'b [T]) -> (&'b [T], &'b [T]) {
+        let first = self.filled_part(buf.len());
+        let second_len = self.count(buf.len()) - first.len();
+        (buf.get(first).unwrap_or(&[]), buf.get(..second_len).unwrap_or(&[]))
+    }
+
+    /// Number of elements in a buffer of length `len`
+    #[inline]
+    pub fn count(&self, len: usize) -> usize {
+        let (head, tail) = (self.head & !CBUF_DATA_BIT, self.tail & !CBUF_DATA_BIT);
+        if (self.head ^ self.tail) & CBUF_DATA_BIT == 0 {
+            head - tail
+        } else {
+            len - tail + head
+        }
+    }
+
+    /// Slot range of filled elements starting at tail, up to the end of buf
+    #[inline]
+    fn filled_part(&self, len: usize) -> core::ops::Range<usize> {
+        let tail = self.tail & !CBUF_DATA_BIT;
+        if (self.head ^ self.tail) & CBUF_DATA_BIT == 0 {
+            tail..(self.head & !CBUF_DATA_BIT)
+        } else {
+            tail..len
+        }
+    }
+
+    /// Slot range of free slots starting at head, up to the end of buf
+    #[cfg(not(feature = "no_std"))]
+    #[inline]
+    fn free_part(&self, len: usize) -> core::ops::Range<usize> {
+        let head = self.head & !CBUF_DATA_BIT;
+        if (self.head ^ self.tail) & CBUF_DATA_BIT == 0 {
+            head..len
+        } else {
+            head..(self.tail & !CBUF_DATA_BIT)
+        }
+    }
+}
+
+/// Elements added and removed between a `Checkpoint` and now
+///
+/// Returned by `CBuf::diff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChangeSet {
+    /// Number of elements put
+    pub added: usize,
+    /// Number of elements removed
+    pub removed: usize,
+}
+
+/// Error of all-or-nothing writes: data doesn't fit in the free space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct NoSpace;
+
+/// Error of `health_check`: ring state is inconsistent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Corruption {
+    /// Head or tail index is invalid
+    Index,
+    /// Copies of the element at this logical offset differ
+    Slot(usize),
+}
+
+/// What `CBuf::put` does when the buffer is full
+///
+/// Set with `CBuf::with_policy`. Only `put` (and the `RingProducer` impl)
+/// follows it; slice and grant methods always stop at the free space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverflowPolicy {
+    /// Ignore the new element
+    #[default]
+    Reject,
+    /// Drop the oldest element to make room, eg. for trace buffers
+    Overwrite,
+    /// Panic, for queues that must never fill up
+    Panic,
+}
+
+/// Elements lost by `CBuf::put` on a full buffer, returned by `CBuf::stats`
+///
+/// Counters wrap on overflow. Short `put_slice` and `grant` writes aren't
+/// counted: their callers see how much was taken.
+#[cfg(feature = "stats")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct OverflowStats {
+    /// New elements ignored under `OverflowPolicy::Reject`
+    pub rejected: u64,
+    /// Old elements dropped under `OverflowPolicy::Overwrite`
+    pub overwritten: u64,
+}
+
+/// Order in which `CBuf::clear_in_order` releases elements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropOrder {
+    /// Oldest element first
+    Fifo,
+    /// Newest element first
+    Reverse,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> CBuf<'a, T> {
+    /// Remove all elements
+    ///
+    /// Slots are left as they are, unless `scrub` overwrites them; see
+    /// `clear_in_order` for releasing resources held by elements.
+    #[inline]
+    pub fn clear(&mut self) {
+        #[cfg(feature = "scrub")]
+        for i in 0..self.count() {
+            if let Some(slot) = self.ctrl.get_at_mut(self.buf, i) {
+                scrub_slot(slot);
+            }
+        }
+        self.ctrl.tail = self.ctrl.head;
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Copy> CBuf<'a, T> {
+    /// `put_slice` for `Copy` elements, copying each part with one memcpy
+    ///
+    /// `put_slice` clones one element at a time and relies on the optimizer
+    /// to merge that into a memcpy, which doesn't happen in debug or
+    /// size-optimized builds.
+    pub fn put_slice_copy(&mut self, src: &[T]) -> usize {
+        let mut count = 0;
+        for _ in 0..2 {
+            let part = self.ctrl.free_part(self.buf.len());
+            let n = core::cmp::min(part.len(), src.len() - count);
+            self.buf[part.start..part.start + n].copy_from_slice(&src[count..count + n]);
+            self.ctrl.head = cbuf_advance(self.ctrl.head, n, self.buf.len());
+            count += n;
+        }
+        self.note_put();
+        count
+    }
+
+    /// `pop_into` for `Copy` elements, copying each part with one memcpy
+    pub fn pop_into_copy(&mut self, dst: &mut [T]) -> usize {
+        let mut count = 0;
+        for _ in 0..2 {
+            let part = self.ctrl.filled_part(self.buf.len());
+            let n = core::cmp::min(part.len(), dst.len() - count);
+            let src = &mut self.buf[part.start..part.start + n];
+            dst[count..count + n].copy_from_slice(src);
+            #[cfg(feature = "scrub")]
+            for slot in src.iter_mut() {
+                scrub_slot(slot);
+            }
+            self.ctrl.tail = cbuf_advance(self.ctrl.tail, n, self.buf.len());
+            count += n;
+        }
+        count
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone + Default> CBuf<'a, T> {
+    /// Remove all elements, dropping them in given order
+    ///
+    /// Every slot holding an element is replaced with `T::default()`, so
+    /// whatever the elements own (eg. lock guards) is released right away
+    /// and in a well-defined order.
+    pub fn clear_in_order(&mut self, order: DropOrder) {
+        let count = self.count();
+        let tail = self.ctrl.tail & !CBUF_DATA_BIT;
+        for i in 0..count {
+            let mut idx = tail + match order {
+                DropOrder::Fifo => i,
+                DropOrder::Reverse => count - 1 - i,
+            };
+            if idx >= self.buf.len() {
+                idx -= self.buf.len();
+            }
+            drop(core::mem::take(&mut self.buf[idx]));
+        }
+        self.clear();
+    }
+
+    /// Move all elements, oldest first, into `dst`
+    ///
+    /// Like `clear_in_order` elements are moved out of their slots (not
+    /// cloned), leaving `T::default()` behind, or `SCRUB_PATTERN` with
+    /// `scrub`.
+    pub fn drain_into<E: Extend<T>>(&mut self, dst: &mut E) {
+        dst.extend(core::iter::from_fn(|| {
+            if self.is_empty() {
+                return None;
+            }
+            let idx = self.ctrl.tail & !CBUF_DATA_BIT;
+            self.ctrl.tail = cbuf_next(self.ctrl.tail, self.buf.len());
+            let val = core::mem::take(&mut self.buf[idx]);
+            #[cfg(feature = "scrub")]
+            scrub_slot(&mut self.buf[idx]);
+            Some(val)
+        }))
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone + core::fmt::Debug> core::fmt::Debug for CBuf<'a, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("CBuf")
+            .field("contents", &::DebugList(self.iter()))
+            .field("count", &self.count())
+            .field("capacity", &self.len())
+            .finish()
+    }
+}
+
+/// `n`-th oldest element, panics if there are no more than `n` elements
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> core::ops::Index<usize> for CBuf<'a, T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, n: usize) -> &T {
+        match self.get_at(n) {
+            Some(val) => val,
+            None => panic!("index {} out of range for {} elements", n, self.count()),
+        }
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> core::ops::IndexMut<usize> for CBuf<'a, T> {
+    #[inline]
+    fn index_mut(&mut self, n: usize) -> &mut T {
+        let count = self.count();
+        match self.get_at_mut(n) {
+            Some(val) => val,
+            None => panic!("index {} out of range for {} elements", n, count),
+        }
+    }
+}
+
+#[cfg(all(feature = "defmt", not(feature = "no_std")))]
+impl<'a, T: Clone + defmt::Format> defmt::Format for CBuf<'a, T> {
+    fn format(&self, f: defmt::Formatter) {
+        let (a, b) = self.as_slices();
+        ::defmt_ring(f, "CBuf", a.iter().chain(b), self.count(), self.len())
+    }
+}
+
+/// Compares queued elements, not raw slots or indexes
+#[cfg(not(feature = "no_std"))]
+impl<'a, 'b, T: Clone + PartialEq<U>, U: Clone> PartialEq<CBuf<'b, U>> for CBuf<'a, T> {
+    fn eq(&self, other: &CBuf<'b, U>) -> bool {
+        let (a, b) = other.as_slices();
+        self.count() == other.count() && self.eq_iter(a.iter().chain(b))
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone + Eq> Eq for CBuf<'a, T> {}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone + PartialEq<U>, U> PartialEq<[U]> for CBuf<'a, T> {
+    fn eq(&self, other: &[U]) -> bool {
+        self.count() == other.len() && self.eq_iter(other.iter())
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, 'r, T: Clone + PartialEq<U>, U> PartialEq<&'r [U]> for CBuf<'a, T> {
+    fn eq(&self, other: &&'r [U]) -> bool {
+        *self == **other
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone + PartialEq<U>, U, const N: usize> PartialEq<[U; N]> for CBuf<'a, T> {
+    fn eq(&self, other: &[U; N]) -> bool {
+        *self == other[..]
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> CBuf<'a, T> {
+    /// Do queued elements match `other`, assuming equal counts?
+    fn eq_iter<'r, U: 'r, I: Iterator<Item = &'r U>>(&self, other: I) -> bool
+        where T: PartialEq<U>
+    {
+        let (a, b) = self.as_slices();
+        a.iter().chain(b).zip(other).all(|(x, y)| x == y)
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> RingProducer<T> for CBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        CBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        CBuf::put(self, val)
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a, T: Clone> RingConsumer<T> for CBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        CBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        CBuf::get(self)
+    }
+}
+
+/// Pops bytes; `Ok(0)` means the buffer is empty
+#[cfg(all(feature = "std", not(feature = "no_std")))]
+impl<'a> io::Read for CBuf<'a, u8> {
+    fn read(&mut self, dst: &mut [u8]) -> io::Result<usize> {
+        Ok(self.pop_into(dst))
+    }
+
+    fn read_vectored(&mut self, dsts: &mut [io::IoSliceMut]) -> io::Result<usize> {
+        let mut count = 0;
+        for dst in dsts.iter_mut() {
+            let n = self.pop_into(dst);
+            count += n;
+            if n < dst.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
+}
+
+/// Borrows the contiguous run of bytes at the front, without copying
+///
+/// `fill_buf` returns an empty slice only when the buffer is empty. Once
+/// the run up to the end of buf is consumed, the next `fill_buf` returns
+/// the bytes wrapped around to its start.
+#[cfg(all(feature = "std", not(feature = "no_std")))]
+impl<'a> io::BufRead for CBuf<'a, u8> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        let part = self.ctrl.filled_part(self.buf.len());
+        Ok(&self.buf[part])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let part = self.ctrl.filled_part(self.buf.len());
+        let n = core::cmp::min(part.len(), amt);
+        #[cfg(feature = "scrub")]
+        for b in self.buf[part.start..part.start + n].iter_mut() {
+            *b = ::SCRUB_PATTERN;
+        }
+        self.ctrl.tail = cbuf_advance(self.ctrl.tail, n, self.buf.len());
+    }
+}
+
+/// Pushes bytes; `Ok(0)` means the buffer is full
+#[cfg(all(feature = "std", not(feature = "no_std")))]
+impl<'a> io::Write for CBuf<'a, u8> {
+    fn write(&mut self, src: &[u8]) -> io::Result<usize> {
+        Ok(self.put_slice(src))
+    }
+
+    fn write_vectored(&mut self, srcs: &[io::IoSlice]) -> io::Result<usize> {
+        let mut count = 0;
+        for src in srcs {
+            let n = self.put_slice(src);
+            count += n;
+            if n < src.len() {
+                break;
+            }
+        }
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'a> CBuf<'a, u8> {
+    /// Logical offset of the oldest `byte`, eg. a delimiter or sync word
+    ///
+    /// See `position`.
+    #[inline]
+    pub fn find(&self, byte: u8) -> Option<usize> {
+        let (a, b) = self.as_slices();
+        memchr(byte, a).or_else(|| memchr(byte, b).map(|pos| a.len() + pos))
+    }
+
+    /// Remove one line ending with `delim` (included), copying it to `out`
+    ///
+    /// Returns `None` and removes nothing if there's no complete line yet;
+    /// otherwise the line length, which may exceed `out.len()` in which
+    /// case the rest of the line is discarded. A full buffer without
+    /// `delim` will never yield a line; check `is_full` to detect it.
+    pub fn read_until(&mut self, delim: u8, out: &mut [u8]) -> Option<usize> {
+        let len = self.find(delim)? + 1;
+        let n = core::cmp::min(len, out.len());
+        self.pop_into(&mut out[..n]);
+        self.discard(len - n);
+        Some(len)
+    }
+
+    /// Iterator removing complete `\n`-terminated lines
+    ///
+    /// Every line is returned without the `\n`, as two slices since it
+    /// may wrap around the end of buf. A partial last line stays queued.
+    /// Removed slots are not scrubbed.
+    pub fn lines(&mut self) -> Lines<'_> {
+        Lines {
+            buf: &*self.buf,
+            ctrl: &mut self.ctrl,
+        }
+    }
+}
+
+/// Offset of the first `byte` in `hay`
+///
+/// With `simd` feature compares 16 bytes at a time.
+#[cfg(not(feature = "no_std"))]
+fn memchr(byte: u8, hay: &[u8]) -> Option<usize> {
+    #[cfg(feature = "simd")]
+    {
+        use core::simd::prelude::*;
+
+        let needle = u8x16::splat(byte);
+        let mut chunks = hay.chunks_exact(16);
+        for (i, chunk) in chunks.by_ref().enumerate() {
+            let mask = u8x16::from_slice(chunk).simd_eq(needle).to_bitmask();
+            if mask != 0 {
+                return Some(i * 16 + mask.trailing_zeros() as usize);
+            }
+        }
+        let done = hay.len() - chunks.remainder().len();
+        chunks.remainder().iter().position(|&x| x == byte).map(|pos| done + pos)
+    }
+    #[cfg(not(feature = "simd"))]
+    hay.iter().position(|&x| x == byte)
+}
+
+/// Iterator returned by `CBuf::iter`
+pub struct Iter<'r, T: 'r> {
+    first: core::slice::Iter<'r, T>,
+    second: core::slice::Iter<'r, T>,
+}
+
+impl<'r, T> Iter<'r, T> {
+    /// Iterate over `first`, then `second`, eg. the two parts of a ring
+    #[inline]
+    pub fn new(first: &'r [T], second: &'r [T]) -> Iter<'r, T> {
+        Iter {
+            first: first.iter(),
+            second: second.iter(),
+        }
+    }
+}
+
+impl<'r, T> Iterator for Iter<'r, T> {
+    type Item = &'r T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'r T> {
+        self.first.next().or_else(|| self.second.next())
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.first.len() + self.second.len();
+        (len, Some(len))
+    }
+}
+
+impl<'r, T> DoubleEndedIterator for Iter<'r, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<&'r T> {
+        self.second.next_back().or_else(|| self.first.next_back())
+    }
+}
+
+impl<'r, T> ExactSizeIterator for Iter<'r, T> {}
+
+impl<'r, T> Clone for Iter<'r, T> {
+    fn clone(&self) -> Iter<'r, T> {
+        Iter {
+            first: self.first.clone(),
+            second: self.second.clone(),
+        }
+    }
+}
+
+impl<'r, T> core::iter::FusedIterator for Iter<'r, T> {}
+
+/// Iterator returned by `CBuf::read_cursor`
+#[cfg(not(feature = "no_std"))]
+pub struct ReadCursor<'r, T: 'r> {
+    buf: &'r mut [T],
+    ctrl: &'r mut CBufControl<T>,
+    pos: usize,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, T: Clone> ReadCursor<'r, T> {
+    /// Number of elements iterated over so far
+    #[inline]
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Remove the elements iterated over so far
+    #[inline]
+    pub fn commit(self) {
+        #[cfg(feature = "scrub")]
+        for i in 0..self.pos {
+            if let Some(slot) = self.ctrl.get_at_mut(self.buf, i) {
+                scrub_slot(slot);
+            }
+        }
+        self.ctrl.tail = cbuf_advance(self.ctrl.tail, self.pos, self.buf.len());
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, T: Clone> Iterator for ReadCursor<'r, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        let val = self.ctrl.get_at(self.buf, self.pos)?.clone();
+        self.pos += 1;
+        Some(val)
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.ctrl.count(self.buf.len()) - self.pos;
+        (len, Some(len))
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, T: Clone> ExactSizeIterator for ReadCursor<'r, T> {}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, T: Clone> core::iter::FusedIterator for ReadCursor<'r, T> {}
+
+/// Iterator returned by `CBuf::drain`
+#[cfg(not(feature = "no_std"))]
+pub struct Drain<'r, 'a: 'r, T: 'a + Clone> {
+    cbuf: &'r mut CBuf<'a, T>,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, 'a, T: Clone> Iterator for Drain<'r, 'a, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.cbuf.get()
+    }
+
+    #[inline]
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.cbuf.count();
+        (len, Some(len))
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, 'a, T: Clone> DoubleEndedIterator for Drain<'r, 'a, T> {
+    #[inline]
+    fn next_back(&mut self) -> Option<T> {
+        self.cbuf.revert_put()
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, 'a, T: Clone> ExactSizeIterator for Drain<'r, 'a, T> {}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, 'a, T: Clone> core::iter::FusedIterator for Drain<'r, 'a, T> {}
+
+/// Removes elements that weren't consumed
+#[cfg(not(feature = "no_std"))]
+impl<'r, 'a, T: Clone> Drop for Drain<'r, 'a, T> {
+    fn drop(&mut self) {
+        while self.cbuf.get().is_some() {}
+    }
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, 'a, T: Clone> IntoIterator for &'r CBuf<'a, T> {
+    type Item = &'r T;
+    type IntoIter = Iter<'r, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'r, T> {
+        self.iter()
+    }
+}
+
+/// Iterator returned by `CBuf::write_regions_iter`
+#[cfg(not(feature = "no_std"))]
+pub struct WriteRegions<'r, T: 'r> {
+    base: *mut T,
+    len: usize,
+    pos: usize,
+    remaining: usize,
+    phantom: PhantomData<&'r mut [T]>,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r, T> Iterator for WriteRegions<'r, T> {
+    type Item = (*mut T, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let n = core::cmp::min(self.remaining, self.len - self.pos);
+        let region = (self.base.wrapping_add(self.pos), n);
+        self.pos = 0;
+        self.remaining -= n;
+        Some(region)
+    }
+}
+
+/// Iterator returned by `CBuf::lines`
+#[cfg(not(feature = "no_std"))]
+pub struct Lines<'r> {
+    buf: &'r [u8],
+    ctrl: &'r mut CBufControl<u8>,
+}
+
+#[cfg(not(feature = "no_std"))]
+impl<'r> Iterator for Lines<'r> {
+    type Item = (&'r [u8], &'r [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (a, b) = self.ctrl.as_slices(self.buf);
+        let pos = memchr(b'\n', a).or_else(|| memchr(b'\n', b).map(|pos| a.len() + pos))?;
+        self.ctrl.tail = cbuf_advance(self.ctrl.tail, pos + 1, self.buf.len());
+        if pos < a.len() {
+            Some((&a[..pos], &[]))
+        } else {
+            Some((a, &b[..pos - a.len()]))
+        }
+    }
+}
+
+/// Renders formatted text into the buffer, eg. with `write!`
+///
+/// Text that doesn't fit is cut off and `fmt::Error` returned.
+#[cfg(not(feature = "no_std"))]
+impl<'a> core::fmt::Write for CBuf<'a, u8> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        if self.put_slice(s.as_bytes()) == s.len() {
+            Ok(())
+        } else {
+            Err(core::fmt::Error)
+        }
+    }
+}
+
+/// Consumer view: `chunk` is the contiguous run of bytes at the front
+#[cfg(all(feature = "bytes", not(feature = "no_std")))]
+impl<'a> bytes::Buf for CBuf<'a, u8> {
+    #[inline]
+    fn remaining(&self) -> usize {
+        self.count()
+    }
+
+    #[inline]
+    fn chunk(&self) -> &[u8] {
+        &self.buf[self.ctrl.filled_part(self.buf.len())]
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        if cnt > self.count() {
+            panic!("cnt > remaining")
+        }
+        #[cfg(feature = "scrub")]
+        for _ in 0..cnt {
+            let idx = self.ctrl.tail & !CBUF_DATA_BIT;
+            self.buf[idx] = ::SCRUB_PATTERN;
+            self.ctrl.tail = cbuf_next(self.ctrl.tail, self.buf.len());
+        }
+        #[cfg(not(feature = "scrub"))]
+        {
+            self.ctrl.tail = cbuf_advance(self.ctrl.tail, cnt, self.buf.len());
+        }
+    }
+}
+
+/// Producer view: `chunk_mut` is the contiguous run of free slots at the
+/// back
+#[cfg(all(feature = "bytes", not(feature = "no_std")))]
+unsafe impl<'a> bytes::BufMut for CBuf<'a, u8> {
+    #[inline]
+    fn remaining_mut(&self) -> usize {
+        self.len() - self.count()
+    }
+
+    #[inline]
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        if cnt > self.remaining_mut() {
+            panic!("cnt > remaining_mut")
+        }
+        self.ctrl.head = cbuf_advance(self.ctrl.head, cnt, self.buf.len());
+        self.note_put();
+    }
+
+    #[inline]
+    fn chunk_mut(&mut self) -> &mut bytes::buf::UninitSlice {
+        let part = self.ctrl.free_part(self.buf.len());
+        bytes::buf::UninitSlice::new(&mut self.buf[part])
+    }
+}
+
+/// Error of the `embedded_io` impls of `CBuf<u8>`: nothing could be moved
+///
+/// `embedded_io` reserves `Ok(0)` for end of stream and forbids it from
+/// `write` of a non-empty slice, so an empty or full buffer is an error.
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum WouldBlock {
+    /// `read` found the buffer empty
+    Empty,
+    /// `write` found the buffer full
+    Full,
+}
+
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+impl embedded_io::Error for WouldBlock {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        match *self {
+            WouldBlock::Empty => embedded_io::ErrorKind::Other,
+            WouldBlock::Full => embedded_io::ErrorKind::WriteZero,
+        }
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+impl<'a> embedded_io::ErrorType for CBuf<'a, u8> {
+    type Error = WouldBlock;
+}
+
+/// Pops bytes without blocking; `WouldBlock::Empty` if there are none
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+impl<'a> embedded_io::Read for CBuf<'a, u8> {
+    fn read(&mut self, dst: &mut [u8]) -> Result<usize, Self::Error> {
+        if !dst.is_empty() && self.is_empty() {
+            return Err(WouldBlock::Empty);
+        }
+        Ok(self.pop_into(dst))
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+impl<'a> embedded_io::ReadReady for CBuf<'a, u8> {
+    fn read_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_empty())
+    }
+}
+
+/// Pushes bytes without blocking; `WouldBlock::Full` if none fit, so
+/// `write_all` fails with it instead of looping
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+impl<'a> embedded_io::Write for CBuf<'a, u8> {
+    fn write(&mut self, src: &[u8]) -> Result<usize, Self::Error> {
+        if !src.is_empty() && self.is_full() {
+            return Err(WouldBlock::Full);
+        }
+        Ok(self.put_slice(src))
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(all(feature = "embedded-io", not(feature = "no_std")))]
+impl<'a> embedded_io::WriteReady for CBuf<'a, u8> {
+    fn write_ready(&mut self) -> Result<bool, Self::Error> {
+        Ok(!self.is_full())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use test::Bencher;
+    use test;
+    use std;
+
+    #[test]
+    fn basic_ctl() {
+        let buf = &mut [0u8, 2];
+        let mut cbuf = CBufControl::<u8>::new();
+
+        assert!(cbuf.is_empty());
+        assert!(!cbuf.is_full());
+
+        cbuf.put(buf, 3);
+        cbuf.put(buf, 4);
+        cbuf.put(buf, 42); // will have no effect
+        cbuf.put(buf, 42); // will have no effect
+        assert!(!cbuf.is_empty());
+        assert!(cbuf.is_full());
+
+        assert_eq!(cbuf.peek(buf).unwrap(), &3);
+        cbuf.peek(buf).unwrap();
+        assert!(!cbuf.is_empty());
+        assert!(cbuf.is_full());
+
+        assert_eq!(cbuf.get(buf).unwrap(), 3);
+        assert_eq!(cbuf.get(buf).unwrap(), 4);
+        assert!(cbuf.is_empty());
+        assert!(!cbuf.is_full());
+
+        assert!(cbuf.get(buf).is_none());
+        assert!(cbuf.get(buf).is_none());
+        cbuf.put(buf, 42);
+        assert_eq!(cbuf.get(buf).unwrap(), 42);
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn raw_indexes() {
+        let buf = &mut [0u8; 3];
+        let mut ctl = CBufControl::<u8>::new();
+
+        ctl.put(buf, 1);
+        ctl.put(buf, 2);
+        assert_eq!((ctl.head_index(), ctl.tail_index()), (2, 0));
+        ctl.get(buf);
+        ctl.put(buf, 3);
+        assert_eq!(ctl.head_index(), CBUF_DATA_BIT);
+
+        // hardware wrote one more element behind our back
+        buf[0] = 4;
+        unsafe { ctl.set_head_index(CBUF_DATA_BIT | 1) };
+        assert!(ctl.is_full());
+        assert_eq!(ctl.get(buf), Some(2));
+        assert_eq!(ctl.get(buf), Some(3));
+        assert_eq!(ctl.get(buf), Some(4));
+        unsafe { ctl.set_tail_index(CBUF_DATA_BIT) };
+        assert_eq!(ctl.get(buf), Some(4));
+        assert!(ctl.is_empty());
+    }
+
+    #[test]
+    fn basic_cbuf() {
+        let buf = &mut [0u8, 0u8];
+        let mut cbuf = CBuf::new(buf);
+
+        assert!(cbuf.len() == 2);
+        assert!(cbuf.is_empty());
+        assert!(!cbuf.is_full());
+
+        cbuf.put(3);
+        cbuf.put(4);
+        cbuf.put(42); // will have no effect
+        cbuf.put(42); // will have no effect
+        assert!(!cbuf.is_empty());
+        assert!(cbuf.is_full());
+
+        assert_eq!(cbuf.peek().unwrap(), &3);
+        cbuf.peek().unwrap();
+        assert!(!cbuf.is_empty());
+        assert!(cbuf.is_full());
+
+        assert_eq!(cbuf.get().unwrap(), 3);
+        assert_eq!(cbuf.get().unwrap(), 4);
+        assert!(cbuf.is_empty());
+        assert!(!cbuf.is_full());
+
+        assert!(cbuf.get().is_none());
+        assert!(cbuf.get().is_none());
+        cbuf.put(42);
+        assert_eq!(cbuf.get().unwrap(), 42);
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    #[allow(deprecated)]
+    fn test_ptr() {
+        let buf = &mut [0u8, 0u8];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put(3);
+        cbuf.put(4);
+        unsafe {
+            let b = cbuf.as_ptr();
+            assert_eq!(*b, 3);
+            assert_eq!(*b.offset(1), 4);
+
+            let b = cbuf.as_mut_ptr();
+            *b.offset(1) = 42;
+        }
+        assert_eq!(cbuf.get().unwrap(), 3);
+        assert_eq!(cbuf.get().unwrap(), 42);
+    }
+
+    #[test]
+    #[cfg(feature = "scrub")]
+    fn scrub() {
+        let buf = &mut [0u32, 0u32];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put(3);
+        cbuf.put(4);
+        assert_eq!(cbuf.get().unwrap(), 3);
+        unsafe {
+            let b = cbuf.as_non_null();
+            assert_eq!(*b.as_ptr(), 0xdededede);
+            assert_eq!(*b.as_ptr().offset(1), 4);
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn test_ptr_range() {
+        let buf = &mut [0u16, 0u16, 0u16];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put(3);
+        cbuf.put(4);
+
+        let range = cbuf.storage_ptr_range();
+        assert_eq!(range.end as usize - range.start as usize, 6);
+        assert_eq!(range.start, cbuf.as_non_null().as_ptr());
+        unsafe {
+            assert_eq!(*range.start, 3);
+            *range.start.offset(1) = 42;
+        }
+        assert_eq!(cbuf.get().unwrap(), 3);
+        assert_eq!(cbuf.get().unwrap(), 42);
+    }
+
+    #[test]
+    #[cfg(feature = "scrub")]
+    fn scrub_clear_and_commit() {
+        let buf = &mut [0u16; 4];
+        {
+            let mut cbuf = CBuf::new(buf);
+            cbuf.put_slice(&[1, 2, 3]);
+            let mut cursor = cbuf.read_cursor();
+            assert_eq!(cursor.next(), Some(1));
+            cursor.commit();
+            cbuf.clear();
+
+            let mut drained = std::vec::Vec::new();
+            cbuf.put(4);
+            cbuf.drain_into(&mut drained);
+            assert_eq!(drained, [4]);
+            assert!(cbuf.is_empty());
+        }
+        assert_eq!(*buf, [0xdede; 4]);
+    }
+
+    #[test]
+    #[cfg(feature = "scrub")]
+    fn scrub_skips_non_plain() {
+        let buf = &mut [""; 2];
+        {
+            let mut cbuf = CBuf::new(buf);
+            cbuf.put("a");
+            assert_eq!(cbuf.get(), Some("a"));
+        }
+        assert_eq!(buf[0], "a");
+    }
+
+    #[test]
+    #[cfg(not(feature = "scrub"))]
+    fn checkpoint_rollback() {
+        let buf = &mut [0u8, 0u8, 0u8];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(1);
+        let cp = cbuf.checkpoint();
+
+        cbuf.put(2);
+        cbuf.put(3);
+        assert_eq!(cbuf.get().unwrap(), 1);
+        assert_eq!(cbuf.get().unwrap(), 2);
+        cbuf.rollback(cp);
+
+        assert_eq!(cbuf.checkpoint(), cp);
+        assert_eq!(cbuf.get().unwrap(), 1);
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "scrub")]
+    #[should_panic(expected = "scrubbed")]
+    fn scrub_rollback() {
+        let buf = &mut [0u8, 0u8, 0u8];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(1);
+        let cp = cbuf.checkpoint();
+        cbuf.get();
+        cbuf.rollback(cp);
+    }
+
+    #[test]
+    fn view_looping() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.get();
+        cbuf.put(3);
+        cbuf.put(4);
+
+        assert_eq!(cbuf.count(), 3);
+        let mut view = cbuf.view();
+        for _ in 0..3 {
+            assert_eq!(view.get_looping(), Some(2));
+            assert_eq!(view.get_looping(), Some(3));
+            assert_eq!(view.get_looping(), Some(4));
+        }
+        assert!(cbuf.is_full());
+    }
+
+    #[test]
+    fn slices() {
+        let buf = &mut [0u16; 4];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.put_slice(&[1, 2, 3]), 3);
+        let out = &mut [0u16; 2];
+        assert_eq!(cbuf.pop_into(out), 2);
+        assert_eq!(out, &[1, 2]);
+        assert_eq!(cbuf.put_slice(&[4, 5, 6, 7]), 3);
+        assert!(cbuf.is_full());
+
+        let out = &mut [0u16; 5];
+        assert_eq!(cbuf.pop_into(out), 4);
+        assert_eq!(out, &[3, 4, 5, 6, 0]);
+        assert_eq!(cbuf.pop_into(out), 0);
+    }
+
+    #[test]
+    fn vectored() {
+        let buf = &mut [0u8; 5];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put_slice(&[0, 0]);
+        cbuf.pop_into(&mut [0, 0]);
+        assert_eq!(cbuf.put_vectored(&[&[1, 2], &[], &[3, 4], &[5, 6]]), 5);
+        assert_eq!(cbuf.as_slices(), (&[1, 2, 3][..], &[4, 5][..]));
+
+        let (a, b) = (&mut [0u8; 1], &mut [0u8; 3]);
+        assert_eq!(cbuf.pop_into_vectored(&mut [a, b]), 4);
+        assert_eq!((&a[..], &b[..]), (&[1][..], &[2, 3, 4][..]));
+        assert_eq!(cbuf.as_slices(), (&[5][..], &[][..]));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn io_vectored() {
+        use std::io::{IoSlice, IoSliceMut, Read, Write};
+
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        let srcs = [IoSlice::new(&[1, 2]), IoSlice::new(&[3, 4])];
+        assert_eq!(cbuf.write_vectored(&srcs).unwrap(), 3);
+        let (a, b) = (&mut [0u8; 2], &mut [0u8; 2]);
+        let mut dsts = [IoSliceMut::new(a), IoSliceMut::new(b)];
+        assert_eq!(cbuf.read_vectored(&mut dsts).unwrap(), 3);
+        assert_eq!((&a[..], b[0]), (&[1, 2][..], 3));
+    }
+
+    #[test]
+    fn front_back() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.capacity(), 3);
+        assert_eq!((cbuf.front(), cbuf.back()), (None, None));
+        cbuf.put(1);
+        assert_eq!((cbuf.front(), cbuf.back()), (Some(&1), Some(&1)));
+        cbuf.put(2);
+        cbuf.put(3);
+        assert!(cbuf.is_at_capacity());
+        assert_eq!((cbuf.front(), cbuf.back()), (Some(&1), Some(&3)));
+        cbuf.get();
+        cbuf.put(4);
+        assert_eq!((cbuf.front(), cbuf.back()), (Some(&2), Some(&4)));
+    }
+
+    #[test]
+    fn iter_drain() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.get();
+        cbuf.put(3);
+        cbuf.put(4);
+        let iter = cbuf.iter();
+        assert_eq!(iter.len(), 3);
+        assert!(iter.rev().eq(&[4, 3, 2]));
+        assert_eq!((&cbuf).into_iter().map(|v| v * 2).collect::<std::vec::Vec<_>>(), [4, 6, 8]);
+
+        {
+            let mut drain = cbuf.drain();
+            assert_eq!(drain.len(), 3);
+            assert_eq!((drain.next_back(), drain.next()), (Some(4), Some(2)));
+            assert_eq!(drain.len(), 1);
+        }
+        assert!(cbuf.is_empty());
+        assert_eq!(cbuf.drain().next(), None);
+        assert_eq!(cbuf.revert_put(), None);
+    }
+
+    #[test]
+    fn shared_views() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.get();
+        cbuf.put(4);
+
+        fn newest<'r>(view: ConstCBuf<'r, u8>) -> Option<&'r u8> {
+            view.back()
+        }
+        let (a, b) = (cbuf.view(), cbuf.view());
+        assert_eq!((a.peek(), newest(b), a[1]), (Some(&2), Some(&4), 3));
+        assert_eq!((a.len(), a.count(), a.is_full(), b.is_empty()), (3, 3, true, false));
+        assert!(b.iter().eq(&[2, 3, 4]));
+        assert!(a.last_n(1).eq(b.get_at(2)));
+        assert_eq!(a.as_slices(), cbuf.as_slices());
+
+        cbuf.clear();
+        assert_eq!((cbuf.view().peek(), cbuf.view().back()), (None, None));
+    }
+
+    #[test]
+    fn overflow_policy() {
+        let buf = &mut [0u8; 2];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2]);
+        cbuf.put(3);
+        assert_eq!((cbuf.policy(), cbuf.as_slices().0), (OverflowPolicy::Reject, &[1, 2][..]));
+
+        let buf = &mut [0u8; 2];
+        let mut cbuf = CBuf::with_policy(buf, OverflowPolicy::Overwrite);
+        for val in 1..6 {
+            cbuf.put(val);
+        }
+        assert_eq!(cbuf, [4, 5]);
+    }
+
+    #[test]
+    fn try_put() {
+        let buf = &mut [0u8; 1];
+        let mut cbuf = CBuf::with_policy(buf, OverflowPolicy::Panic);
+        assert_eq!(cbuf.try_put(1), Ok(()));
+        assert_eq!(cbuf.try_put(2), Err(2));
+        assert_eq!(cbuf.get(), Some(1));
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn overflow_stats() {
+        let buf = &mut [0u8; 2];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]); // short write, not counted
+        cbuf.put(4);
+        cbuf.put(5);
+        assert_eq!(cbuf.stats(), OverflowStats { rejected: 2, overwritten: 0 });
+        cbuf.reset_stats();
+        assert_eq!(cbuf.stats(), OverflowStats::default());
+
+        let buf = &mut [0u8; 2];
+        let mut cbuf = CBuf::with_policy(buf, OverflowPolicy::Overwrite);
+        for val in 1..6 {
+            cbuf.put(val);
+        }
+        assert_eq!(cbuf.stats(), OverflowStats { rejected: 0, overwritten: 3 });
+        assert_eq!(cbuf.snapshot_and_reset(), OverflowStats { rejected: 0, overwritten: 3 });
+        cbuf.put(6);
+        assert_eq!(cbuf.snapshot_and_reset(), OverflowStats { rejected: 0, overwritten: 1 });
+        assert_eq!(cbuf.stats(), OverflowStats::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "buffer full")]
+    fn overflow_panic() {
+        let buf = &mut [0u8; 1];
+        let mut cbuf = CBuf::with_policy(buf, OverflowPolicy::Panic);
+        cbuf.put(1);
+        cbuf.put(2);
+    }
+
+    #[test]
+    fn last_n() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.get();
+        cbuf.put_slice(&[4, 5]);
+        assert!(cbuf.last_n(2).eq(&[4, 5]));
+        assert!(cbuf.last_n(3).eq(&[3, 4, 5]));
+        assert!(cbuf.last_n(9).eq(&[2, 3, 4, 5]));
+        assert_eq!(cbuf.last_n(0).len(), 0);
+        assert!(cbuf.last_n(3).rev().eq(&[5, 4, 3]));
+    }
+
+    #[test]
+    fn discard() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.get();
+        cbuf.put_slice(&[4, 5]);
+        assert_eq!(cbuf.discard(3), 3);
+        assert_eq!(cbuf, [5]);
+        assert_eq!(cbuf.discard(3), 1);
+        assert!(cbuf.is_empty());
+        assert_eq!(cbuf.discard(1), 0);
+        assert_eq!(cbuf.health_check(), Ok(()));
+    }
+
+    #[test]
+    fn revert_put() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.get();
+        cbuf.put(4); // head wraps to slot 1
+        assert_eq!(cbuf.revert_put(), Some(4));
+        assert_eq!(cbuf.revert_put(), Some(3));
+        cbuf.put(5);
+        assert_eq!(cbuf, [2, 5]);
+        assert_eq!(cbuf.health_check(), Ok(()));
+        assert_eq!((cbuf.revert_put(), cbuf.revert_put(), cbuf.revert_put()), (Some(5), Some(2), None));
+    }
+
+    #[test]
+    fn read_cursor() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[3, 7, 8, 9]);
+
+        // Length-prefixed frame: header says 3 bytes, only 3 queued after it
+        let decode = |cbuf: &mut CBuf<u8>| {
+            let mut cursor = cbuf.read_cursor();
+            let len = cursor.next()? as usize;
+            let frame: std::vec::Vec<u8> = cursor.by_ref().take(len).collect();
+            if frame.len() < len {
+                return None;
+            }
+            cursor.commit();
+            Some(frame)
+        };
+        assert_eq!(decode(&mut cbuf), Some(std::vec![7, 8, 9]));
+        assert!(cbuf.is_empty());
+
+        cbuf.put_slice(&[2, 1]);
+        assert_eq!(decode(&mut cbuf), None);
+        assert_eq!(cbuf, [2, 1]);
+
+        let mut cursor = cbuf.read_cursor();
+        assert_eq!((cursor.len(), cursor.next(), cursor.position(), cursor.len()), (2, Some(2), 1, 1));
+        cursor.commit();
+        assert_eq!(cbuf, [1]);
+    }
+
+    #[test]
+    fn index_logical() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.get();
+        cbuf.put(3);
+        cbuf.put(4);
+        assert_eq!((cbuf[0], cbuf[1], cbuf[2]), (2, 3, 4));
+        cbuf[2] = 5;
+        assert_eq!(cbuf.get_at(2), Some(&5));
+        assert_eq!(cbuf.get_at(3), None);
+        *cbuf.get_at_mut(0).unwrap() = 6;
+        assert_eq!(cbuf.get(), Some(6));
+    }
+
+    #[test]
+    #[should_panic(expected = "index 2 out of range for 2 elements")]
+    fn index_out_of_range() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(1);
+        cbuf.put(2);
+        let _ = cbuf[2];
+    }
+
+    #[test]
+    fn grant_commit() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+
+        {
+            let region = cbuf.grant(3).unwrap();
+            region.copy_from_slice(&[1, 2, 3]);
+        }
+        assert!(cbuf.is_empty());
+        cbuf.commit(2);
+        assert_eq!(cbuf.as_slices(), (&[1, 2][..], &[][..]));
+
+        cbuf.get();
+        // free slots: 2..4 and 0..1
+        assert!(cbuf.grant(3).is_none());
+        cbuf.grant(2).unwrap().copy_from_slice(&[4, 5]);
+        cbuf.commit(2);
+        cbuf.grant(1).unwrap()[0] = 6;
+        cbuf.commit(1);
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.as_slices(), (&[2, 4, 5][..], &[6][..]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn commit_too_much() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put(1);
+        cbuf.commit(4);
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn dma_region() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.pop_into(&mut [0; 2]);
+
+        let (ptr, len) = cbuf.dma_write_region();
+        assert_eq!((ptr, len), (cbuf.as_non_null().as_ptr().wrapping_add(3), 1));
+        unsafe { *ptr = 4 };
+        cbuf.dma_commit(1);
+
+        let (ptr, len) = cbuf.dma_write_region();
+        assert_eq!((ptr, len), (cbuf.as_non_null().as_ptr(), 2));
+        unsafe { *ptr = 5 };
+        cbuf.dma_commit(1);
+        assert_eq!(cbuf.as_slices(), (&[3, 4][..], &[5][..]));
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn write_regions() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.pop_into(&mut [0; 2]);
+
+        let base = cbuf.as_non_null().as_ptr();
+        {
+            let mut regions = cbuf.write_regions_iter(8);
+            assert_eq!(regions.next(), Some((base.wrapping_add(3), 1)));
+            assert_eq!(regions.next(), Some((base, 2)));
+            assert_eq!(regions.next(), None);
+        }
+        for (i, (ptr, len)) in cbuf.write_regions_iter(2).enumerate() {
+            assert_eq!(len, 1);
+            unsafe { *ptr = 4 + i as u8 };
+        }
+        cbuf.commit_regions(2);
+        assert_eq!(cbuf.as_slices(), (&[3, 4][..], &[5][..]));
+    }
+
+    #[test]
+    #[cfg(not(feature = "safe"))]
+    fn control_layout() {
+        use core::mem;
+
+        let mut ctrl = CBufControl::<u32>::new();
+        let buf = &mut [0u32; 3];
+        ctrl.put(buf, 1);
+        ctrl.put(buf, 2);
+        ctrl.get(buf);
+        assert_eq!(mem::size_of::<CBufControl<u32>>(), 2 * mem::size_of::<usize>());
+        let raw = unsafe { *(&ctrl as *const CBufControl<u32> as *const [usize; 2]) };
+        assert_eq!(raw, [ctrl.head_index(), ctrl.tail_index()]);
+        assert_eq!(raw, [2, 1]);
+    }
+
+    #[test]
+    fn health_check() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.get();
+        assert_eq!(cbuf.health_check(), Ok(()));
+
+        cbuf.ctrl.head = CBUF_DATA_BIT | 4;
+        assert_eq!(cbuf.health_check(), Err(Corruption::Index));
+    }
+
+    #[test]
+    fn with_offsets() {
+        let buf = &mut [0u8; 4];
+        let mut ctrl = CBufControl::<u8>::new();
+        for val in 1..6 {
+            ctrl.put(buf, val);
+            ctrl.get(buf);
+        }
+        ctrl.put(buf, 6);
+        ctrl.put(buf, 7);
+        let (head, tail) = (ctrl.head_index(), ctrl.tail_index());
+
+        // after a reset, with the indexes kept in retained RAM
+        let mut ctrl = CBufControl::<u8>::with_offsets(head, tail, buf.len()).unwrap();
+        assert_eq!((ctrl.get(buf), ctrl.get(buf), ctrl.get(buf)), (Some(6), Some(7), None));
+
+        assert!(CBufControl::<u8>::with_offsets(4, 0, 4).is_err());
+        assert!(CBufControl::<u8>::with_offsets(1, 2, 4).is_err());
+        assert!(CBufControl::<u8>::with_offsets(CBUF_DATA_BIT | 3, 2, 4).is_err());
+        assert!(CBufControl::<u8>::with_offsets(CBUF_DATA_BIT | 2, 2, 4).is_ok());
+    }
+
+    #[test]
+    fn slices_copy() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        assert_eq!(cbuf.put_slice_copy(&[1, 2, 3]), 3);
+        let dst = &mut [0u8; 2];
+        assert_eq!(cbuf.pop_into_copy(dst), 2);
+        assert_eq!(dst, &[1, 2]);
+
+        assert_eq!(cbuf.put_slice_copy(&[4, 5, 6, 7]), 3);
+        assert_eq!(cbuf.as_slices(), (&[3, 4][..], &[5, 6][..]));
+        let dst = &mut [0u8; 8];
+        assert_eq!(cbuf.pop_into_copy(dst), 4);
+        assert_eq!(&dst[..4], &[3, 4, 5, 6]);
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn rollback_foreign() {
+        let (buf, small) = (&mut [0u8; 4], &mut [0u8; 2]);
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]);
+        let checkpoint = cbuf.checkpoint();
+        CBuf::new(small).rollback(checkpoint);
+    }
+
+    #[test]
+    fn narrow_index() {
+        let buf = &mut [0u8; 127];
+        let mut ctrl = CBufControl::<u8, u8>::new();
+        assert_eq!(core::mem::size_of_val(&ctrl), 2);
+
+        let (mut put_val, mut get_val) = (0u8, 0u8);
+        for i in 0..1000 {
+            ctrl.put(buf, put_val);
+            put_val = put_val.wrapping_add(1);
+            if i % 3 != 0 {
+                assert_eq!(ctrl.get(buf), Some(get_val));
+                get_val = get_val.wrapping_add(1);
+            }
+            if ctrl.is_full() {
+                break;
+            }
+        }
+        assert!(ctrl.is_full());
+        assert_eq!(ctrl.head_index() ^ ctrl.tail_index(), 0x80);
+        assert_eq!(ctrl.health_check(buf.len()), Ok(()));
+        while let Some(val) = ctrl.get(buf) {
+            assert_eq!(val, get_val);
+            get_val = get_val.wrapping_add(1);
+        }
+        assert_eq!(get_val, put_val);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "MAX_CAPACITY")]
+    fn narrow_index_too_long() {
+        let buf = &mut [0u8; 128];
+        let mut ctrl = CBufControl::<u8, u8>::new();
+        ctrl.put(buf, 1);
+    }
+
+    #[test]
+    fn max_capacity() {
+        assert_eq!(::MAX_CAPACITY, <usize as RingIndex>::MAX_CAPACITY);
+        assert_eq!(<u16 as RingIndex>::MAX_CAPACITY, 32767);
+        assert_eq!(<u8 as RingIndex>::MAX_CAPACITY, 127);
+
+        let buf = &mut [(); ::MAX_CAPACITY];
+        assert!(CBuf::try_new(buf).is_some());
+        let buf = &mut [(); ::MAX_CAPACITY + 1];
+        assert!(CBuf::try_new(buf).is_none());
+        assert!(CBuf::<u8>::try_new(&mut []).is_none());
+
+        // advancing by more than half the index range must not overflow
+        let len = ::MAX_CAPACITY;
+        assert_eq!(cbuf_advance(CBUF_DATA_BIT | (len - 2), len, len), len - 2);
+        assert_eq!(cbuf_advance(len - 2, len, len), CBUF_DATA_BIT | (len - 2));
+    }
+
+    #[test]
+    fn max_capacity_16bit() {
+        // indexes of a 16-bit target, emulated by `u16`
+        let buf = &mut [(); 32767];
+        let mut ctrl = CBufControl::<(), u16>::new();
+        for _ in 0..3 {
+            while !ctrl.is_full() {
+                ctrl.put(buf, ());
+            }
+            assert_eq!(ctrl.health_check(buf.len()), Ok(()));
+            while ctrl.get(buf).is_some() {}
+            assert!(ctrl.is_empty());
+        }
+    }
+
+    #[test]
+    fn const_ops() {
+        const DRAINED: (Option<u8>, Option<u8>, bool) = {
+            let mut buf = [0u8; 2];
+            let mut ctrl = CBufControl::<u8>::new();
+            ctrl.const_put(&mut buf, 7);
+            ctrl.const_put(&mut buf, 8);
+            ctrl.const_put(&mut buf, 9); // will have no effect
+            let full = ctrl.const_is_full();
+            (ctrl.const_get(&buf), ctrl.const_get(&buf), full && ctrl.const_get(&buf).is_none())
+        };
+        assert_eq!(DRAINED, (Some(7), Some(8), true));
+    }
+
+    #[test]
+    fn debug_logical() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[9, 9, 1, 2]);
+        cbuf.pop_into(&mut [0; 2]);
+        cbuf.put(3);
+
+        assert_eq!(std::format!("{:?}", cbuf), "CBuf { contents: [1, 2, 3], count: 3, capacity: 4 }");
+    }
+
+    #[test]
+    fn eq_logical() {
+        let (buf_a, buf_b) = (&mut [0u8; 4], &mut [7u8; 3]);
+        let (mut a, mut b) = (CBuf::new(buf_a), CBuf::new(buf_b));
+        a.put_slice(&[9, 9, 1, 2]);
+        a.pop_into(&mut [0; 2]);
+        a.put(3);
+        b.put_slice(&[1, 2, 3]);
+
+        assert_eq!(a, b);
+        assert_eq!(a, [1, 2, 3]);
+        assert_eq!(a, &[1, 2, 3][..]);
+        assert!(a != [1, 2]);
+        b.get();
+        b.put(1);
+        assert!(a != b);
+    }
+
+    #[test]
+    fn snapshot_restore() {
+        let buf = &mut [0u8; 4];
+        let mut ctrl = CBufControl::<u8, u8>::new();
+        ctrl.put(buf, 1);
+        ctrl.put(buf, 2);
+        ctrl.put(buf, 3);
+
+        // speculative parse of a 4 byte message that isn't complete yet
+        let snapshot = ctrl.snapshot();
+        let mut msg = [0u8; 4];
+        let complete = msg.iter_mut().all(|b| ctrl.get(buf).map(|v| *b = v).is_some());
+        assert!(!complete);
+        ctrl.restore(snapshot);
+
+        ctrl.put(buf, 4);
+        assert!(msg.iter_mut().all(|b| ctrl.get(buf).map(|v| *b = v).is_some()));
+        assert_eq!(msg, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn find() {
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[9, 9, 9]);
+        cbuf.pop_into(&mut [0; 3]);
+        cbuf.put_slice(&[1, 2, 0x7e, 3]);
+
+        assert_eq!(cbuf.find(0x7e), Some(2));
+        assert_eq!(cbuf.find(9), None);
+        assert_eq!(cbuf.position(|&x| x > 2), Some(2));
+        cbuf.pop_into(&mut [0; 2]);
+        assert_eq!(cbuf.find(0x7e), Some(0));
+    }
+
+    #[test]
+    fn find_long() {
+        let buf = &mut [0u8; 64];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1; 40]);
+        cbuf.pop_into(&mut [0; 40]);
+        for i in 0..64 {
+            cbuf.put(i);
+        }
+
+        for i in 0..64 {
+            assert_eq!(cbuf.find(i), Some(i as usize));
+        }
+        assert_eq!(cbuf.find(64), None);
+    }
+
+    #[test]
+    fn read_until() {
+        let buf = &mut [0u8; 8];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(b"xxxxxx");
+        cbuf.pop_into(&mut [0; 6]);
+
+        let out = &mut [0u8; 8];
+        cbuf.put_slice(b"ab;cd");
+        assert_eq!(cbuf.read_until(b';', out), Some(3));
+        assert_eq!(&out[..3], b"ab;");
+        assert_eq!(cbuf.read_until(b';', out), None);
+        cbuf.put_slice(b"e;f");
+        assert_eq!(cbuf.read_until(b';', &mut out[..1]), Some(4));
+        assert_eq!(out[0], b'c');
+        assert_eq!(cbuf.as_slices(), (&b"f"[..], &b""[..]));
+    }
+
+    #[test]
+    fn lines() {
+        let buf = &mut [0u8; 8];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(b"xxxx");
+        cbuf.pop_into(&mut [0; 4]);
+
+        cbuf.put_slice(b"ab\ncd\nef");
+        {
+            let mut lines = cbuf.lines();
+            assert_eq!(lines.next(), Some((&b"ab"[..], &b""[..])));
+            assert_eq!(lines.next(), Some((&b"c"[..], &b"d"[..])));
+            assert_eq!(lines.next(), None);
+        }
+        assert_eq!(cbuf.as_slices(), (&b"ef"[..], &b""[..]));
+    }
+
+    #[test]
+    fn fmt_write() {
+        use core::fmt::Write;
+
+        let buf = &mut [0u8; 8];
+        let mut cbuf = CBuf::new(buf);
+
+        assert!(write!(cbuf, "t={}", 42).is_ok());
+        let out = &mut [0u8; 8];
+        assert_eq!(cbuf.pop_into(out), 4);
+        assert_eq!(&out[..4], b"t=42");
+
+        assert!(cbuf.write_str("too long!").is_err());
+        assert_eq!(cbuf.pop_into(out), 8);
+        assert_eq!(out, b"too long");
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn bytes_buf() {
+        use bytes::{Buf, BufMut};
+
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.remaining_mut(), 4);
+        BufMut::put_slice(&mut cbuf, &[1, 2, 3]);
+        assert_eq!(cbuf.get_u8(), 1);
+        assert_eq!(cbuf.get_u8(), 2);
+        BufMut::put_slice(&mut cbuf, &[4, 5, 6]);
+        assert_eq!(cbuf.remaining_mut(), 0);
+
+        assert_eq!(cbuf.remaining(), 4);
+        assert_eq!(cbuf.chunk(), &[3, 4]);
+        let out = &mut [0u8; 4];
+        cbuf.copy_to_slice(out);
+        assert_eq!(out, &[3, 4, 5, 6]);
+        assert!(!cbuf.has_remaining());
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-io")]
+    fn embedded_io() {
+        use embedded_io::{Read, ReadReady, Write, WriteReady};
+
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.read_ready(), Ok(false));
+        assert_eq!(cbuf.write(&[1, 2, 3, 4]), Ok(3));
+        assert_eq!(cbuf.write_ready(), Ok(false));
+        let out = &mut [0u8; 4];
+        assert_eq!(cbuf.read(out), Ok(3));
+        assert_eq!(&out[..3], &[1, 2, 3]);
+        assert_eq!(cbuf.write_ready(), Ok(true));
+        assert_eq!(cbuf.read(out), Err(WouldBlock::Empty));
+        assert_eq!(cbuf.read(&mut []), Ok(0));
+    }
+
+    #[test]
+    #[cfg(feature = "embedded-io")]
+    fn embedded_io_write_all_full() {
+        use embedded_io::{Error, ErrorKind, Write};
+
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.write_all(&[1, 2, 3, 4]), Err(WouldBlock::Full));
+        assert_eq!(WouldBlock::Full.kind(), ErrorKind::WriteZero);
+        assert_eq!(cbuf.write_all(&[]), Ok(()));
+        assert!(cbuf.iter().eq(&[1, 2, 3]));
+    }
+
+    #[derive(Clone, Default)]
+    struct Guard<'a>(u8, Option<&'a core::cell::RefCell<std::vec::Vec<u8>>>);
+
+    impl<'a> Drop for Guard<'a> {
+        fn drop(&mut self) {
+            if let Some(log) = self.1 {
+                log.borrow_mut().push(self.0);
+            }
+        }
+    }
+
+    #[test]
+    fn clear_drop_order() {
+        let log = core::cell::RefCell::new(std::vec::Vec::new());
+        let buf = &mut [Guard::default(), Guard::default(), Guard::default()];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(Guard(1, Some(&log)));
+        cbuf.put(Guard(2, Some(&log)));
+        cbuf.put(Guard(3, Some(&log)));
+        log.borrow_mut().clear();
+        cbuf.clear_in_order(DropOrder::Reverse);
+        assert!(cbuf.is_empty());
+        assert_eq!(*log.borrow(), [3, 2, 1]);
+
+        cbuf.put(Guard(4, Some(&log)));
+        cbuf.put(Guard(5, Some(&log)));
+        log.borrow_mut().clear();
+        cbuf.clear_in_order(DropOrder::Fifo);
+        assert_eq!(*log.borrow(), [4, 5]);
+
+        cbuf.put(Guard(6, Some(&log)));
+        cbuf.clear();
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    fn drain_into() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2, 3]);
+        cbuf.get();
+        cbuf.put(4);
+
+        let mut out = std::vec::Vec::new();
+        cbuf.drain_into(&mut out);
+        assert_eq!(out, [2, 3, 4]);
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    fn diff() {
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[1, 2]);
+
+        let before = cbuf.checkpoint();
+        assert_eq!(cbuf.diff(&before), ChangeSet { added: 0, removed: 0 });
+        cbuf.get();
+        cbuf.put_slice(&[3, 4]);
+        cbuf.pop_into(&mut [0; 3]);
+        cbuf.put_slice(&[5, 6, 7]);
+        assert_eq!(cbuf.diff(&before), ChangeSet { added: 5, removed: 4 });
+    }
+
+    #[test]
+    fn zst() {
+        let buf = &mut [(); 3];
+        let mut cbuf = CBuf::new(buf);
+
+        assert!(cbuf.is_empty());
+        assert!(cbuf.get().is_none());
+        cbuf.put(());
+        cbuf.put(());
+        cbuf.put(());
+        cbuf.put(()); // will have no effect
+        assert!(cbuf.is_full());
+
+        for _ in 0..10 {
+            assert_eq!(cbuf.peek(), Some(&()));
+            assert_eq!(cbuf.get(), Some(()));
+            cbuf.put(());
+            assert!(cbuf.is_full());
+        }
+
+        assert_eq!(cbuf.get(), Some(()));
+        assert_eq!(cbuf.get(), Some(()));
+        assert_eq!(cbuf.get(), Some(()));
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    fn zst_max_len() {
+        let buf = &mut [(); CBUF_DATA_BIT - 1];
+        let mut cbuf = CBuf::new(buf);
+
+        cbuf.put(());
+        assert_eq!(cbuf.get(), Some(()));
+        assert!(cbuf.is_empty());
+        assert!(!cbuf.is_full());
+    }
+
+    #[test]
+    #[should_panic]
+    fn zst_too_long() {
+        let buf = &mut [(); CBUF_DATA_BIT];
+        CBuf::new(buf);
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    #[repr(align(64))]
+    struct OverAligned(u8);
+
+    #[test]
+    fn over_aligned() {
+        let buf = &mut [OverAligned(0), OverAligned(0), OverAligned(0)];
+        let mut cbuf = CBuf::new(buf);
+
+        for i in 0..3 {
+            cbuf.put(OverAligned(i));
+            assert_eq!(cbuf.peek().unwrap() as *const _ as usize % 64, 0);
+            assert_eq!(cbuf.get().unwrap(), OverAligned(i));
+        }
+    }
+
+    #[test]
+    fn patterns() {
+        let mut buf = [0u8, 7];
+        let mut cbuf = CBufControl::<u8>::new();
+
+        let mut cur_len = 0;
+        let mut put_val = 0;
+        let mut get_val = 0;
+
+        for pattern in 0..256 {
+            if cur_len == 0 {
+                assert!(cbuf.is_empty());
+            }
+            if cur_len == buf.len() {
+                assert!(cbuf.is_full());
+            }
+
+            for bit_i in 0..8 {
+                match pattern & (1 << bit_i) == 0 {
+                    true => {
+                        if cbuf.is_empty() {
+                            assert!(cbuf.peek(&buf).is_none());
+                            assert!(cbuf.get(&buf).is_none());
+                        } else {
+                            assert!(cbuf.peek(&buf).unwrap() == &get_val);
+                            let val = cbuf.get(&buf).unwrap();
+                            assert!(val == get_val);
+                            get_val = get_val.wrapping_add(1);
+                            cur_len -= 1;
+                        }
+                    }
+                    false => {
+                        if cbuf.is_full() {
+                            cbuf.put(&mut buf, put_val);
+                            assert!(cbuf.is_full());
+                        } else {
+                            cbuf.put(&mut buf, put_val);
+                            put_val = put_val.wrapping_add(1);
+                            cur_len += 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn io_read_write() {
+        use std::io::{self, Read, Write};
+
+        let buf = &mut [0u8; 5];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.write(&[1, 2, 3]).unwrap(), 3);
+        let out = &mut [0u8; 2];
+        assert_eq!(cbuf.read(out).unwrap(), 2);
+        assert_eq!(out, &[1, 2]);
+
+        // wraps around the end of buf
+        assert_eq!(cbuf.write(&[4, 5, 6, 7, 8, 9]).unwrap(), 4);
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.write(&[9]).unwrap(), 0);
+        let out = &mut [0u8; 8];
+        assert_eq!(cbuf.read(out).unwrap(), 5);
+        assert_eq!(&out[..5], &[3, 4, 5, 6, 7]);
+        assert!(cbuf.is_empty());
+        assert_eq!(cbuf.read(out).unwrap(), 0);
+
+        let mut src: &[u8] = &[10, 11, 12];
+        assert_eq!(io::copy(&mut src, &mut cbuf).unwrap(), 3);
+        assert_eq!(cbuf.read(out).unwrap(), 3);
+        assert_eq!(&out[..3], &[10, 11, 12]);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn io_buf_read() {
+        use std::io::{BufRead, Write};
+
+        let buf = &mut [0u8; 4];
+        let mut cbuf = CBuf::new(buf);
+
+        assert_eq!(cbuf.fill_buf().unwrap(), &[]);
+        assert_eq!(cbuf.write(&[1, 2, 3]).unwrap(), 3);
+        assert_eq!(cbuf.fill_buf().unwrap(), &[1, 2, 3]);
+        cbuf.consume(2);
+        assert_eq!(cbuf.write(&[4, 5, 6]).unwrap(), 3);
+        assert!(cbuf.is_full());
+
+        assert_eq!(cbuf.fill_buf().unwrap(), &[3, 4]);
+        cbuf.consume(5); // clamped to the returned run
+        assert_eq!(cbuf.fill_buf().unwrap(), &[5, 6]);
+        cbuf.consume(2);
+        assert!(cbuf.is_empty());
+    }
+
+    #[bench]
+    pub fn put_and_get(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut cbuf = CBuf::new(buf);
+
+        b.iter(|| {
+            cbuf.put(0u8);
+            cbuf.get();
+        });
+
+        test::black_box(cbuf.get());
+    }
+
+    #[bench]
+    pub fn put_and_peek(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut cbuf = CBuf::new(buf);
+
+        b.iter(|| {
+            cbuf.put(1u8);
+            test::black_box(cbuf.peek());
+            cbuf.get();
+        });
+
+        test::black_box(cbuf.get());
+    }
+
+    #[bench]
+    pub fn find_delimiter(b: &mut Bencher) {
+        let buf = &mut [0u8; 4096];
+        let mut cbuf = CBuf::new(buf);
+        cbuf.put_slice(&[b'x'; 4000]);
+        cbuf.put(b'\n');
+
+        b.iter(|| test::black_box(cbuf.find(b'\n')));
+    }
+
+    #[bench]
+    pub fn put_slice_and_pop_into(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut cbuf = CBuf::new(buf);
+        let chunk = &mut [0u8; 100];
+
+        b.iter(|| {
+            cbuf.put_slice(chunk);
+            cbuf.pop_into(chunk);
+        });
+
+        test::black_box(chunk);
+    }
+
+    #[bench]
+    pub fn put_slice_and_pop_into_copy(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut cbuf = CBuf::new(buf);
+        let chunk = &mut [0u8; 100];
+
+        b.iter(|| {
+            cbuf.put_slice_copy(chunk);
+            cbuf.pop_into_copy(chunk);
+        });
+
+        test::black_box(chunk);
+    }
+
+    #[bench]
+    #[cfg(not(feature = "safe"))]
+    pub fn put_unchecked_and_get(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut cbuf = CBuf::new(buf);
+
+        b.iter(|| unsafe {
+            cbuf.put_unchecked(0u8);
+            cbuf.get_unchecked();
+        });
+
+        test::black_box(cbuf.get());
+    }
+}
@@ -0,0 +1,258 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Read-only Circular Buffer view over constant data
+
+use ring::Iter;
+use traits::RingConsumer;
+
+/// Read-only Circular Buffer over data in flash/ROM
+///
+/// Plays `count` elements of `buf` starting at slot `tail` (wrapping
+/// around the end) through the same consumer API as live rings, without
+/// ever writing to `buf`. Also returned by `CBuf::view`, to inspect a
+/// live ring with only `&self`. Can be built in `const`/`static` context
+/// and copied into a mutable cursor:
+///
+/// ```
+/// use cbuf::ConstCBuf;
+///
+/// static TABLE: [u8; 4] = [1, 2, 3, 4];
+/// static WAVE: ConstCBuf<u8> = ConstCBuf::new(&TABLE, 3, 2);
+///
+/// let mut wave = WAVE;
+/// assert_eq!(wave.get(), Some(4));
+/// assert_eq!(wave.get(), Some(1));
+/// assert_eq!(wave.get(), None);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct ConstCBuf<'a, T: 'a> {
+    buf: &'a [T],
+    tail: usize,
+    count: usize,
+    pos: usize,
+}
+
+impl<'a, T> ConstCBuf<'a, T> {
+    /// Create new ConstCBuf
+    ///
+    /// panics if buf.len() == 0, tail >= buf.len() or count > buf.len()
+    pub const fn new(buf: &'a [T], tail: usize, count: usize) -> ConstCBuf<'a, T> {
+        if buf.is_empty() {
+            panic!("len==0")
+        }
+        if tail >= buf.len() || count > buf.len() {
+            panic!("tail or count out of range")
+        }
+
+        ConstCBuf {
+            buf,
+            tail,
+            count,
+            pos: 0,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Number of elements not yet removed
+    #[inline]
+    pub const fn count(&self) -> usize {
+        self.count - self.pos
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.pos == self.count
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub const fn is_full(&self) -> bool {
+        self.count() == self.buf.len()
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&self) -> Option<&'a T> {
+        self.get_at(0)
+    }
+
+    /// Newest element, last to be removed
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn back(&self) -> Option<&'a T> {
+        self.get_at(self.count().checked_sub(1)?)
+    }
+
+    /// `n`-th oldest element, `get_at(0)` being the next one removed
+    ///
+    /// Returns `None` if there are not that many elements.
+    #[inline]
+    pub fn get_at(&self, n: usize) -> Option<&'a T> {
+        if n >= self.count() {
+            return None;
+        }
+        let mut idx = self.tail + self.pos + n;
+        if idx >= self.buf.len() {
+            idx -= self.buf.len();
+        }
+        self.buf.get(idx)
+    }
+
+    /// Elements as two slices, oldest first
+    #[inline]
+    pub fn as_slices(&self) -> (&'a [T], &'a [T]) {
+        let len = self.buf.len();
+        let mut start = self.tail + self.pos;
+        if start >= len {
+            start -= len;
+        }
+        let end = start + self.count();
+        if end <= len {
+            (&self.buf[start..end], &[])
+        } else {
+            (&self.buf[start..], &self.buf[..end - len])
+        }
+    }
+
+    /// Iterate over elements, oldest first, without removing them
+    #[inline]
+    pub fn iter(&self) -> Iter<'a, T> {
+        let (a, b) = self.as_slices();
+        Iter::new(a, b)
+    }
+
+    /// Iterate over the newest `n` elements (or all, if fewer), oldest
+    /// first, without removing them
+    #[inline]
+    pub fn last_n(&self, n: usize) -> Iter<'a, T> {
+        let newest = ConstCBuf {
+            pos: self.count - core::cmp::min(n, self.count()),
+            ..*self
+        };
+        newest.iter()
+    }
+}
+
+impl<'a, T> core::ops::Index<usize> for ConstCBuf<'a, T> {
+    type Output = T;
+
+    #[inline]
+    fn index(&self, n: usize) -> &T {
+        match self.get_at(n) {
+            Some(val) => val,
+            None => panic!("index {} out of range for {} elements", n, self.count()),
+        }
+    }
+}
+
+impl<'a, T> IntoIterator for ConstCBuf<'a, T> {
+    type Item = &'a T;
+    type IntoIter = Iter<'a, T>;
+
+    #[inline]
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<'a, T: Clone> ConstCBuf<'a, T> {
+    /// Remove one element from the view
+    ///
+    /// Returns `None` if buffer is empty. Underlying data is not touched.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        let val = self.peek()?.clone();
+        self.pos += 1;
+        Some(val)
+    }
+
+    /// Remove one element, starting over once all were removed
+    ///
+    /// For waveform/pattern playback: never returns `None` unless the view
+    /// was created empty.
+    #[inline]
+    pub fn get_looping(&mut self) -> Option<T> {
+        if self.is_empty() {
+            self.pos = 0;
+        }
+        self.get()
+    }
+}
+
+impl<'a, T: Clone> RingConsumer<T> for ConstCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        ConstCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        ConstCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use traits::RingConsumerExt;
+
+    static TABLE: [u16; 5] = [10, 20, 30, 40, 50];
+    static WAVE: ConstCBuf<u16> = ConstCBuf::new(&TABLE, 3, 4);
+
+    #[test]
+    fn const_cbuf() {
+        let mut wave = WAVE;
+        assert_eq!(wave.len(), 5);
+        assert_eq!(wave.peek(), Some(&40));
+        assert!(wave.get_iter().eq([40, 50, 10, 20].iter().cloned()));
+        assert!(wave.is_empty());
+        assert_eq!(wave.peek(), None);
+
+        // the static is left alone
+        let mut wave = WAVE;
+        assert_eq!(wave.get(), Some(40));
+    }
+
+    #[test]
+    fn get_looping() {
+        let mut wave = ConstCBuf::new(&TABLE, 4, 2);
+        for _ in 0..3 {
+            assert_eq!(wave.get_looping(), Some(50));
+            assert_eq!(wave.get_looping(), Some(10));
+        }
+
+        let mut empty = ConstCBuf::new(&TABLE, 0, 0);
+        assert_eq!(empty.get_looping(), None);
+    }
+
+    #[test]
+    fn inspect() {
+        let mut wave = WAVE;
+        assert_eq!(wave.get(), Some(40));
+        assert_eq!((wave.count(), wave.is_full(), wave[1]), (3, false, 10));
+        assert_eq!((wave.peek(), wave.back(), wave.get_at(3)), (Some(&50), Some(&20), None));
+        assert_eq!(wave.as_slices(), (&TABLE[4..], &TABLE[..2]));
+        assert!(wave.iter().eq(&[50, 10, 20]));
+        assert!(wave.last_n(2).eq(&[10, 20]));
+        assert!(wave.into_iter().rev().eq(&[20, 10, 50]));
+        assert!(ConstCBuf::new(&TABLE, 2, 5).is_full());
+    }
+
+    #[test]
+    #[should_panic]
+    fn const_cbuf_out_of_range() {
+        ConstCBuf::new(&TABLE, 5, 0);
+    }
+}
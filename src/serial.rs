@@ -0,0 +1,122 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Buffered serial queues implementing `embedded-hal-nb` serial traits
+//!
+//! Both queues wrap one half of an `SpscCBuf`; the other half belongs to
+//! the UART interrupt handler:
+//!
+//! * TX: application writes through `SerialTxQueue`, the TX-empty
+//!   interrupt takes words off the `Consumer` and feeds the data register,
+//! * RX: the RX-not-empty interrupt puts received words into the
+//!   `Producer`, application reads through `SerialRxQueue`.
+
+use core::convert::Infallible;
+
+use embedded_hal_nb::nb;
+use embedded_hal_nb::serial::{ErrorType, Read, Write};
+use spsc::{Consumer, Producer};
+
+/// Application side of a buffered serial transmitter
+pub struct SerialTxQueue<'b, Word: 'b = u8> {
+    prod: Producer<'b, Word>,
+}
+
+/// Application side of a buffered serial receiver
+///
+/// Words arriving while the queue is full are dropped by the interrupt
+/// handler's `put`.
+pub struct SerialRxQueue<'b, Word: 'b = u8> {
+    cons: Consumer<'b, Word>,
+}
+
+impl<'b, Word: Copy> SerialTxQueue<'b, Word> {
+    /// Create new SerialTxQueue writing into `prod`
+    pub fn new(prod: Producer<'b, Word>) -> SerialTxQueue<'b, Word> {
+        SerialTxQueue { prod }
+    }
+
+    /// Get back the wrapped producer
+    pub fn into_inner(self) -> Producer<'b, Word> {
+        self.prod
+    }
+}
+
+impl<'b, Word: Copy> SerialRxQueue<'b, Word> {
+    /// Create new SerialRxQueue reading from `cons`
+    pub fn new(cons: Consumer<'b, Word>) -> SerialRxQueue<'b, Word> {
+        SerialRxQueue { cons }
+    }
+
+    /// Get back the wrapped consumer
+    pub fn into_inner(self) -> Consumer<'b, Word> {
+        self.cons
+    }
+}
+
+impl<'b, Word: Copy> ErrorType for SerialTxQueue<'b, Word> {
+    type Error = Infallible;
+}
+
+/// `WouldBlock` while the queue is full; `flush` until it's drained
+impl<'b, Word: Copy> Write<Word> for SerialTxQueue<'b, Word> {
+    fn write(&mut self, word: Word) -> nb::Result<(), Infallible> {
+        if self.prod.is_full() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.prod.put(word);
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Infallible> {
+        if self.prod.is_empty() {
+            Ok(())
+        } else {
+            Err(nb::Error::WouldBlock)
+        }
+    }
+}
+
+impl<'b, Word: Copy> ErrorType for SerialRxQueue<'b, Word> {
+    type Error = Infallible;
+}
+
+/// `WouldBlock` while the queue is empty
+impl<'b, Word: Copy> Read<Word> for SerialRxQueue<'b, Word> {
+    fn read(&mut self) -> nb::Result<Word, Infallible> {
+        self.cons.get().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spsc::SpscCBuf;
+
+    #[test]
+    fn serial_queues() {
+        let buf = &mut [0u8; 2];
+        let mut ring = SpscCBuf::new(buf);
+        let (prod, mut isr) = ring.split();
+        let mut tx = SerialTxQueue::new(prod);
+
+        assert_eq!(tx.write(1), Ok(()));
+        assert_eq!(tx.write(2), Ok(()));
+        assert_eq!(tx.write(3), Err(nb::Error::WouldBlock));
+        assert_eq!(isr.get(), Some(1));
+        assert_eq!(tx.flush(), Err(nb::Error::WouldBlock));
+        assert_eq!(isr.get(), Some(2));
+        assert_eq!(tx.flush(), Ok(()));
+
+        let buf = &mut [0u16; 2];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut isr, cons) = ring.split();
+        let mut rx = SerialRxQueue::new(cons);
+
+        assert_eq!(rx.read(), Err(nb::Error::WouldBlock));
+        isr.put(0x1ff);
+        assert_eq!(rx.read(), Ok(0x1ff));
+    }
+}
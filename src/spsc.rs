@@ -0,0 +1,1069 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Lock-free single-producer single-consumer Circular Buffer
+
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
+#[cfg(feature = "async")]
+use core::future::Future;
+#[cfg(feature = "async")]
+use core::pin::Pin;
+#[cfg(feature = "async")]
+use core::sync::atomic::fence;
+
+use super::{cbuf_next, CachePadded, CBUF_DATA_BIT};
+#[cfg(feature = "scrub")]
+use super::scrub_slot;
+use traits::{RingConsumer, RingProducer};
+#[cfg(feature = "async")]
+use traits::{AsyncRingConsumer, AsyncRingProducer};
+#[cfg(feature = "async")]
+use waker::AtomicWaker;
+#[cfg(feature = "stream")]
+use futures_core::Stream;
+#[cfg(feature = "sink")]
+use futures_sink::Sink;
+#[cfg(feature = "sink")]
+use core::convert::Infallible;
+
+/// Circular Buffer shareable between one producer and one consumer
+///
+/// Head and tail are atomic, so after `split` one thread (or ISR) can
+/// `put` while another one does `get`, without any lock. With
+/// `cache_padded` feature (default) they are kept on separate cache lines
+/// to avoid false sharing.
+pub struct SpscCBuf<'a, T: 'a> {
+    buf: &'a [UnsafeCell<T>],
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+    #[cfg(feature = "async")]
+    data_waker: AtomicWaker,
+    #[cfg(feature = "async")]
+    space_waker: AtomicWaker,
+    // elements / free slots the waiting task needs before it's woken
+    #[cfg(feature = "async")]
+    data_wanted: AtomicUsize,
+    #[cfg(feature = "async")]
+    space_wanted: AtomicUsize,
+}
+
+// Only `Producer` writes slots and head, only `Consumer` reads slots and
+// writes tail, and each slot changes hands through a Release store of one
+// index and an Acquire load of it on the other side. Elements do cross
+// threads, hence `T: Send`; `T: Sync` isn't needed as no slot is ever
+// accessed from both sides at once.
+unsafe impl<'a, T: Send> Sync for SpscCBuf<'a, T> {}
+
+/// Producer half of `SpscCBuf`
+///
+/// `Send` and `Sync` when `T: Send`, so it can be moved into another
+/// thread, task or interrupt handler. Elements that can't cross threads
+/// are rejected at compile time:
+///
+/// ```compile_fail
+/// extern crate cbuf;
+/// use std::rc::Rc;
+///
+/// fn assert_send<S: Send>(_: &S) {}
+///
+/// let buf = &mut [Rc::new(0u8)];
+/// let mut ring = cbuf::SpscCBuf::new(buf);
+/// let (prod, _cons) = ring.split();
+/// assert_send(&prod);
+/// ```
+pub struct Producer<'b, T: 'b> {
+    ring: &'b SpscCBuf<'b, T>,
+}
+
+/// Consumer half of `SpscCBuf`
+///
+/// `Send` when `T: Send`, like `Producer`, but `Sync` only when `T` is
+/// `Sync` as well: `peek` hands out `&T`, so sharing a `&Consumer` shares
+/// the element. Interior mutable elements can't be peeked from two threads
+/// at once:
+///
+/// ```compile_fail
+/// extern crate cbuf;
+/// use std::cell::Cell;
+///
+/// fn assert_sync<S: Sync>(_: &S) {}
+///
+/// let buf = &mut [Cell::new(0u8)];
+/// let mut ring = cbuf::SpscCBuf::new(buf);
+/// let (_prod, cons) = ring.split();
+/// assert_sync(&cons);
+/// ```
+pub struct Consumer<'b, T: 'b> {
+    ring: &'b SpscCBuf<'b, T>,
+    // Opts out of `Sync`, added back below for `T: Sync`
+    phantom: PhantomData<Cell<()>>,
+}
+
+// `&Consumer` only reads the tail and lends `&T`, so sharing it is fine as
+// long as sharing `T` is.
+unsafe impl<'b, T: Send + Sync> Sync for Consumer<'b, T> {}
+
+impl<'a, T: Clone> SpscCBuf<'a, T> {
+    /// Create new SpscCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> SpscCBuf<'a, T> {
+        if buf.is_empty() {
+            panic!("len==0")
+        }
+        if buf.len() >= CBUF_DATA_BIT {
+            panic!("len too big")
+        }
+
+        // `UnsafeCell<T>` has the same in-memory representation as `T`
+        let buf = unsafe { &*(buf as *mut [T] as *const [UnsafeCell<T>]) };
+
+        SpscCBuf {
+            buf,
+            head: CachePadded::new(AtomicUsize::new(0)),
+            tail: CachePadded::new(AtomicUsize::new(0)),
+            #[cfg(feature = "async")]
+            data_waker: AtomicWaker::new(),
+            #[cfg(feature = "async")]
+            space_waker: AtomicWaker::new(),
+            #[cfg(feature = "async")]
+            data_wanted: AtomicUsize::new(1),
+            #[cfg(feature = "async")]
+            space_wanted: AtomicUsize::new(1),
+        }
+    }
+
+    /// Split into producer and consumer halves
+    pub fn split<'b>(&'b mut self) -> (Producer<'b, T>, Consumer<'b, T>) {
+        let cons = Consumer {
+            ring: self,
+            phantom: PhantomData,
+        };
+        (Producer { ring: self }, cons)
+    }
+}
+
+impl<'a, T> SpscCBuf<'a, T> {
+    /// Number of elements between `tail` and `head`
+    #[inline]
+    fn count(&self, head: usize, tail: usize) -> usize {
+        let (h, t) = (head & !CBUF_DATA_BIT, tail & !CBUF_DATA_BIT);
+        if (head ^ tail) & CBUF_DATA_BIT == 0 {
+            h - t
+        } else {
+            self.buf.len() - t + h
+        }
+    }
+
+    /// Wake the consumer if published `head` gives it enough elements
+    #[cfg(feature = "async")]
+    #[inline]
+    fn wake_data(&self, head: usize) {
+        // Pairs with the fence in `poll_data_ready`: either the consumer
+        // sees the new head, or this sees its `data_wanted`
+        fence(Ordering::SeqCst);
+        let tail = self.tail.load(Ordering::Relaxed);
+        if self.count(head, tail) >= self.data_wanted.load(Ordering::Relaxed) {
+            self.data_waker.wake();
+        }
+    }
+
+    /// Wake the producer if published `tail` gives it enough free slots
+    #[cfg(feature = "async")]
+    #[inline]
+    fn wake_space(&self, tail: usize) {
+        fence(Ordering::SeqCst);
+        let head = self.head.load(Ordering::Relaxed);
+        if self.buf.len() - self.count(head, tail) >= self.space_wanted.load(Ordering::Relaxed) {
+            self.space_waker.wake();
+        }
+    }
+}
+
+impl<'b, T: Clone> Producer<'b, T> {
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        (head ^ tail) == CBUF_DATA_BIT
+    }
+
+    /// Number of free slots
+    #[inline]
+    pub fn remaining_capacity(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        self.ring.buf.len() - self.ring.count(head, tail)
+    }
+
+    /// Is buffer empty, ie. has the consumer taken everything put so far?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        let tail = self.ring.tail.load(Ordering::Acquire);
+        head == tail
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        unsafe { self.put_unchecked(val) }
+    }
+
+    /// Add element the buffer
+    ///
+    /// # Safety
+    ///
+    /// The buffer must not be full (eg. `is_full` returned `false`; only
+    /// this producer can fill it). Otherwise the write races with the
+    /// consumer reading the oldest slot, and the head overtakes the tail.
+    #[inline]
+    pub unsafe fn put_unchecked(&mut self, val: T) {
+        let head = self.ring.head.load(Ordering::Relaxed);
+        *self.ring.buf[head & !CBUF_DATA_BIT].get() = val;
+        let head = cbuf_next(head, self.ring.buf.len());
+        self.ring.head.store(head, Ordering::Release);
+        #[cfg(feature = "async")]
+        self.ring.wake_data(head);
+    }
+}
+
+impl<'b, T: Clone> Consumer<'b, T> {
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        let head = self.ring.head.load(Ordering::Acquire);
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        head == tail
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        let head = self.ring.head.load(Ordering::Acquire);
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        self.ring.count(head, tail)
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&self) -> Option<&T> {
+        if self.is_empty() {
+            return None;
+        }
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        Some(unsafe { &*self.ring.buf[tail & !CBUF_DATA_BIT].get() })
+    }
+
+    /// Remove one element from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        Some(unsafe { self.get_unchecked() })
+    }
+
+    /// Remove one element from the buffer
+    ///
+    /// # Safety
+    ///
+    /// The buffer must not be empty (eg. `is_empty` returned `false`; only
+    /// this consumer can empty it). Otherwise the read races with the
+    /// producer writing the slot, and the tail overtakes the head.
+    #[inline]
+    pub unsafe fn get_unchecked(&mut self) -> T {
+        let tail = self.ring.tail.load(Ordering::Relaxed);
+        let val = (*self.ring.buf[tail & !CBUF_DATA_BIT].get()).clone();
+        #[cfg(feature = "scrub")]
+        scrub_slot(&mut *self.ring.buf[tail & !CBUF_DATA_BIT].get());
+        let tail = cbuf_next(tail, self.ring.buf.len());
+        self.ring.tail.store(tail, Ordering::Release);
+        #[cfg(feature = "async")]
+        self.ring.wake_space(tail);
+        val
+    }
+}
+
+impl<'b, T: Clone> Producer<'b, T> {
+    /// Publish head only every `batch` puts
+    ///
+    /// Consumer sees new elements in batches (or on `flush`), in exchange
+    /// the shared head cache line is written `batch` times less often.
+    ///
+    /// panics if batch == 0
+    pub fn batched(self, batch: usize) -> BatchedProducer<'b, T> {
+        if batch == 0 {
+            panic!("batch==0")
+        }
+        BatchedProducer {
+            head: self.ring.head.load(Ordering::Relaxed),
+            ring: self.ring,
+            pending: 0,
+            batch,
+        }
+    }
+}
+
+impl<'b, T: Clone> Consumer<'b, T> {
+    /// Publish tail only every `batch` gets
+    ///
+    /// Producer sees freed slots in batches (or on `flush`).
+    ///
+    /// panics if batch == 0
+    pub fn batched(self, batch: usize) -> BatchedConsumer<'b, T> {
+        if batch == 0 {
+            panic!("batch==0")
+        }
+        BatchedConsumer {
+            tail: self.ring.tail.load(Ordering::Relaxed),
+            ring: self.ring,
+            pending: 0,
+            batch,
+        }
+    }
+}
+
+/// Producer half of `SpscCBuf` publishing its head in batches
+///
+/// Unpublished elements are published on drop.
+pub struct BatchedProducer<'b, T: 'b> {
+    ring: &'b SpscCBuf<'b, T>,
+    head: usize,
+    pending: usize,
+    batch: usize,
+}
+
+/// Consumer half of `SpscCBuf` publishing its tail in batches
+///
+/// Unpublished freed slots are published on drop.
+pub struct BatchedConsumer<'b, T: 'b> {
+    ring: &'b SpscCBuf<'b, T>,
+    tail: usize,
+    pending: usize,
+    batch: usize,
+}
+
+impl<'b, T: Clone> BatchedProducer<'b, T> {
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        (self.head ^ self.ring.tail.load(Ordering::Acquire)) == CBUF_DATA_BIT
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        unsafe { *self.ring.buf[self.head & !CBUF_DATA_BIT].get() = val };
+        self.head = cbuf_next(self.head, self.ring.buf.len());
+        self.pending += 1;
+        if self.pending >= self.batch {
+            self.flush();
+        }
+    }
+
+    /// Publish all elements put so far to the consumer
+    #[inline]
+    pub fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+        self.ring.head.store(self.head, Ordering::Release);
+        self.pending = 0;
+        #[cfg(feature = "async")]
+        self.ring.wake_data(self.head);
+    }
+
+    /// Flush and go back to publishing on every put
+    pub fn into_inner(mut self) -> Producer<'b, T> {
+        self.flush();
+        let ring = self.ring;
+        core::mem::forget(self);
+        Producer { ring }
+    }
+}
+
+impl<'b, T> Drop for BatchedProducer<'b, T> {
+    fn drop(&mut self) {
+        if self.pending != 0 {
+            self.ring.head.store(self.head, Ordering::Release);
+            #[cfg(feature = "async")]
+            self.ring.wake_data(self.head);
+        }
+    }
+}
+
+impl<'b, T: Clone> BatchedConsumer<'b, T> {
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ring.head.load(Ordering::Acquire) == self.tail
+    }
+
+    /// Remove one element from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let slot = self.ring.buf[self.tail & !CBUF_DATA_BIT].get();
+        let val = unsafe { (*slot).clone() };
+        #[cfg(feature = "scrub")]
+        unsafe { scrub_slot(&mut *slot) };
+        self.tail = cbuf_next(self.tail, self.ring.buf.len());
+        self.pending += 1;
+        if self.pending >= self.batch {
+            self.flush();
+        }
+        Some(val)
+    }
+
+    /// Hand all slots freed so far back to the producer
+    #[inline]
+    pub fn flush(&mut self) {
+        if self.pending == 0 {
+            return;
+        }
+        self.ring.tail.store(self.tail, Ordering::Release);
+        self.pending = 0;
+        #[cfg(feature = "async")]
+        self.ring.wake_space(self.tail);
+    }
+
+    /// Flush and go back to publishing on every get
+    pub fn into_inner(mut self) -> Consumer<'b, T> {
+        self.flush();
+        let ring = self.ring;
+        core::mem::forget(self);
+        Consumer {
+            ring,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<'b, T> Drop for BatchedConsumer<'b, T> {
+    fn drop(&mut self) {
+        if self.pending != 0 {
+            self.ring.tail.store(self.tail, Ordering::Release);
+            #[cfg(feature = "async")]
+            self.ring.wake_space(self.tail);
+        }
+    }
+}
+
+impl<'b, T: Clone> RingProducer<T> for BatchedProducer<'b, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        BatchedProducer::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        BatchedProducer::put(self, val)
+    }
+}
+
+impl<'b, T: Clone> RingConsumer<T> for BatchedConsumer<'b, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        BatchedConsumer::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        BatchedConsumer::get(self)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'b, T: Clone> Producer<'b, T> {
+    /// Poll for free space in the buffer
+    ///
+    /// Once it returns `Ready`, next `put` won't be ignored.
+    pub fn poll_put_ready(&mut self, cx: &mut Context) -> Poll<()> {
+        self.poll_space_ready(1, cx)
+    }
+
+    /// Poll for at least `n` free slots in the buffer
+    ///
+    /// The consumer wakes this task only once `n` slots are free, not on
+    /// every `get`.
+    ///
+    /// panics if n is more than the buffer length
+    pub fn poll_space_ready(&mut self, n: usize, cx: &mut Context) -> Poll<()> {
+        if n > self.ring.buf.len() {
+            panic!("n > len")
+        }
+        if self.remaining_capacity() >= n {
+            return Poll::Ready(());
+        }
+        self.ring.space_wanted.store(n, Ordering::Relaxed);
+        self.ring.space_waker.register(cx.waker());
+        fence(Ordering::SeqCst);
+        if self.remaining_capacity() >= n {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// Wait until at least `n` slots are free, eg. for a whole batch
+    ///
+    /// panics like `poll_space_ready`
+    pub fn wait_for_space<'c>(&'c mut self, n: usize) -> SpaceFuture<'c, 'b, T> {
+        SpaceFuture {
+            producer: self,
+            n,
+        }
+    }
+
+    /// Add element to the buffer, waiting for free space
+    pub fn put_async<'c>(&'c mut self, val: T) -> PutFuture<'c, 'b, T> {
+        PutFuture {
+            producer: self,
+            val: Some(val),
+        }
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'b, T: Clone> Consumer<'b, T> {
+    /// Poll for next element, removing it from the buffer
+    pub fn poll_get(&mut self, cx: &mut Context) -> Poll<T> {
+        match self.poll_data_ready(1, cx) {
+            Poll::Ready(()) => Poll::Ready(unsafe { self.get_unchecked() }),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    /// Poll for at least `n` elements in the buffer
+    ///
+    /// The producer wakes this task only once `n` elements are there, not
+    /// on every `put`.
+    ///
+    /// panics if n is more than the buffer length
+    pub fn poll_data_ready(&mut self, n: usize, cx: &mut Context) -> Poll<()> {
+        if n > self.ring.buf.len() {
+            panic!("n > len")
+        }
+        if self.count() >= n {
+            return Poll::Ready(());
+        }
+        self.ring.data_wanted.store(n, Ordering::Relaxed);
+        self.ring.data_waker.register(cx.waker());
+        fence(Ordering::SeqCst);
+        if self.count() >= n {
+            return Poll::Ready(());
+        }
+        Poll::Pending
+    }
+
+    /// Wait until at least `n` elements are there, eg. for a whole batch
+    ///
+    /// panics like `poll_data_ready`
+    pub fn wait_for_data<'c>(&'c mut self, n: usize) -> DataFuture<'c, 'b, T> {
+        DataFuture {
+            consumer: self,
+            n,
+        }
+    }
+
+    /// Remove one element from the buffer, waiting for it if empty
+    pub fn get_async<'c>(&'c mut self) -> GetFuture<'c, 'b, T> {
+        GetFuture { consumer: self }
+    }
+}
+
+/// Future returned by `Producer::put_async`
+#[cfg(feature = "async")]
+pub struct PutFuture<'c, 'b: 'c, T: 'b> {
+    producer: &'c mut Producer<'b, T>,
+    val: Option<T>,
+}
+
+// `val` is never pinned
+#[cfg(feature = "async")]
+impl<'c, 'b, T> Unpin for PutFuture<'c, 'b, T> {}
+
+#[cfg(feature = "async")]
+impl<'c, 'b, T: Clone> Future for PutFuture<'c, 'b, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let this = &mut *self;
+        match this.producer.poll_put_ready(cx) {
+            Poll::Ready(()) => {
+                if let Some(val) = this.val.take() {
+                    this.producer.put(val);
+                }
+                Poll::Ready(())
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Future returned by `Consumer::get_async`
+#[cfg(feature = "async")]
+pub struct GetFuture<'c, 'b: 'c, T: 'b> {
+    consumer: &'c mut Consumer<'b, T>,
+}
+
+#[cfg(feature = "async")]
+impl<'c, 'b, T: Clone> Future for GetFuture<'c, 'b, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        self.consumer.poll_get(cx)
+    }
+}
+
+/// Future returned by `Producer::wait_for_space`
+#[cfg(feature = "async")]
+pub struct SpaceFuture<'c, 'b: 'c, T: 'b> {
+    producer: &'c mut Producer<'b, T>,
+    n: usize,
+}
+
+#[cfg(feature = "async")]
+impl<'c, 'b, T: Clone> Future for SpaceFuture<'c, 'b, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let n = self.n;
+        self.producer.poll_space_ready(n, cx)
+    }
+}
+
+/// Future returned by `Consumer::wait_for_data`
+#[cfg(feature = "async")]
+pub struct DataFuture<'c, 'b: 'c, T: 'b> {
+    consumer: &'c mut Consumer<'b, T>,
+    n: usize,
+}
+
+#[cfg(feature = "async")]
+impl<'c, 'b, T: Clone> Future for DataFuture<'c, 'b, T> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let n = self.n;
+        self.consumer.poll_data_ready(n, cx)
+    }
+}
+
+/// Stream of elements removed from the buffer
+///
+/// Never terminates: an empty buffer only means `Pending`.
+#[cfg(feature = "stream")]
+impl<'b, T: Clone> Stream for Consumer<'b, T> {
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<T>> {
+        self.poll_get(cx).map(Some)
+    }
+}
+
+/// Sink of elements added to the buffer
+///
+/// `poll_ready` waits for free space, so a full buffer applies
+/// backpressure. Elements are visible to the consumer right after
+/// `start_send`, so flushing and closing complete immediately.
+#[cfg(feature = "sink")]
+impl<'b, T: Clone> Sink<T> for Producer<'b, T> {
+    type Error = Infallible;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Infallible>> {
+        self.poll_put_ready(cx).map(Ok)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Infallible> {
+        self.put(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'b, T: Clone> RingProducer<T> for Producer<'b, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        Producer::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        Producer::put(self, val)
+    }
+}
+
+impl<'b, T: Clone> RingConsumer<T> for Consumer<'b, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        Consumer::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        Consumer::get(self)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'b, T: Clone> AsyncRingProducer<T> for Producer<'b, T> {
+    #[inline]
+    fn poll_put_ready(&mut self, cx: &mut Context) -> Poll<()> {
+        Producer::poll_put_ready(self, cx)
+    }
+}
+
+#[cfg(feature = "async")]
+impl<'b, T: Clone> AsyncRingConsumer<T> for Consumer<'b, T> {
+    #[inline]
+    fn poll_get(&mut self, cx: &mut Context) -> Poll<T> {
+        Consumer::poll_get(self, cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+    use test::Bencher;
+    use test;
+    #[cfg(feature = "async")]
+    use core::task::Waker;
+
+    #[test]
+    fn halves_send_sync() {
+        fn assert_send<S: Send>() {}
+        fn assert_send_sync<S: Send + Sync>() {}
+        assert_send_sync::<Producer<std::string::String>>();
+        assert_send_sync::<Consumer<std::string::String>>();
+        // `!Sync` elements: see the `compile_fail` example on `Consumer`
+        assert_send_sync::<Producer<core::cell::Cell<u8>>>();
+        assert_send::<Consumer<core::cell::Cell<u8>>>();
+        #[cfg(feature = "channel")]
+        {
+            assert_send_sync::<::channel::Sender<std::string::String>>();
+            assert_send_sync::<::channel::Receiver<std::string::String>>();
+        }
+    }
+
+    #[test]
+    fn spsc() {
+        let buf = &mut [0u8; 2];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        assert!(cons.is_empty());
+        assert!(cons.get().is_none());
+        prod.put(1);
+        prod.put(2);
+        prod.put(42); // will have no effect
+        assert!(prod.is_full());
+
+        assert_eq!(cons.peek().unwrap(), &1);
+        assert_eq!(cons.get().unwrap(), 1);
+        prod.put(3);
+        assert_eq!(cons.get().unwrap(), 2);
+        assert_eq!(cons.get().unwrap(), 3);
+        assert!(cons.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "cache_padded")]
+    fn spsc_padding() {
+        let buf = &mut [0u8; 2];
+        let ring = SpscCBuf::new(buf);
+        let head = &*ring.head as *const AtomicUsize as usize;
+        let tail = &*ring.tail as *const AtomicUsize as usize;
+        assert!(head.max(tail) - head.min(tail) >= 64);
+    }
+
+    #[cfg(feature = "async")]
+    struct CountingWaker(AtomicUsize);
+
+    #[cfg(feature = "async")]
+    impl std::task::Wake for CountingWaker {
+        fn wake(self: std::sync::Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn spsc_async() {
+        let counter = std::sync::Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let buf = &mut [0u8; 1];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        {
+            let mut get = cons.get_async();
+            assert_eq!(Pin::new(&mut get).poll(&mut cx), Poll::Pending);
+        }
+        prod.put(1);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+
+        {
+            let mut put = prod.put_async(2);
+            assert_eq!(Pin::new(&mut put).poll(&mut cx), Poll::Pending);
+        }
+        assert_eq!(cons.poll_get(&mut cx), Poll::Ready(1));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+
+        {
+            let mut put = prod.put_async(2);
+            assert_eq!(Pin::new(&mut put).poll(&mut cx), Poll::Ready(()));
+        }
+        let mut get = cons.get_async();
+        assert_eq!(Pin::new(&mut get).poll(&mut cx), Poll::Ready(2));
+    }
+
+    #[test]
+    #[cfg(feature = "async")]
+    fn spsc_async_threshold() {
+        let counter = std::sync::Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let buf = &mut [0u8; 4];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        {
+            let mut data = cons.wait_for_data(3);
+            assert_eq!(Pin::new(&mut data).poll(&mut cx), Poll::Pending);
+        }
+        prod.put(1);
+        prod.put(2);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 0);
+        prod.put(3);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(cons.poll_data_ready(3, &mut cx), Poll::Ready(()));
+
+        prod.put(4);
+        {
+            let mut space = prod.wait_for_space(2);
+            assert_eq!(Pin::new(&mut space).poll(&mut cx), Poll::Pending);
+        }
+        assert_eq!(cons.get(), Some(1));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(cons.get(), Some(2));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 2);
+        assert_eq!(prod.remaining_capacity(), 2);
+        assert_eq!(prod.poll_space_ready(2, &mut cx), Poll::Ready(()));
+    }
+
+    #[test]
+    #[cfg(feature = "stream")]
+    fn spsc_stream() {
+        let counter = std::sync::Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let buf = &mut [0u8; 2];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        assert_eq!(Pin::new(&mut cons).poll_next(&mut cx), Poll::Pending);
+        prod.put(1);
+        prod.put(2);
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut cons).poll_next(&mut cx), Poll::Ready(Some(1)));
+        assert_eq!(Pin::new(&mut cons).poll_next(&mut cx), Poll::Ready(Some(2)));
+        assert_eq!(Pin::new(&mut cons).poll_next(&mut cx), Poll::Pending);
+    }
+
+    #[test]
+    #[cfg(feature = "sink")]
+    fn spsc_sink() {
+        let counter = std::sync::Arc::new(CountingWaker(AtomicUsize::new(0)));
+        let waker = Waker::from(counter.clone());
+        let mut cx = Context::from_waker(&waker);
+
+        let buf = &mut [0u8; 1];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        assert_eq!(Pin::new(&mut prod).poll_ready(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(Pin::new(&mut prod).start_send(1), Ok(()));
+        assert_eq!(Pin::new(&mut prod).poll_ready(&mut cx), Poll::Pending);
+        assert_eq!(cons.get(), Some(1));
+        assert_eq!(counter.0.load(Ordering::SeqCst), 1);
+        assert_eq!(Pin::new(&mut prod).poll_ready(&mut cx), Poll::Ready(Ok(())));
+        assert_eq!(Pin::new(&mut prod).poll_flush(&mut cx), Poll::Ready(Ok(())));
+    }
+
+    #[test]
+    fn spsc_threads() {
+        let buf = &mut [0u32; 7];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..10000 {
+                    while prod.is_full() {
+                        std::thread::yield_now();
+                    }
+                    prod.put(i);
+                }
+            });
+
+            for i in 0..10000 {
+                loop {
+                    if let Some(val) = cons.get() {
+                        assert_eq!(val, i);
+                        break;
+                    }
+                    std::thread::yield_now();
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn spsc_batched() {
+        let buf = &mut [0u8; 4];
+        let mut ring = SpscCBuf::new(buf);
+        let (prod, cons) = ring.split();
+        let (mut prod, mut cons) = (prod.batched(2), cons.batched(2));
+
+        prod.put(1);
+        assert!(cons.is_empty());
+        prod.put(2);
+        prod.put(3);
+        assert_eq!(cons.get(), Some(1));
+        assert_eq!(cons.get(), Some(2));
+        assert_eq!(cons.get(), None);
+        prod.flush();
+        assert_eq!(cons.get(), Some(3));
+
+        // slot of `3` not handed back yet
+        prod.put(4);
+        prod.put(5);
+        prod.put(6);
+        assert!(prod.is_full());
+        drop(cons);
+        assert!(!prod.is_full());
+
+        let mut prod = prod.into_inner();
+        prod.put(7);
+        assert!(prod.is_full());
+    }
+
+    #[test]
+    fn spsc_batched_threads() {
+        let buf = &mut [0u32; 7];
+        let mut ring = SpscCBuf::new(buf);
+        let (prod, cons) = ring.split();
+        let (mut prod, mut cons) = (prod.batched(3), cons.batched(3));
+
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..10000 {
+                    while prod.is_full() {
+                        std::thread::yield_now();
+                    }
+                    prod.put(i);
+                }
+            });
+
+            for i in 0..10000 {
+                loop {
+                    if let Some(val) = cons.get() {
+                        assert_eq!(val, i);
+                        break;
+                    }
+                    std::thread::yield_now();
+                }
+            }
+        });
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    #[repr(align(64))]
+    struct OverAligned(u8);
+
+    #[test]
+    fn over_aligned() {
+
+
+        let buf = &mut [OverAligned(0), OverAligned(0)];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+        for i in 0..3 {
+            prod.put(OverAligned(i));
+            assert_eq!(cons.peek().unwrap() as *const _ as usize % 64, 0);
+            assert_eq!(cons.get().unwrap(), OverAligned(i));
+        }
+
+    }
+
+    #[bench]
+    pub fn spsc_put_and_get(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+
+        b.iter(|| {
+            prod.put(0u8);
+            cons.get();
+        });
+
+        test::black_box(cons.get());
+    }
+
+    #[bench]
+    pub fn spsc_batched_put_and_get(b: &mut Bencher) {
+        let buf = &mut [0u8; 256];
+        let mut ring = SpscCBuf::new(buf);
+        let (prod, cons) = ring.split();
+        let (mut prod, mut cons) = (prod.batched(16), cons.batched(16));
+
+        b.iter(|| {
+            for _ in 0..16 {
+                prod.put(0u8);
+            }
+            for _ in 0..16 {
+                cons.get();
+            }
+        });
+
+        test::black_box(cons.get());
+    }
+}
@@ -0,0 +1,268 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Pair of Circular Buffers swapped between a producer and a consumer
+
+use core::cell::{Cell, UnsafeCell};
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use ring::CBuf;
+use traits::{RingConsumer, RingProducer};
+
+const SWAP_ACTIVE_BIT: usize = 1;
+const SWAP_BUSY_BIT: usize = 2;
+// Producer buffer was full when the producer last left `fill`
+const SWAP_FULL_BIT: usize = 4;
+
+/// Pair of Circular Buffers for bulk handoff between threads
+///
+/// Producer fills one buffer while consumer drains the other. When the
+/// consumer is done, it swaps them with a single atomic operation, so
+/// per-element synchronization is avoided entirely.
+pub struct SwapPair<'a, T: 'a> {
+    rings: [UnsafeCell<CBuf<'a, T>>; 2],
+    state: AtomicUsize,
+}
+
+// Each buffer is accessed by one side at a time: the producer holds the
+// busy bit while in `fill`, and the consumer only swaps while it's clear.
+// Whole buffers change hands, so `T: Send` is enough.
+unsafe impl<'a, T: Send> Sync for SwapPair<'a, T> {}
+
+/// Producer half of `SwapPair`
+///
+/// `Send` when `T: Send`, but not `Sync`: the buffer state is only
+/// guarded against the consumer, not against other threads sharing the
+/// same half.
+pub struct SwapProducer<'b, 'a: 'b, T: 'a> {
+    pair: &'b SwapPair<'a, T>,
+    // Opts out of `Sync`
+    phantom: PhantomData<Cell<()>>,
+}
+
+/// Consumer half of `SwapPair`
+///
+/// `Send` when `T: Send`, but not `Sync`.
+pub struct SwapConsumer<'b, 'a: 'b, T: 'a> {
+    pair: &'b SwapPair<'a, T>,
+    // Opts out of `Sync`
+    phantom: PhantomData<Cell<()>>,
+}
+
+impl<'a, T: Clone> SwapPair<'a, T> {
+    /// Create new SwapPair
+    ///
+    /// Producer starts with `a`, consumer with `b`.
+    ///
+    /// panics if either buffer has len() == 0
+    pub fn new(a: &'a mut [T], b: &'a mut [T]) -> SwapPair<'a, T> {
+        SwapPair {
+            rings: [UnsafeCell::new(CBuf::new(a)), UnsafeCell::new(CBuf::new(b))],
+            state: AtomicUsize::new(0),
+        }
+    }
+
+    /// Split into producer and consumer halves
+    pub fn split<'b>(&'b mut self) -> (SwapProducer<'b, 'a, T>, SwapConsumer<'b, 'a, T>) {
+        let prod = SwapProducer {
+            pair: self,
+            phantom: PhantomData,
+        };
+        let cons = SwapConsumer {
+            pair: self,
+            phantom: PhantomData,
+        };
+        (prod, cons)
+    }
+}
+
+impl<'b, 'a, T: Clone> SwapProducer<'b, 'a, T> {
+    /// Access the producer buffer
+    ///
+    /// Consumer can't swap while `f` runs, so whole batches are
+    /// handed off together.
+    pub fn fill<R, F: FnOnce(&mut CBuf<'a, T>) -> R>(&mut self, f: F) -> R {
+        let state = self.pair.state.fetch_or(SWAP_BUSY_BIT, Ordering::Acquire);
+        let ring = unsafe { &mut *self.pair.rings[state & SWAP_ACTIVE_BIT].get() };
+        let ret = f(ring);
+        let full = if ring.is_full() { SWAP_FULL_BIT } else { 0 };
+        // Consumer can't swap while busy, so nobody else writes the state
+        self.pair.state.store((state & SWAP_ACTIVE_BIT) | full, Ordering::Release);
+        ret
+    }
+
+    /// Is producer buffer full?
+    ///
+    /// Reads the state saved by the last `fill`, so it never blocks a
+    /// swap.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.pair.state.load(Ordering::Acquire) & SWAP_FULL_BIT != 0
+    }
+
+    /// Add element to the producer buffer
+    ///
+    /// Ignores the write if buffer is full.
+    pub fn put(&mut self, val: T) {
+        self.fill(|ring| ring.put(val))
+    }
+}
+
+impl<'b, 'a, T: Clone> SwapConsumer<'b, 'a, T> {
+    /// Access the consumer buffer
+    pub fn drain<R, F: FnOnce(&mut CBuf<'a, T>) -> R>(&mut self, f: F) -> R {
+        // Only the consumer ever changes the active bit
+        let state = self.pair.state.load(Ordering::Relaxed);
+        let ring = self.pair.rings[(state & SWAP_ACTIVE_BIT) ^ 1].get();
+        f(unsafe { &mut *ring })
+    }
+
+    /// Is consumer buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        // Producer never touches the consumer buffer, so a shared borrow
+        // can't race
+        let state = self.pair.state.load(Ordering::Relaxed);
+        let ring = self.pair.rings[(state & SWAP_ACTIVE_BIT) ^ 1].get();
+        unsafe { &*ring }.is_empty()
+    }
+
+    /// Remove one element from the consumer buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    pub fn get(&mut self) -> Option<T> {
+        self.drain(|ring| ring.get())
+    }
+
+    /// Swap producer and consumer buffers
+    ///
+    /// Returns `false` and does nothing if consumer buffer is not yet
+    /// empty, or producer is in the middle of `fill`.
+    pub fn swap(&mut self) -> bool {
+        if !self.is_empty() {
+            return false;
+        }
+        let state = self.pair.state.load(Ordering::Relaxed);
+        if state & SWAP_BUSY_BIT != 0 {
+            return false;
+        }
+        // Producer gets the empty consumer buffer, so it's no longer full
+        let swapped = (state ^ SWAP_ACTIVE_BIT) & !SWAP_FULL_BIT;
+        self.pair
+            .state
+            .compare_exchange(state, swapped, Ordering::AcqRel, Ordering::Relaxed)
+            .is_ok()
+    }
+}
+
+impl<'b, 'a, T: Clone> RingProducer<T> for SwapProducer<'b, 'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        SwapProducer::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        SwapProducer::put(self, val)
+    }
+}
+
+impl<'b, 'a, T: Clone> RingConsumer<T> for SwapConsumer<'b, 'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        SwapConsumer::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        SwapConsumer::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std;
+
+    #[test]
+    fn swap_pair() {
+        let a = &mut [0u8; 4];
+        let b = &mut [0u8; 4];
+        let mut pair = SwapPair::new(a, b);
+        let (mut prod, mut cons) = pair.split();
+
+        prod.put(1);
+        prod.fill(|ring| {
+            ring.put(2);
+            ring.put(3);
+        });
+        assert!(cons.get().is_none());
+
+        assert!(cons.swap());
+        prod.put(4);
+        assert_eq!(cons.get().unwrap(), 1);
+        assert!(!cons.swap());
+        assert_eq!(cons.get().unwrap(), 2);
+        assert_eq!(cons.get().unwrap(), 3);
+        assert!(cons.get().is_none());
+
+        assert!(cons.swap());
+        assert_eq!(cons.get().unwrap(), 4);
+
+        prod.fill(|ring| while !ring.is_full() {
+            ring.put(5)
+        });
+        assert!(prod.is_full());
+        assert!(cons.is_empty());
+        assert!(cons.swap());
+        assert!(!prod.is_full());
+        assert!(!cons.is_empty());
+    }
+
+    #[test]
+    fn halves_send() {
+        fn assert_send<S: Send>() {}
+        assert_send::<SwapProducer<std::string::String>>();
+        assert_send::<SwapConsumer<std::string::String>>();
+    }
+
+    #[test]
+    fn swap_pair_threads() {
+        let a = &mut [0u32; 16];
+        let b = &mut [0u32; 16];
+        let mut pair = SwapPair::new(a, b);
+        let (mut prod, mut cons) = pair.split();
+
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                let mut next = 0;
+                while next < 1000 {
+                    prod.fill(|ring| {
+                        while next < 1000 && !ring.is_full() {
+                            ring.put(next);
+                            next += 1;
+                        }
+                    });
+                    std::thread::yield_now();
+                }
+            });
+
+            let mut expected = 0;
+            while expected < 1000 {
+                match cons.get() {
+                    Some(val) => {
+                        assert_eq!(val, expected);
+                        expected += 1;
+                    }
+                    None => {
+                        cons.swap();
+                        std::thread::yield_now();
+                    }
+                }
+            }
+        });
+    }
+}
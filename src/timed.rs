@@ -0,0 +1,227 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer dropping elements that outlived their deadline
+
+use ring::{CBuf, Iter};
+
+/// Circular Buffer of elements with an expiry time
+///
+/// Every element is put with the tick it expires at, stored in
+/// `deadlines`. Reads take the current tick and skip elements whose
+/// deadline has passed, so a consumer coming back from a stall never acts
+/// on stale commands. Ticks are whatever monotonic `u64` clock the caller
+/// uses; an element is expired once `now >= expires_at`.
+///
+/// Expired elements are dropped lazily by `get` and `peek` when they reach
+/// the front; `purge_expired` drops all of them, eg. to make room.
+///
+/// ```
+/// use cbuf::TimedCBuf;
+///
+/// let (buf, deadlines) = (&mut [0u8; 4], &mut [0; 4]);
+/// let mut cmds = TimedCBuf::new(buf, deadlines);
+/// cmds.put(1, 10);
+/// cmds.put(2, 50);
+/// assert_eq!(cmds.get(20), Some(2));
+/// ```
+pub struct TimedCBuf<'a, T: 'a> {
+    buf: CBuf<'a, T>,
+    deadlines: CBuf<'a, u64>,
+}
+
+impl<'a, T: Clone> TimedCBuf<'a, T> {
+    /// Create new TimedCBuf
+    ///
+    /// panics if buf.len() == 0, or if buf.len() != deadlines.len()
+    pub fn new(buf: &'a mut [T], deadlines: &'a mut [u64]) -> TimedCBuf<'a, T> {
+        if buf.len() != deadlines.len() {
+            panic!("deadlines.len() != buf.len()")
+        }
+        TimedCBuf {
+            buf: CBuf::new(buf),
+            deadlines: CBuf::new(deadlines),
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Number of elements in the buffer, including expired ones not
+    /// dropped yet
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.buf.count()
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.buf.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.buf.is_empty()
+    }
+
+    /// Add element expiring at tick `expires_at`
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T, expires_at: u64) {
+        if self.is_full() {
+            return;
+        }
+        self.buf.put(val);
+        self.deadlines.put(expires_at);
+    }
+
+    /// Drop expired elements at the front
+    #[inline]
+    fn skip_expired(&mut self, now: u64) {
+        while self.deadlines.peek().is_some_and(|&expires_at| now >= expires_at) {
+            self.deadlines.discard(1);
+            self.buf.discard(1);
+        }
+    }
+
+    /// Peek oldest element still valid at tick `now`
+    ///
+    /// Drops expired elements in front of it. Returns `None` if there's
+    /// none.
+    #[inline]
+    pub fn peek(&mut self, now: u64) -> Option<&T> {
+        self.skip_expired(now);
+        self.buf.peek()
+    }
+
+    /// Remove oldest element still valid at tick `now`
+    ///
+    /// Drops expired elements in front of it. Returns `None` if there's
+    /// none.
+    #[inline]
+    pub fn get(&mut self, now: u64) -> Option<T> {
+        self.skip_expired(now);
+        self.deadlines.get()?;
+        self.buf.get()
+    }
+
+    /// Drop every element expired at tick `now`
+    ///
+    /// Keeps the order of the others. Returns the number of elements
+    /// dropped. Takes time proportional to `count`.
+    pub fn purge_expired(&mut self, now: u64) -> usize {
+        let mut dropped = 0;
+        for _ in 0..self.count() {
+            let (val, expires_at) = match (self.buf.get(), self.deadlines.get()) {
+                (Some(val), Some(expires_at)) => (val, expires_at),
+                _ => break,
+            };
+            if now >= expires_at {
+                dropped += 1;
+            } else {
+                self.buf.put(val);
+                self.deadlines.put(expires_at);
+            }
+        }
+        dropped
+    }
+
+    /// Iterate over elements still valid at tick `now`, oldest first,
+    /// without removing them
+    #[inline]
+    pub fn iter(&self, now: u64) -> TimedIter<'_, T> {
+        TimedIter {
+            buf: self.buf.iter(),
+            deadlines: self.deadlines.iter(),
+            now,
+        }
+    }
+
+    /// Remove all elements
+    #[inline]
+    pub fn clear(&mut self) {
+        self.buf.clear();
+        self.deadlines.clear();
+    }
+}
+
+/// Iterator over unexpired elements, returned by `TimedCBuf::iter`
+pub struct TimedIter<'r, T: 'r> {
+    buf: Iter<'r, T>,
+    deadlines: Iter<'r, u64>,
+    now: u64,
+}
+
+impl<'r, T> Iterator for TimedIter<'r, T> {
+    type Item = &'r T;
+
+    #[inline]
+    fn next(&mut self) -> Option<&'r T> {
+        loop {
+            let val = self.buf.next()?;
+            if self.now < *self.deadlines.next()? {
+                return Some(val);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn timed() {
+        let (buf, deadlines) = (&mut [0u8; 4], &mut [0; 4]);
+        let mut cbuf = TimedCBuf::new(buf, deadlines);
+
+        cbuf.put(1, 10);
+        cbuf.put(2, 30);
+        cbuf.put(3, 20);
+        cbuf.put(4, 40);
+        cbuf.put(5, 40); // will have no effect
+        assert_eq!(cbuf.iter(0).cloned().collect::<Vec<_>>(), [1, 2, 3, 4]);
+        assert_eq!(cbuf.iter(25).cloned().collect::<Vec<_>>(), [2, 4]);
+        assert_eq!(cbuf.count(), 4);
+
+        assert_eq!(cbuf.peek(10), Some(&2));
+        assert_eq!(cbuf.count(), 3);
+        // `3` is behind a valid element, `get` drops it once it's in front
+        assert_eq!(cbuf.get(25), Some(2));
+        assert_eq!(cbuf.get(25), Some(4));
+        assert_eq!(cbuf.get(25), None);
+        assert!(cbuf.is_empty());
+    }
+
+    #[test]
+    fn purge_expired() {
+        let (buf, deadlines) = (&mut [0u8; 4], &mut [0; 4]);
+        let mut cbuf = TimedCBuf::new(buf, deadlines);
+
+        cbuf.put(1, 30);
+        cbuf.put(2, 10);
+        cbuf.put(3, 40);
+        cbuf.put(4, 20);
+        assert_eq!(cbuf.purge_expired(20), 2);
+        assert_eq!(cbuf.count(), 2);
+        cbuf.put(5, 50);
+        assert_eq!(cbuf.iter(0).cloned().collect::<Vec<_>>(), [1, 3, 5]);
+        assert_eq!(cbuf.purge_expired(0), 0);
+        assert_eq!((cbuf.get(35), cbuf.get(35), cbuf.get(35)), (Some(3), Some(5), None));
+    }
+
+    #[test]
+    #[should_panic]
+    fn deadlines_len() {
+        TimedCBuf::new(&mut [0u8; 4], &mut [0; 3]);
+    }
+}
@@ -0,0 +1,250 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Traits shared by all Circular Buffer variants
+
+use core::marker::PhantomData;
+#[cfg(feature = "async")]
+use core::task::{Context, Poll};
+#[cfg(feature = "nb")]
+use core::convert::Infallible;
+#[cfg(feature = "nb")]
+use nb;
+
+/// Write side of any Circular Buffer variant
+pub trait RingProducer<T> {
+    /// Is buffer full?
+    fn is_full(&self) -> bool;
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    fn put(&mut self, val: T);
+}
+
+/// Read side of any Circular Buffer variant
+pub trait RingConsumer<T> {
+    /// Is buffer empty?
+    fn is_empty(&self) -> bool;
+
+    /// Remove one element from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    fn get(&mut self) -> Option<T>;
+}
+
+/// Circular Buffer variant that is both read and written through one value
+pub trait RingBuffer<T>: RingProducer<T> + RingConsumer<T> {}
+
+impl<T, R: RingProducer<T> + RingConsumer<T> + ?Sized> RingBuffer<T> for R {}
+
+/// Async write side of a Circular Buffer
+#[cfg(feature = "async")]
+pub trait AsyncRingProducer<T>: RingProducer<T> {
+    /// Poll for free space in the buffer
+    ///
+    /// Once it returns `Ready`, next `put` won't be ignored.
+    fn poll_put_ready(&mut self, cx: &mut Context) -> Poll<()>;
+}
+
+/// Async read side of a Circular Buffer
+#[cfg(feature = "async")]
+pub trait AsyncRingConsumer<T>: RingConsumer<T> {
+    /// Poll for next element, removing it from the buffer
+    fn poll_get(&mut self, cx: &mut Context) -> Poll<T>;
+}
+
+/// Convenience methods for every `RingProducer`
+pub trait RingProducerExt<T>: RingProducer<T> {
+    /// Put elements from `iter` until buffer is full
+    ///
+    /// Returns number of elements put. Elements left in `iter` are not
+    /// consumed.
+    fn put_iter<I: IntoIterator<Item = T>>(&mut self, iter: I) -> usize {
+        let mut count = 0;
+        let mut iter = iter.into_iter();
+        while !self.is_full() {
+            match iter.next() {
+                Some(val) => self.put(val),
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+
+    /// Add element the buffer, or `WouldBlock` if it's full
+    #[cfg(feature = "nb")]
+    #[inline]
+    fn nb_put(&mut self, val: T) -> nb::Result<(), Infallible> {
+        if self.is_full() {
+            return Err(nb::Error::WouldBlock);
+        }
+        self.put(val);
+        Ok(())
+    }
+}
+
+impl<T, P: RingProducer<T> + ?Sized> RingProducerExt<T> for P {}
+
+/// Convenience methods for every `RingConsumer`
+pub trait RingConsumerExt<T>: RingConsumer<T> {
+    /// Iterator removing elements until buffer is empty
+    fn get_iter<'r>(&'r mut self) -> GetIter<'r, Self, T> {
+        GetIter {
+            consumer: self,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Remove one element from the buffer, or `WouldBlock` if it's empty
+    #[cfg(feature = "nb")]
+    #[inline]
+    fn nb_get(&mut self) -> nb::Result<T, Infallible> {
+        self.get().ok_or(nb::Error::WouldBlock)
+    }
+}
+
+impl<T, C: RingConsumer<T> + ?Sized> RingConsumerExt<T> for C {}
+
+/// Object-safe write side of any byte Circular Buffer
+///
+/// Lets rings of different capacities and backings be stored and pumped
+/// uniformly as `&mut dyn ByteSink`.
+pub trait ByteSink: RingProducer<u8> {
+    /// Put bytes from `src` until buffer is full
+    ///
+    /// Returns number of bytes put.
+    fn put_bytes(&mut self, src: &[u8]) -> usize {
+        let mut count = 0;
+        for &b in src {
+            if self.is_full() {
+                break;
+            }
+            self.put(b);
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<P: RingProducer<u8> + ?Sized> ByteSink for P {}
+
+/// Object-safe read side of any byte Circular Buffer
+///
+/// Counterpart of `ByteSink`, usable as `&mut dyn ByteSource`.
+pub trait ByteSource: RingConsumer<u8> {
+    /// Remove bytes into `dst` until buffer is empty or `dst` is full
+    ///
+    /// Returns number of bytes removed.
+    fn get_bytes(&mut self, dst: &mut [u8]) -> usize {
+        let mut count = 0;
+        for b in dst.iter_mut() {
+            match self.get() {
+                Some(val) => *b = val,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<C: RingConsumer<u8> + ?Sized> ByteSource for C {}
+
+/// Iterator returned by `RingConsumerExt::get_iter`
+pub struct GetIter<'r, C: 'r + ?Sized, T> {
+    consumer: &'r mut C,
+    phantom: PhantomData<T>,
+}
+
+impl<'r, C: RingConsumer<T> + ?Sized, T> Iterator for GetIter<'r, C, T> {
+    type Item = T;
+
+    #[inline]
+    fn next(&mut self) -> Option<T> {
+        self.consumer.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    #[cfg(all(feature = "spsc", feature = "mpmc"))]
+    fn prelude_generic() {
+        use prelude::*;
+        use ring::CBuf;
+        use spsc::SpscCBuf;
+        use mpmc::MpmcCBuf;
+        use core::sync::atomic::AtomicUsize;
+
+        fn fill<P: RingProducer<u8> + ?Sized>(to: &mut P) -> usize {
+            to.put_iter(0..)
+        }
+
+        let buf = &mut [0u8; 3];
+        let mut cbuf = CBuf::new(buf);
+        assert_eq!(fill(&mut cbuf), 3);
+
+        let buf = &mut [0u8; 2];
+        let mut ring = SpscCBuf::new(buf);
+        let (mut prod, mut cons) = ring.split();
+        assert_eq!(fill(&mut prod), 2);
+
+        let buf = &mut [0u8; 4];
+        let stamps: &mut [AtomicUsize; 4] = &mut core::array::from_fn(|_| AtomicUsize::new(0));
+        let mpmc = MpmcCBuf::new(buf, stamps);
+        let mut mpmc_ref = &mpmc;
+        let dst: &mut dyn RingProducer<u8> = &mut mpmc_ref;
+        assert_eq!(fill(dst), 4);
+
+        let mut mpmc_ref = &mpmc;
+        assert!(cons.get_iter().eq(0..2));
+        assert!(cbuf.get_iter().eq(0..3));
+        assert!(mpmc_ref.get_iter().eq(0..4));
+        assert!(RingConsumer::is_empty(&cbuf));
+    }
+
+    #[test]
+    #[cfg(all(feature = "nb", not(feature = "no_std")))]
+    fn nb_adapters() {
+        use prelude::*;
+        use ring::CBuf;
+        use nb;
+
+        let buf = &mut [0u8; 1];
+        let mut cbuf = CBuf::new(buf);
+        assert_eq!(cbuf.nb_get(), Err(nb::Error::WouldBlock));
+        assert_eq!(::nb::block!(cbuf.nb_put(1)), Ok(()));
+        assert_eq!(cbuf.nb_put(2), Err(nb::Error::WouldBlock));
+        assert_eq!(::nb::block!(cbuf.nb_get()), Ok(1));
+    }
+
+    #[test]
+    fn byte_dyn() {
+        use prelude::*;
+        use ring::CBuf;
+
+        fn pump(from: &mut dyn ByteSource, to: &mut [&mut dyn ByteSink]) {
+            let mut chunk = [0u8; 2];
+            for sink in to.iter_mut() {
+                let n = from.get_bytes(&mut chunk);
+                assert_eq!(sink.put_bytes(&chunk[..n]), n);
+            }
+        }
+
+        let buf = &mut [0u8; 8];
+        let mut src = CBuf::new(buf);
+        assert_eq!(src.put_bytes(&[1, 2, 3, 4, 5]), 5);
+
+        let (buf_a, buf_b) = (&mut [0u8; 2], &mut [0u8; 4]);
+        let (mut a, mut b) = (CBuf::new(buf_a), CBuf::new(buf_b));
+        pump(&mut src, &mut [&mut a, &mut b]);
+        assert_eq!(a.put_bytes(&[9]), 0);
+        assert!(a.get_iter().eq(1..3));
+        assert!(b.get_iter().eq(3..5));
+        assert!(src.get_iter().eq(5..6));
+    }
+}
@@ -0,0 +1,162 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer accessing its slots with volatile reads and writes
+
+use core::marker::PhantomData;
+
+use super::{cbuf_next, CBUF_DATA_BIT};
+use ring::CBufControl;
+use traits::{RingConsumer, RingProducer};
+
+/// Circular Buffer over memory shared with a coprocessor or peripheral
+///
+/// Every slot access goes through `read_volatile`/`write_volatile`, so the
+/// compiler never elides, merges or reorders them relative to other
+/// volatile accesses. Indexes are ordinary memory: only the slots are
+/// shared.
+pub struct VolatileCBuf<'a, T: 'a> {
+    ptr: *mut T,
+    len: usize,
+    ctrl: CBufControl<T>,
+    phantom: PhantomData<&'a mut [T]>,
+}
+
+impl<'a, T: Copy> VolatileCBuf<'a, T> {
+    /// Create new VolatileCBuf
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T]) -> VolatileCBuf<'a, T> {
+        unsafe { Self::from_raw_parts(buf.as_mut_ptr(), buf.len()) }
+    }
+
+    /// Create new VolatileCBuf over `len` slots at `ptr`
+    ///
+    /// panics if ptr is null or misaligned, if len == 0, or if len doesn't
+    /// fit below the index tag bit
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must be non-null and valid for volatile reads and writes of
+    /// `len` properly aligned `T`s for `'a`, eg. a fixed shared memory
+    /// region. Nothing may create references into that region meanwhile.
+    pub unsafe fn from_raw_parts(ptr: *mut T, len: usize) -> VolatileCBuf<'a, T> {
+        if ptr.is_null() || !ptr.is_aligned() {
+            panic!("bad ptr")
+        }
+        if len == 0 {
+            panic!("len==0")
+        }
+        if len >= CBUF_DATA_BIT {
+            panic!("len too big")
+        }
+
+        VolatileCBuf {
+            ptr,
+            len,
+            ctrl: CBufControl::new(),
+            phantom: PhantomData,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.ctrl.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.ctrl.is_empty()
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        if self.is_empty() {
+            return None;
+        }
+        let tail = self.ctrl.tail_index();
+        let val = unsafe { self.ptr.add(tail & !CBUF_DATA_BIT).read_volatile() };
+        unsafe { self.ctrl.set_tail_index(cbuf_next(tail, self.len)) };
+        Some(val)
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        if self.is_full() {
+            return;
+        }
+        let head = self.ctrl.head_index();
+        unsafe {
+            self.ptr.add(head & !CBUF_DATA_BIT).write_volatile(val);
+            self.ctrl.set_head_index(cbuf_next(head, self.len));
+        }
+    }
+}
+
+impl<'a, T: Copy> RingProducer<T> for VolatileCBuf<'a, T> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        VolatileCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        VolatileCBuf::put(self, val)
+    }
+}
+
+impl<'a, T: Copy> RingConsumer<T> for VolatileCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        VolatileCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        VolatileCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volatile() {
+        let buf = &mut [0u32; 2];
+        let mut cbuf = VolatileCBuf::new(buf);
+
+        assert!(cbuf.is_empty());
+        cbuf.put(1);
+        cbuf.put(2);
+        cbuf.put(3); // will have no effect
+        assert!(cbuf.is_full());
+        assert_eq!(cbuf.get(), Some(1));
+        cbuf.put(4);
+        assert_eq!(cbuf.get(), Some(2));
+        assert_eq!(cbuf.get(), Some(4));
+        assert_eq!(cbuf.get(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bad ptr")]
+    fn null_ptr() {
+        unsafe { VolatileCBuf::<u32>::from_raw_parts(core::ptr::null_mut(), 2) };
+    }
+}
@@ -0,0 +1,74 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Waker storage for async buffer operations
+
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::Waker;
+
+const WAKER_WAITING: usize = 0;
+const WAKER_REGISTERING: usize = 1;
+const WAKER_WAKING: usize = 2;
+
+/// Waker slot shared between the waiting task and the waking side
+///
+/// Registration and wake-up can race; whichever side loses the race on
+/// `state` makes sure the wake-up is not lost.
+pub struct AtomicWaker {
+    state: AtomicUsize,
+    waker: UnsafeCell<Option<Waker>>,
+}
+
+impl AtomicWaker {
+    pub fn new() -> AtomicWaker {
+        AtomicWaker {
+            state: AtomicUsize::new(WAKER_WAITING),
+            waker: UnsafeCell::new(None),
+        }
+    }
+
+    pub fn register(&self, waker: &Waker) {
+        match self.state
+            .compare_exchange(WAKER_WAITING,
+                              WAKER_REGISTERING,
+                              Ordering::Acquire,
+                              Ordering::Acquire)
+            .unwrap_or_else(|x| x) {
+            WAKER_WAITING => unsafe {
+                let slot = &mut *self.waker.get();
+                match *slot {
+                    Some(ref old) if old.will_wake(waker) => {}
+                    _ => *slot = Some(waker.clone()),
+                }
+                if self.state
+                    .compare_exchange(WAKER_REGISTERING,
+                                      WAKER_WAITING,
+                                      Ordering::AcqRel,
+                                      Ordering::Acquire)
+                    .is_err() {
+                    // `wake` came in the meantime and left it to us
+                    let waker = slot.take();
+                    self.state.swap(WAKER_WAITING, Ordering::AcqRel);
+                    if let Some(waker) = waker {
+                        waker.wake();
+                    }
+                }
+            },
+            WAKER_WAKING => waker.wake_by_ref(),
+            _ => {}
+        }
+    }
+
+    pub fn wake(&self) {
+        if self.state.fetch_or(WAKER_WAKING, Ordering::AcqRel) == WAKER_WAITING {
+            let waker = unsafe { (*self.waker.get()).take() };
+            self.state.fetch_and(!WAKER_WAKING, Ordering::Release);
+            if let Some(waker) = waker {
+                waker.wake();
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer notifying when occupancy crosses watermarks
+
+use ring::CBuf;
+use traits::{RingConsumer, RingProducer};
+
+/// Watermark crossed, passed to the `WatermarkCBuf` callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Watermark {
+    /// Occupancy rose to the high watermark
+    High,
+    /// Occupancy fell back to the low watermark
+    Low,
+}
+
+/// Circular Buffer calling back when occupancy crosses watermarks
+///
+/// `High` is reported once occupancy reaches `high` elements, `Low` once
+/// it falls back to `low` or below; in between nothing is reported, so
+/// the callback doesn't flap. Every `put` and `get` checks the
+/// occupancy, so eg. a UART driver can deassert RTS from the callback
+/// without polling the buffer:
+///
+/// ```
+/// use cbuf::{Watermark, WatermarkCBuf};
+///
+/// let mut rts = true;
+/// let buf = &mut [0u8; 8];
+/// let mut rx = WatermarkCBuf::new(buf, 2, 6, |mark| rts = mark == Watermark::Low);
+/// rx.put_slice(&[0; 6]);
+/// rx.pop_into(&mut [0; 4]);
+/// drop(rx);
+/// assert!(rts);
+/// ```
+pub struct WatermarkCBuf<'a, T: 'a, F: FnMut(Watermark)> {
+    inner: CBuf<'a, T>,
+    low: usize,
+    high: usize,
+    above: bool,
+    on_cross: F,
+}
+
+impl<'a, T: Clone, F: FnMut(Watermark)> WatermarkCBuf<'a, T, F> {
+    /// Create new WatermarkCBuf calling `on_cross` at `low` and `high`
+    /// elements
+    ///
+    /// panics if buf.len() == 0, low >= high or high > buf.len()
+    pub fn new(buf: &'a mut [T], low: usize, high: usize, on_cross: F) -> WatermarkCBuf<'a, T, F> {
+        WatermarkCBuf::from_cbuf(CBuf::new(buf), low, high, on_cross)
+    }
+
+    /// Wrap `inner`, eg. one with an `OverflowPolicy`, calling `on_cross`
+    /// at `low` and `high` elements
+    ///
+    /// panics if low >= high or high > inner.len()
+    pub fn from_cbuf(inner: CBuf<'a, T>, low: usize, high: usize, on_cross: F) -> WatermarkCBuf<'a, T, F> {
+        if low >= high || high > inner.len() {
+            panic!("bad watermarks")
+        }
+
+        WatermarkCBuf {
+            above: inner.count() >= high,
+            inner,
+            low,
+            high,
+            on_cross,
+        }
+    }
+
+    /// get the buffer length
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Number of elements in the buffer
+    #[inline]
+    pub fn count(&self) -> usize {
+        self.inner.count()
+    }
+
+    /// Is buffer full?
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Was `High` reported last, ie. is occupancy above the low watermark
+    /// after reaching the high one?
+    #[inline]
+    pub fn is_high(&self) -> bool {
+        self.above
+    }
+
+    /// Peek next element without removing it
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn peek(&mut self) -> Option<&T> {
+        self.inner.peek()
+    }
+
+    /// Remove one element
+    ///
+    /// Returns `None` if buffer is empty.
+    #[inline]
+    pub fn get(&mut self) -> Option<T> {
+        let val = self.inner.get();
+        self.check();
+        val
+    }
+
+    /// Add element the buffer
+    ///
+    /// Ignores the write if buffer is full.
+    #[inline]
+    pub fn put(&mut self, val: T) {
+        self.inner.put(val);
+        self.check();
+    }
+
+    /// See `CBuf::put_slice`
+    pub fn put_slice(&mut self, src: &[T]) -> usize {
+        let n = self.inner.put_slice(src);
+        self.check();
+        n
+    }
+
+    /// See `CBuf::pop_into`
+    pub fn pop_into(&mut self, dst: &mut [T]) -> usize {
+        let n = self.inner.pop_into(dst);
+        self.check();
+        n
+    }
+
+    /// Report a crossing, if occupancy just made one
+    #[inline]
+    fn check(&mut self) {
+        let count = self.inner.count();
+        if !self.above && count >= self.high {
+            self.above = true;
+            (self.on_cross)(Watermark::High);
+        } else if self.above && count <= self.low {
+            self.above = false;
+            (self.on_cross)(Watermark::Low);
+        }
+    }
+}
+
+impl<'a, T: Clone, F: FnMut(Watermark)> RingProducer<T> for WatermarkCBuf<'a, T, F> {
+    #[inline]
+    fn is_full(&self) -> bool {
+        WatermarkCBuf::is_full(self)
+    }
+
+    #[inline]
+    fn put(&mut self, val: T) {
+        WatermarkCBuf::put(self, val)
+    }
+}
+
+impl<'a, T: Clone, F: FnMut(Watermark)> RingConsumer<T> for WatermarkCBuf<'a, T, F> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        WatermarkCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        WatermarkCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[test]
+    fn watermark() {
+        let mut events = Vec::new();
+        {
+            let buf = &mut [0u8; 4];
+            let mut cbuf = WatermarkCBuf::new(buf, 1, 3, |mark| events.push(mark));
+
+            cbuf.put(1);
+            cbuf.put(2);
+            assert!(!cbuf.is_high());
+            cbuf.put(3);
+            assert!(cbuf.is_high());
+            cbuf.put(4);
+            cbuf.get();
+            cbuf.get(); // 2 left: between the watermarks
+            assert!(cbuf.is_high());
+            cbuf.put(5);
+            assert_eq!(cbuf.pop_into(&mut [0; 3]), 3);
+            assert!(!cbuf.is_high());
+            cbuf.get();
+            assert!(cbuf.is_empty());
+        }
+        assert_eq!(events, [Watermark::High, Watermark::Low]);
+    }
+}
@@ -0,0 +1,136 @@
+// Modified 2016 Garrett Berg <vitiral@gmail.com>
+// Copyright 2015 Dawid Ciężarkiewicz
+// See LICENSE-MPL
+//
+
+//! Circular Buffer bounding total weight of its elements
+
+use ring::CBuf;
+use traits::RingConsumer;
+
+/// Cost of an element counted against a `WeightedCBuf` budget
+///
+/// Typically the number of bytes an element owns or points to.
+pub trait Weight {
+    /// Weight of this element
+    fn weight(&self) -> usize;
+}
+
+impl<T> Weight for &[T] {
+    #[inline]
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+impl Weight for &str {
+    #[inline]
+    fn weight(&self) -> usize {
+        self.len()
+    }
+}
+
+/// Circular Buffer rejecting elements once a weight budget is used up
+///
+/// Bounds memory held by variable-size elements, not just their count.
+pub struct WeightedCBuf<'a, T: 'a> {
+    inner: CBuf<'a, T>,
+    weight: usize,
+    budget: usize,
+}
+
+impl<'a, T: Clone + Weight> WeightedCBuf<'a, T> {
+    /// Create new WeightedCBuf holding at most `budget` total weight
+    ///
+    /// panics if buf.len() == 0
+    pub fn new(buf: &'a mut [T], budget: usize) -> WeightedCBuf<'a, T> {
+        WeightedCBuf {
+            inner: CBuf::new(buf),
+            weight: 0,
+            budget,
+        }
+    }
+
+    /// Total weight of elements in the buffer
+    #[inline]
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    /// Weight budget given to `new`
+    #[inline]
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Is buffer full? (by count, regardless of weight)
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.inner.is_full()
+    }
+
+    /// Is buffer empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Add element the buffer
+    ///
+    /// Gives `val` back if buffer is full or its weight would exceed the
+    /// budget.
+    pub fn put(&mut self, val: T) -> Result<(), T> {
+        let weight = val.weight();
+        if self.is_full() || weight > self.budget - self.weight {
+            return Err(val);
+        }
+        self.weight += weight;
+        self.inner.put(val);
+        Ok(())
+    }
+
+    /// Remove one element from the buffer
+    ///
+    /// Returns `None` if buffer is empty.
+    pub fn get(&mut self) -> Option<T> {
+        let val = self.inner.get()?;
+        self.weight -= val.weight();
+        Some(val)
+    }
+}
+
+impl<'a, T: Clone + Weight> RingConsumer<T> for WeightedCBuf<'a, T> {
+    #[inline]
+    fn is_empty(&self) -> bool {
+        WeightedCBuf::is_empty(self)
+    }
+
+    #[inline]
+    fn get(&mut self) -> Option<T> {
+        WeightedCBuf::get(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weighted() {
+        let buf: &mut [&str] = &mut [""; 4];
+        let mut cbuf = WeightedCBuf::new(buf, 10);
+
+        assert_eq!(cbuf.put("hello"), Ok(()));
+        assert_eq!(cbuf.put("world!"), Err("world!"));
+        assert_eq!(cbuf.put("abc"), Ok(()));
+        assert_eq!(cbuf.weight(), 8);
+        assert_eq!(cbuf.put(""), Ok(()));
+        assert_eq!(cbuf.put("x"), Ok(()));
+        assert_eq!(cbuf.put(""), Err(""));
+
+        assert_eq!(cbuf.get(), Some("hello"));
+        assert_eq!(cbuf.weight(), 4);
+        assert_eq!(cbuf.put("world!"), Ok(()));
+        assert_eq!(cbuf.weight(), cbuf.budget());
+    }
+}